@@ -0,0 +1,156 @@
+//! Absences declared by the students with `/absence`, each missed colle needs a rattrapage listed
+//! by `/rattrapages` until the student books one with `/rattrapage reserver`.
+use crate::{
+    class::Class, colle::ColleStringFormat, error::WattouError, guild_data::SavedData,
+    index::IndexedColle,
+};
+use color_eyre::Result;
+use serde::{Deserialize, Serialize};
+use serenity::all::{Mention, UserId};
+use time::OffsetDateTime;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Absence {
+    pub class: String,
+    pub group_id: String,
+    /// [IndexedColle::key] of the missed colle
+    pub colle_key: String,
+    /// e.g. : Maths 1: Lundi 13 Jan 17h-18h avec Dupont en 207
+    pub colle: String,
+    pub prof: String,
+    pub user_id: UserId,
+    pub reason: String,
+    /// Unix timestamp of the declaration
+    pub declared_at: i64,
+    pub needs_rattrapage: bool,
+    /// Id of the [crate::rattrapage::RattrapageSlot] booked to make up the colle
+    #[serde(default)]
+    pub rattrapage: Option<u32>,
+}
+
+impl Absence {
+    pub fn new(class: &Class, indexed: &IndexedColle, user_id: UserId, reason: String) -> Self {
+        Self {
+            class: class.name.to_string(),
+            group_id: indexed.group_id.to_string(),
+            colle_key: indexed.key(),
+            colle: indexed.colle.format(ColleStringFormat::Explicit, vec![]),
+            prof: indexed.colle.prof.name().to_string(),
+            user_id,
+            reason,
+            declared_at: OffsetDateTime::now_utc().unix_timestamp(),
+            needs_rattrapage: true,
+            rattrapage: None,
+        }
+    }
+
+    /// Subject letter of the missed colle
+    pub fn subject(&self) -> Option<char> {
+        self.colle_key.rsplit('-').nth(1)?.chars().next()
+    }
+
+    pub fn is_pending(&self) -> bool {
+        self.needs_rattrapage && self.rattrapage.is_none()
+    }
+
+    /// Message sent to the colleur and the delegates, `student` being the name of the student,
+    /// cut to the 2000 characters of a Discord message
+    pub fn notification(&self, student: &str) -> String {
+        let notification = format!(
+            "📅 {} ({}, groupe {}) sera absent à la colle {}\nRaison : {}",
+            student,
+            Mention::from(self.user_id),
+            self.group_id,
+            self.colle,
+            self.reason
+        );
+        notification.chars().take(2000).collect()
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Absences {
+    absences: Vec<Absence>,
+}
+
+impl SavedData for Absences {
+    const FILE_NAME: &'static str = "absences.json";
+
+    fn ser(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+
+    fn de(value: &str) -> Result<Self> {
+        Ok(serde_json::from_str(value)?)
+    }
+}
+
+impl Absences {
+    /// Records the absence, fails if the student already declared one for this colle
+    pub fn add(&mut self, absence: Absence) -> Result<()> {
+        if self.absences.iter().any(|other| {
+            other.user_id == absence.user_id
+                && other.class == absence.class
+                && other.colle_key == absence.colle_key
+        }) {
+            Err(WattouError::AlreadyAbsent(absence.colle.clone()))?
+        }
+        self.absences.push(absence);
+        Ok(())
+    }
+
+    /// Absences of the class whose colle has not been made up yet, oldest colle first
+    pub fn needing_rattrapage(&self, class: &Class) -> Vec<&Absence> {
+        let mut absences = self
+            .absences
+            .iter()
+            .filter(|absence| absence.is_pending() && absence.class == *class.name)
+            .collect::<Vec<_>>();
+        absences.sort_by_key(|absence| {
+            absence
+                .colle_key
+                .rsplit('-')
+                .next()
+                .and_then(|start| start.parse::<i64>().ok())
+        });
+        absences
+    }
+
+    /// Absences of the user in the class still waiting for a rattrapage
+    pub fn pending_for(&self, class: &Class, user_id: UserId) -> Vec<&Absence> {
+        self.needing_rattrapage(class)
+            .into_iter()
+            .filter(|absence| absence.user_id == user_id)
+            .collect()
+    }
+
+    /// Links the oldest pending absence of the user in that subject to the rattrapage, returns
+    /// whether there was one
+    pub fn assign(&mut self, class: &Class, user_id: UserId, subject: char, slot_id: u32) -> bool {
+        let Some(key) = self
+            .pending_for(class, user_id)
+            .into_iter()
+            .find(|absence| absence.subject() == Some(subject))
+            .map(|absence| absence.colle_key.clone())
+        else {
+            return false;
+        };
+        self.absences
+            .iter_mut()
+            .filter(|absence| absence.user_id == user_id && absence.colle_key == key)
+            .for_each(|absence| absence.rattrapage = Some(slot_id));
+        true
+    }
+
+    /// The absences of the user, or of everyone when [None], made up by the rattrapage need
+    /// one again
+    pub fn unassign(&mut self, slot_id: u32, user_id: Option<UserId>) {
+        self.absences
+            .iter_mut()
+            .filter(|absence| {
+                absence.rattrapage == Some(slot_id)
+                    && user_id.is_none_or(|id| absence.user_id == id)
+            })
+            .for_each(|absence| absence.rattrapage = None);
+    }
+}
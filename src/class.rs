@@ -0,0 +1,605 @@
+use crate::{
+    colle::{Colle, ColleData, ColleStringFormat},
+    error::WattouError,
+    ghost::Ghost,
+    group::{Group, GroupId},
+    index::{ColleIndex, ColleQuery, IndexedColle},
+    prof::Prof,
+    utils::{Jour, easy_comp_string, now_in_timezone},
+    weeks::{DATE_FORMAT, Weeks, strip_comment},
+};
+use color_eyre::{Result, eyre::eyre};
+use serenity::all::GuildId;
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use time::{Date, Duration};
+use tracing::{debug, info, warn};
+
+/// One colloscope of a guild, e.g. MPSI, with its own files in a sub folder of the guild
+#[derive(Debug)]
+pub struct Class {
+    pub guild_id: GuildId,
+    pub name: Arc<str>,
+    /// Sorted by start, never empty
+    pub periods: Vec<Period>,
+}
+
+/// Part of the year with its own colloscope files, e.g. a semester
+#[derive(Debug)]
+pub struct Period {
+    pub name: Arc<str>,
+    /// First day of the period, [None] for a period that has always been valid
+    pub start: Option<Date>,
+    /// Last day of the period, [None] for a period that stays valid
+    pub end: Option<Date>,
+    pub groups: Vec<Group>,
+    pub ghosts: Vec<Ghost>,
+    /// Ids of the `ghosts` file that are not groups of the period, ignored
+    pub unknown_ghosts: Vec<String>,
+    pub index: ColleIndex,
+    pub weeks: Weeks,
+    /// Where the files of the period are read from
+    pub folder: PathBuf,
+}
+
+impl Class {
+    pub const FILE_NAME_GHOSTS_GROUPS: &'static str = "ghosts";
+    pub const FILE_NAME_COLLE_LIST: &'static str = "colles";
+    pub const FILE_NAME_WEEKS_INFO: &'static str = "weeks";
+    pub const FILE_NAME_COLLOSCOPE: &'static str = "colloscope";
+    /// Validity range of a period folder: its first day, then optionally its last day
+    pub const FILE_NAME_PERIOD: &'static str = "periode";
+
+    /// Cell of the colloscope of a group without colles during the weeks of the column
+    pub const EMPTY_CELL: &'static str = "-";
+
+    /// Name of the class of a guild whose files are directly in its folder
+    pub const DEFAULT_NAME: &'static str = "classe";
+    /// Name of the period of a class whose files are directly in its folder
+    pub const DEFAULT_PERIOD_NAME: &'static str = "année";
+
+    /// Parses the files of `folder`, the profs are shared by every class of the guild
+    pub fn new(
+        guild_id: GuildId,
+        name: Arc<str>,
+        folder: PathBuf,
+        profs: &mut HashMap<Arc<str>, Arc<Prof>>,
+    ) -> Result<Self> {
+        debug!(class = %name, "parsing class data");
+
+        let mut periods = if Self::is_period_folder(&folder) {
+            vec![(Arc::from(Self::DEFAULT_PERIOD_NAME), None, None, folder)]
+        } else {
+            let periods = Self::period_folders(&folder)?;
+            if periods.is_empty() {
+                Err(WattouError::NoPeriod(folder.display().to_string()))?
+            }
+            periods
+        };
+        periods.sort_by_key(|(_, start, _, _)| *start);
+
+        // A period without a last day ends the day before the next one starts
+        let next_starts = periods
+            .iter()
+            .skip(1)
+            .map(|(_, start, _, _)| *start)
+            .chain([None])
+            .collect::<Vec<_>>();
+        let periods = periods
+            .into_iter()
+            .zip(next_starts)
+            .map(|((period, start, end, folder), next_start)| {
+                let end = end.or(next_start.and_then(|next| next.previous_day()));
+                Period::new(guild_id, &name, period, start, end, folder, profs)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        info!(
+            class = %name,
+            periods = periods.len(),
+            colles = periods.iter().map(|period| period.index.len()).sum::<usize>(),
+            "parsed class data"
+        );
+
+        Ok(Self {
+            guild_id,
+            name,
+            periods,
+        })
+    }
+
+    /// Whether `folder` holds the files of a class, directly or in period sub folders
+    pub fn is_class_folder(folder: &Path) -> bool {
+        Self::is_period_folder(folder)
+            || fs::read_dir(folder).is_ok_and(|entries| {
+                entries
+                    .flatten()
+                    .any(|entry| Self::is_period_sub_folder(&entry.path()))
+            })
+    }
+
+    fn is_period_folder(folder: &Path) -> bool {
+        folder.join(Self::FILE_NAME_COLLOSCOPE).is_file()
+    }
+
+    /// A period folder with its validity range, in the folder of a class
+    fn is_period_sub_folder(folder: &Path) -> bool {
+        folder.join(Self::FILE_NAME_PERIOD).is_file() && Self::is_period_folder(folder)
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn period_folders(
+        folder: &Path,
+    ) -> Result<Vec<(Arc<str>, Option<Date>, Option<Date>, PathBuf)>> {
+        let mut periods = Vec::new();
+        for entry in fs::read_dir(folder)? {
+            let path = entry?.path();
+            let period_file = path.join(Self::FILE_NAME_PERIOD);
+            if let Some(name) = path.file_name().and_then(|name| name.to_str())
+                && Self::is_period_sub_folder(&path)
+            {
+                let content = fs::read_to_string(&period_file)?;
+                let mut dates = content
+                    .split_whitespace()
+                    .map(|date| Date::parse(date, DATE_FORMAT));
+                let start = dates
+                    .next()
+                    .ok_or_else(|| eyre!("{} vide", period_file.display()))??;
+                let end = dates.next().transpose()?;
+                periods.push((Arc::from(name), Some(start), end, path));
+            }
+        }
+        Ok(periods)
+    }
+
+    /// Period of the given day, or the next one to start, or the last one
+    pub fn period_at(&self, date: Date) -> &Period {
+        self.periods
+            .iter()
+            .find(|period| period.contains(date))
+            .or_else(|| {
+                self.periods
+                    .iter()
+                    .find(|period| period.start.is_some_and(|start| start > date))
+            })
+            .or(self.periods.last())
+            .expect("Class::new refuses a class without periods")
+    }
+
+    /// Period used for the board and the list of groups, changes at midnight of the first day of
+    /// the next period
+    pub fn active_period(&self) -> &Period {
+        self.period_at(now_in_timezone().date())
+    }
+
+    /// Colles of every period matching the query, sorted by start
+    pub fn query(&self, query: &ColleQuery) -> Vec<&IndexedColle> {
+        let mut colles = self
+            .periods
+            .iter()
+            .filter(|period| period.overlaps(query))
+            .flat_map(|period| period.query(query))
+            .collect::<Vec<_>>();
+        colles.sort_by(|a, b| a.colle.cmp(&b.colle).then(a.group_id.cmp(&b.group_id)));
+        colles.truncate(query.limit.unwrap_or(usize::MAX));
+        colles
+    }
+
+    /// The group with exactly this id, in the active period or in another period when it only
+    /// exists there, for the ids saved by the bot
+    pub fn group(&self, group_id: &str) -> Option<&Group> {
+        std::iter::once(self.active_period())
+            .chain(self.periods.iter())
+            .find_map(|period| period.group(group_id))
+    }
+
+    /// Declared id of a group saved by its position in the active period before groups could
+    /// declare one
+    pub fn legacy_group_id(&self, group_id: &str) -> Option<&GroupId> {
+        if self.group(group_id).is_some() {
+            return None;
+        }
+        self.active_period().legacy_group_id(group_id)
+    }
+
+    /// The group typed by a user: its id or its name, in the active period or in another period
+    /// when it only exists there, or its position in the active period
+    pub fn get_group(&self, group_id: &str) -> Result<&Group> {
+        let periods = || std::iter::once(self.active_period()).chain(self.periods.iter());
+        let group_cmp = easy_comp_string(group_id);
+        periods()
+            .find_map(|period| period.groups.iter().find(|g| &*g.id == group_id))
+            .or_else(|| {
+                periods().find_map(|period| {
+                    period.groups.iter().find(|g| {
+                        easy_comp_string(&g.id) == group_cmp
+                            || g.name
+                                .as_deref()
+                                .is_some_and(|name| easy_comp_string(name) == group_cmp)
+                    })
+                })
+            })
+            .or_else(|| {
+                let position = group_id.parse::<usize>().ok()?.checked_sub(1)?;
+                self.active_period().groups.get(position)
+            })
+            .ok_or_else(|| {
+                WattouError::NoGroupInClass(
+                    self.guild_id,
+                    self.name.to_string(),
+                    group_id.to_string(),
+                )
+                .into()
+            })
+    }
+
+    /// Ghost covering the colle in the period of the colle
+    pub fn ghost_of(&self, indexed: &IndexedColle) -> Option<&Ghost> {
+        self.period_at(indexed.colle.start.date()).ghost_of(indexed)
+    }
+
+    /// Ghost of the group today, if the group has no students anymore
+    pub fn current_ghost(&self, group_id: &str) -> Option<&Ghost> {
+        let today = now_in_timezone().date();
+        self.active_period()
+            .ghosts
+            .iter()
+            .find(|ghost| &*ghost.group_id == group_id && ghost.covers(today))
+    }
+
+    /// Upcoming colles of ghost groups that students can claim
+    pub fn open_places(&self) -> Vec<&IndexedColle> {
+        self.query(&ColleQuery::upcoming().with_ghosts())
+            .into_iter()
+            .filter(|indexed| {
+                self.ghost_of(indexed)
+                    .is_some_and(|ghost| ghost.open_places)
+            })
+            .collect()
+    }
+
+    /// Groups of every period, the active period first, without duplicates
+    pub fn all_groups(&self) -> Vec<&Group> {
+        let mut groups: Vec<&Group> = Vec::new();
+        for period in std::iter::once(self.active_period()).chain(self.periods.iter()) {
+            for group in period.groups.iter() {
+                if !groups.iter().any(|g| g.id == group.id) {
+                    groups.push(group);
+                }
+            }
+        }
+        groups
+    }
+
+    /// Calendar with the colles of the group in every period, and `extra` colles such as the
+    /// rattrapages of a student
+    pub fn ics_calendar(&self, group_id: &str, extra: Vec<Colle>) -> Result<String> {
+        let group = self.get_group(group_id)?;
+        let mut colles = self
+            .query(&ColleQuery::new().group(&group.id))
+            .into_iter()
+            .map(|indexed| indexed.colle.clone())
+            .chain(extra)
+            .collect::<Vec<_>>();
+        colles.sort();
+        Group {
+            colles,
+            ..group.clone()
+        }
+        .ics_calendar()
+    }
+
+    /// e.g. : "1–15", or "G1, G2, G4" when the ids are not consecutive numbers
+    pub fn groups_summary(&self) -> String {
+        let groups = &self.active_period().groups;
+        let numbers = groups
+            .iter()
+            .map(|g| g.id.parse::<usize>().ok())
+            .collect::<Option<Vec<_>>>()
+            .unwrap_or_default();
+        match (numbers.first(), numbers.last()) {
+            (Some(first), Some(last)) if numbers.len() > 2 && last - first + 1 == numbers.len() => {
+                format!("{first}–{last}")
+            }
+            _ => groups
+                .iter()
+                .map(|g| g.id.as_ref())
+                .collect::<Vec<_>>()
+                .join(", "),
+        }
+    }
+
+    /// Upcoming colles of every group of the active period, as shown on the board, ghost groups
+    /// are left out unless `show_ghosts`
+    pub fn board_msg(&self, colles_per_group: usize, show_ghosts: bool) -> String {
+        self.active_period()
+            .groups
+            .iter()
+            .filter_map(|group| {
+                let ghost = self.current_ghost(&group.id);
+                if ghost.is_some() && !show_ghosts {
+                    return None;
+                }
+                Some(format!(
+                    "\nGroupe {}{} {}",
+                    group,
+                    match ghost {
+                        Some(ghost) if ghost.open_places => " (fantôme 👻, places libres)",
+                        Some(_) => " (fantôme 👻)",
+                        None => "",
+                    },
+                    self.query(&ColleQuery {
+                        ghosts: ghost.is_some(),
+                        ..ColleQuery::upcoming()
+                            .group(&group.id)
+                            .limit(colles_per_group)
+                    })
+                    .iter()
+                    .map(|indexed| format!(
+                        "\n- {}",
+                        indexed.colle.format(ColleStringFormat::Implicit, vec![])
+                    ))
+                    .collect::<String>()
+                ))
+            })
+            .collect()
+    }
+}
+
+impl Period {
+    fn new(
+        guild_id: GuildId,
+        class: &Arc<str>,
+        name: Arc<str>,
+        start: Option<Date>,
+        end: Option<Date>,
+        folder: PathBuf,
+        profs: &mut HashMap<Arc<str>, Arc<Prof>>,
+    ) -> Result<Self> {
+        let files = ClassFiles {
+            folder: folder.clone(),
+        };
+
+        let weeks = files.read_weeks_data()?;
+        let mut groups = files.read_groups_data(guild_id, class, &weeks, profs)?;
+        // The files may list weeks of the next period, these belong to the files of that period
+        for group in groups.iter_mut() {
+            group.colles.retain(|colle| {
+                let date = colle.start.date();
+                start.is_none_or(|start| date >= start) && end.is_none_or(|end| date <= end)
+            });
+        }
+        let (ghosts, unknown_ghosts) = files.read_ghost_groups(&groups)?;
+        if !unknown_ghosts.is_empty() {
+            warn!(class = %class, period = %name, groups = ?unknown_ghosts, "unknown ghost groups ignored");
+        }
+        let index = ColleIndex::new(&groups);
+
+        debug!(class = %class, period = %name, colles = index.len(), "parsed period data");
+
+        Ok(Self {
+            name,
+            start,
+            end,
+            groups,
+            ghosts,
+            unknown_ghosts,
+            index,
+            weeks,
+            folder,
+        })
+    }
+
+    pub fn group(&self, group_id: &str) -> Option<&Group> {
+        self.groups.iter().find(|group| &*group.id == group_id)
+    }
+
+    /// Declared id of a group saved by its position before groups could declare one, [None]
+    /// when `group_id` is already an id or is not a position
+    pub fn legacy_group_id(&self, group_id: &str) -> Option<&GroupId> {
+        if self.group(group_id).is_some() {
+            return None;
+        }
+        let position = group_id.parse::<usize>().ok()?.checked_sub(1)?;
+        self.groups.get(position).map(|group| &group.id)
+    }
+
+    /// Colles of the index matching the query, without those of ghost groups unless the query
+    /// asks for them
+    fn query(&self, query: &ColleQuery) -> Vec<&IndexedColle> {
+        if query.ghosts || self.ghosts.is_empty() {
+            return self.index.query(query);
+        }
+        self.index
+            .query(&ColleQuery {
+                limit: None,
+                ..query.clone()
+            })
+            .into_iter()
+            .filter(|indexed| self.ghost_of(indexed).is_none())
+            .take(query.limit.unwrap_or(usize::MAX))
+            .collect()
+    }
+
+    pub fn ghost_of(&self, indexed: &IndexedColle) -> Option<&Ghost> {
+        self.ghosts.iter().find(|ghost| {
+            ghost.group_id == indexed.group_id && ghost.covers(indexed.colle.start.date())
+        })
+    }
+
+    pub fn contains(&self, date: Date) -> bool {
+        self.start.is_none_or(|start| date >= start) && self.end.is_none_or(|end| date <= end)
+    }
+
+    /// Whether colles of the period may match the time range of the query
+    fn overlaps(&self, query: &ColleQuery) -> bool {
+        query
+            .from
+            .is_none_or(|from| self.end.is_none_or(|end| from.date() <= end))
+            && query
+                .to
+                .is_none_or(|to| self.start.is_none_or(|start| to.date() >= start))
+    }
+}
+
+/// Id, optional name and colles of a group line of the colloscope
+type ColloscopeLine = (GroupId, Option<String>, Vec<Colle>);
+
+/// Files of a class, see the `FILE_NAME_*` constants of [Class]
+struct ClassFiles {
+    folder: PathBuf,
+}
+
+impl ClassFiles {
+    fn read_text(&self, file: &str) -> Result<String> {
+        Ok(fs::read_to_string(self.folder.join(file))?)
+    }
+
+    fn read_groups_data(
+        &self,
+        guild_id: GuildId,
+        class: &Arc<str>,
+        weeks: &Weeks,
+        profs: &mut HashMap<Arc<str>, Arc<Prof>>,
+    ) -> Result<Vec<Group>> {
+        let colloscope = self.parse_colloscope(weeks, profs)?;
+        let groups = colloscope
+            .into_iter()
+            .map(|(id, name, colles)| Group {
+                guild_id,
+                class: class.clone(),
+                id,
+                name,
+                colles,
+            })
+            .collect();
+
+        Ok(groups)
+    }
+
+    fn read_ghost_groups(&self, groups: &[Group]) -> Result<(Vec<Ghost>, Vec<String>)> {
+        Ghost::parse_file(&self.read_text(Class::FILE_NAME_GHOSTS_GROUPS)?, groups)
+    }
+
+    fn parse_colloscope(
+        &self,
+        weeks: &Weeks,
+        profs: &mut HashMap<Arc<str>, Arc<Prof>>,
+    ) -> Result<Vec<ColloscopeLine>> {
+        let colloscope = self.read_text(Class::FILE_NAME_COLLOSCOPE)?;
+        let colle_list =
+            ColleData::parse_list(&self.read_text(Class::FILE_NAME_COLLE_LIST)?, profs)?;
+
+        let mut lines = colloscope
+            .lines()
+            .enumerate()
+            .filter_map(|(i, line)| Some((i + 1, strip_comment(line)?)));
+
+        let Some((header_line, header)) = lines.next() else {
+            return Ok(vec![]);
+        };
+        let week_numbers = weeks.parse_header(header_line, header)?;
+        let mondays = weeks.mondays();
+
+        let mut ids: Vec<GroupId> = Vec::new();
+        let groups = lines
+            .enumerate()
+            .map(|(position, (line_number, group_colles))| {
+                let error = |column: usize, reason: String| WattouError::InvalidColloscope {
+                    line: line_number,
+                    column: column + 1,
+                    reason,
+                };
+
+                // `[G7 Trinôme Rouge] M1 A2...`, the id being the position of the line if omitted
+                let (id, name, group_colles) = match group_colles
+                    .strip_prefix('[')
+                    .and_then(|rest| rest.split_once(']'))
+                {
+                    Some((declaration, group_colles)) => {
+                        let (id, name) = declaration
+                            .trim()
+                            .split_once(char::is_whitespace)
+                            .map_or((declaration.trim(), None), |(id, name)| {
+                                (id, Some(name.trim().to_string()))
+                            });
+                        (GroupId::from(id), name, group_colles)
+                    }
+                    None => (
+                        GroupId::from((position + 1).to_string()),
+                        None,
+                        group_colles,
+                    ),
+                };
+                if id.is_empty() || ids.contains(&id) {
+                    Err(error(
+                        0,
+                        format!("identifiant de groupe « {id} » vide ou déjà utilisé"),
+                    ))?
+                }
+                ids.push(id.clone());
+
+                let cells = group_colles.split_whitespace().count();
+                if cells < week_numbers.len() {
+                    Err(error(
+                        cells,
+                        format!(
+                            "{cells} colonne(s) au lieu des {} de l'en-tête, `{}` pour une semaine sans colle",
+                            week_numbers.len(),
+                            Class::EMPTY_CELL
+                        ),
+                    ))?
+                }
+
+                let mut colles = group_colles
+                    .split_whitespace()
+                    .enumerate()
+                    .map(|(i, colles)| {
+                        let weeks_n = week_numbers
+                            .get(i)
+                            .ok_or_else(|| error(i, "colonne absente de l'en-tête".to_string()))?;
+                        // A group without colles this week
+                        if colles == Class::EMPTY_CELL {
+                            return Ok(vec![]);
+                        }
+                        let data = colles
+                            .split("+")
+                            .map(|colle_id| {
+                                colle_list
+                                    .get(colle_id)
+                                    .ok_or_else(|| error(i, format!("colle {colle_id} inconnue")))
+                            })
+                            .collect::<Result<Vec<_>, _>>()?;
+
+                        Ok(weeks_n
+                            .iter()
+                            .flat_map(|week| data.iter().flat_map(|d| d.colles(*week, &mondays)))
+                            .collect::<Vec<Colle>>())
+                    })
+                    .collect::<Result<Vec<Vec<Colle>>, WattouError>>()?
+                    .into_iter()
+                    .flatten()
+                    .collect::<Vec<Colle>>();
+                colles.sort();
+                Ok((id, name, colles))
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(groups)
+    }
+
+    fn read_weeks_data(&self) -> Result<Weeks> {
+        Weeks::parse(&self.read_text(Class::FILE_NAME_WEEKS_INFO)?)
+    }
+}
+
+/// Day of the week `week`, the `weeks` file gives the monday of each week
+pub fn get_date(weeks: &[Date], week: usize, jour: Jour) -> Date {
+    weeks[week - 1]
+        .saturating_sub(Duration::days(1))
+        .next_occurrence(jour.inner())
+}
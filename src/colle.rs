@@ -1,17 +1,22 @@
-use crate::GLOBAL_DATA;
+use crate::class::get_date;
+use crate::config::config;
 use crate::error::{ColleParsingError, WattouError};
+use crate::guild_data::WeekId;
 use crate::prof::Prof;
 use crate::utils::{Jour, month_to_short_fr};
 use color_eyre::{Result, eyre};
 use ics::Event;
+use ics::parameters::TzIDParam;
 use ics::properties::{Categories, Description, DtEnd, DtStart, Organizer, Summary};
 use once_cell::sync::Lazy;
 use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
 use std::str::FromStr;
 use std::sync::Arc;
-use time::format_description::well_known::Iso8601;
+use time::format_description::BorrowedFormatItem;
 use time::macros::format_description;
-use time::{Date, OffsetDateTime};
+use time::{Date, OffsetDateTime, Time};
 use uuid::Uuid;
 
 /// e.g. : M4 (Maths n°4)
@@ -31,31 +36,196 @@ impl FromStr for ColleId {
 }
 
 impl ColleId {
+    /// Number of the colles of a subject that are rattrapages, outside of the colloscope
+    pub const RATTRAPAGE: u8 = 0;
+
+    pub fn subject_name(subject: char) -> Option<&'static str> {
+        match subject {
+            'M' => Some("Maths"),
+            'P' => Some("Physique"),
+            'A' => Some("Anglais"),
+            _ => None,
+        }
+    }
+
+    /// Subject letter from the letter itself or the name of the subject, e.g. `M` or `maths`
+    pub fn parse_subject(s: &str) -> Option<char> {
+        let s = s.trim();
+        let mut chars = s.chars();
+        match (chars.next(), chars.next()) {
+            (Some(subject), None) if subject.is_ascii_alphabetic() => {
+                Some(subject.to_ascii_uppercase())
+            }
+            _ => ['M', 'P', 'A'].into_iter().find(|subject| {
+                Self::subject_name(*subject).is_some_and(|name| name.eq_ignore_ascii_case(s))
+            }),
+        }
+    }
+
+    /// e.g. : Maths 4, or Rattrapage Maths
     pub fn explicit(&self) -> String {
-        let mut s = match &self.0 {
-            'M' => "Maths",
-            'P' => "Physique",
-            'A' => "Anglais",
-            _ => panic!(),
+        if self.1 == Self::RATTRAPAGE {
+            return format!(
+                "Rattrapage {}",
+                Self::subject_name(self.0)
+                    .map(str::to_string)
+                    .unwrap_or_else(|| self.0.to_string())
+            );
         }
-        .to_string();
+        let mut s = Self::subject_name(self.0)
+            .map(str::to_string)
+            .unwrap_or_else(|| self.0.to_string());
         s.push(' ');
         s += &self.1.to_string();
         s
     }
 }
 
-impl ToString for ColleId {
-    fn to_string(&self) -> String {
-        self.0.to_string() + &self.1.to_string()
+impl Display for ColleId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.0, self.1)
     }
 }
 
 /// Room number, e.g. : 207
 pub type RoomNumber = String;
-pub type ColleData = (ColleId, (u8, u8), Jour, RoomNumber, Arc<Prof>);
 
-const ICS_CATEGORY: Lazy<Categories> = Lazy::new(|| Categories::new("Colles"));
+/// Weeks of the colloscope a line of the colles file applies to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WeekSelector {
+    All,
+    Even,
+    Odd,
+    List(Vec<WeekId>),
+}
+
+impl WeekSelector {
+    pub fn matches(&self, week: WeekId) -> bool {
+        match self {
+            Self::All => true,
+            Self::Even => week.is_multiple_of(2),
+            Self::Odd => !week.is_multiple_of(2),
+            Self::List(weeks) => weeks.contains(&week),
+        }
+    }
+}
+
+impl FromStr for WeekSelector {
+    type Err = eyre::Report;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.trim() {
+            "paires" => Self::Even,
+            "impaires" => Self::Odd,
+            list => Self::List(
+                list.split(',')
+                    .map(|week| week.trim().parse())
+                    .collect::<Result<_, _>>()
+                    .map_err(|_| {
+                        WattouError::ColleParsingFailed(ColleParsingError::InvalidWeekSelector)
+                    })?,
+            ),
+        })
+    }
+}
+
+/// When and where a colle takes place on the weeks of its selector
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColleSlot {
+    pub weeks: WeekSelector,
+    pub horaire: (Time, Time),
+    pub jour: Jour,
+    pub room: RoomNumber,
+    pub prof: Arc<Prof>,
+}
+
+/// Room or colleur replacing the usual one on some weeks
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Substitution {
+    pub weeks: WeekSelector,
+    pub change: Change,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    Room(RoomNumber),
+    Prof(Arc<Prof>),
+}
+
+/// A line of the colles file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ColleDefinition {
+    Slot(ColleSlot),
+    Substitution(Substitution),
+}
+
+/// Every line of the colles file with the same id
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColleData {
+    pub id: ColleId,
+    pub slots: Vec<ColleSlot>,
+    pub substitutions: Vec<Substitution>,
+}
+
+impl ColleData {
+    pub fn new(id: ColleId) -> Self {
+        Self {
+            id,
+            slots: vec![],
+            substitutions: vec![],
+        }
+    }
+
+    pub fn add(&mut self, definition: ColleDefinition) {
+        match definition {
+            ColleDefinition::Slot(slot) => self.slots.push(slot),
+            ColleDefinition::Substitution(substitution) => self.substitutions.push(substitution),
+        }
+    }
+
+    /// Parses the colles file, by colle id, every id must have a slot
+    pub fn parse_list(
+        s: &str,
+        profs: &mut HashMap<Arc<str>, Arc<Prof>>,
+    ) -> Result<HashMap<String, Self>> {
+        let mut colle_list: HashMap<String, Self> = HashMap::new();
+        for line in s.lines() {
+            let (id, definition) = Colle::parse_string(line, profs)?;
+            colle_list
+                .entry(id.to_string())
+                .or_insert_with(|| Self::new(id))
+                .add(definition);
+        }
+        if let Some(data) = colle_list.values().find(|data| data.slots.is_empty()) {
+            Err(WattouError::ColleParsingFailed(ColleParsingError::NoSlot(
+                data.id.to_string(),
+            )))?
+        }
+        Ok(colle_list)
+    }
+
+    /// Colles of the given week, a later substitution wins over an earlier one
+    pub fn colles(&self, week: WeekId, weeks: &[Date]) -> Vec<Colle> {
+        self.slots
+            .iter()
+            .filter(|slot| slot.weeks.matches(week))
+            .map(|slot| {
+                let mut slot = slot.clone();
+                for substitution in self.substitutions.iter() {
+                    if substitution.weeks.matches(week) {
+                        match &substitution.change {
+                            Change::Room(room) => slot.room = room.clone(),
+                            Change::Prof(prof) => slot.prof = prof.clone(),
+                        }
+                    }
+                }
+                let date = get_date(weeks, week, slot.jour);
+                Colle::from_slot_and_date(self.id, &slot, date)
+            })
+            .collect()
+    }
+}
+
+static ICS_CATEGORY: Lazy<Categories> = Lazy::new(|| Categories::new("Colles"));
 
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct Colle {
@@ -67,17 +237,17 @@ pub struct Colle {
     pub end: OffsetDateTime,
 }
 
-impl ToString for Colle {
-    fn to_string(&self) -> String {
-        self.format(ColleStringFormat::Explicit, vec![])
+impl Display for Colle {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.format(ColleStringFormat::Explicit, vec![]))
     }
 }
 
 impl Colle {
+    /// e.g. : 14h-15h, 17h30-18h25
     pub fn horaire(&self) -> String {
-        let format = format_description!("[hour]h");
-        [self.start, self.end]
-            .map(|date| date.format(format).unwrap())
+        [self.start.time(), self.end.time()]
+            .map(format_time)
             .join("-")
     }
 
@@ -88,7 +258,7 @@ impl Colle {
                 ColleStringFormat::Explicit => self.id.explicit(),
                 ColleStringFormat::Implicit | ColleStringFormat::ForProf => self.id.to_string(),
             },
-            Jour::from(self.start.weekday()).to_string(),
+            Jour::from(self.start.weekday()),
             self.start.day(),
             month_to_short_fr(self.start.month()),
             self.horaire(),
@@ -100,89 +270,126 @@ impl Colle {
         )
     }
 
-    pub fn parse_string(s: impl Into<String>) -> Result<ColleData> {
-        let mut string = s.into();
-        let open_paren = string
-            .find("(")
+    /// Parses a line of the colles file, profs are shared between the colles of a guild
+    /// through `profs`
+    ///
+    /// A line is either a slot, e.g. `M1 Dupont Ma 17h-18h (207) [impaires]`, or a substitution,
+    /// e.g. `M1 [5] salle 305` or `M1 [5, 7] colleur Martin`. The week selector is optional for
+    /// slots and can be `paires`, `impaires` or a list of weeks.
+    pub fn parse_string(
+        s: impl Into<String>,
+        profs: &mut HashMap<Arc<str>, Arc<Prof>>,
+    ) -> Result<(ColleId, ColleDefinition)> {
+        let string = s.into();
+        let (id_str, rest) = string
+            .trim()
+            .split_once(' ')
             .ok_or(WattouError::ColleParsingFailed(ColleParsingError::Unknown))?;
-        let room_number = &string[open_paren + 1..string.len() - 1].to_string();
-        string.replace_range(open_paren - 1..string.len(), "");
-        let mut words = string.split(" ");
-        let id = ColleId::from_str(
-            words
-                .next()
-                .ok_or(WattouError::ColleParsingFailed(ColleParsingError::Unknown))?,
-        )?;
+        let id = ColleId::from_str(id_str)?;
 
-        let mut words_vec: Vec<_> = words.collect();
+        if let Some(selector) = rest.strip_prefix('[') {
+            let (selector, change) =
+                selector
+                    .split_once(']')
+                    .ok_or(WattouError::ColleParsingFailed(
+                        ColleParsingError::InvalidWeekSelector,
+                    ))?;
+            let weeks = selector.parse()?;
+            let change = match change.trim().split_once(' ') {
+                Some(("salle", room)) => Change::Room(room.trim().to_string()),
+                Some(("colleur", prof)) => Change::Prof(get_prof(profs, prof.trim())),
+                _ => Err(WattouError::ColleParsingFailed(
+                    ColleParsingError::UnknownSubstitution,
+                ))?,
+            };
+            return Ok((
+                id,
+                ColleDefinition::Substitution(Substitution { weeks, change }),
+            ));
+        }
+
+        let (slot, weeks) = match rest.trim_end().strip_suffix(']') {
+            Some(rest) => {
+                let (rest, selector) =
+                    rest.rsplit_once('[')
+                        .ok_or(WattouError::ColleParsingFailed(
+                            ColleParsingError::InvalidWeekSelector,
+                        ))?;
+                (rest.trim_end(), selector.parse()?)
+            }
+            None => (rest.trim_end(), WeekSelector::All),
+        };
+
+        let invalid_room = || {
+            WattouError::ColleParsingFailed(ColleParsingError::InvalidRoom(string.trim().into()))
+        };
+        let (slot, room_number) = slot
+            .strip_suffix(')')
+            .and_then(|slot| slot.rsplit_once('('))
+            .filter(|(slot, _)| !slot.trim().is_empty())
+            .ok_or_else(invalid_room)?;
+
+        let mut words_vec: Vec<_> = slot.split_whitespace().collect();
 
         let horaire = words_vec
             .pop()
             .ok_or(WattouError::ColleParsingFailed(ColleParsingError::Unknown))?;
 
-        let [start, end]: [u8; 2] = horaire
-            .split("-")
-            .map(|p| p[..p.len() - 1].parse().ok())
-            .collect::<Option<Vec<_>>>()
-            .ok_or(WattouError::ColleParsingFailed(ColleParsingError::Unknown))?
-            .try_into()
-            .ok()
+        let (start, end) = parse_horaire(horaire)
             .ok_or(WattouError::ColleParsingFailed(ColleParsingError::Unknown))?;
 
         let jour_str = words_vec
             .pop()
             .ok_or(WattouError::ColleParsingFailed(ColleParsingError::Unknown))?;
-        let jour = Jour::from(jour_str);
-
-        let prof_str = words_vec.join(" ");
-        let arc_str = Arc::from(prof_str);
-
-        let prof = if let Some(arc) = GLOBAL_DATA.lock().unwrap().profs.get(&arc_str) {
-            arc.clone()
-        } else {
-            let arc = Arc::new(Prof::new(arc_str.clone()));
-            GLOBAL_DATA
-                .lock()
-                .unwrap()
-                .profs
-                .insert(arc_str, arc.clone());
-            arc
-        };
+        let jour = Jour::try_from(jour_str).map_err(|day| {
+            WattouError::ColleParsingFailed(ColleParsingError::UnknownDay {
+                day,
+                line: string.trim().into(),
+            })
+        })?;
 
-        Ok((id, (start, end), jour, room_number.clone(), prof))
-    }
+        let prof = get_prof(profs, &words_vec.join(" "));
 
-    pub fn from_data_and_date(date: Date, data: ColleData) -> Result<Self> {
-        let (id, (start, end), _, room, prof) = data;
+        Ok((
+            id,
+            ColleDefinition::Slot(ColleSlot {
+                weeks,
+                horaire: (start, end),
+                jour,
+                room: room_number.to_string(),
+                prof,
+            }),
+        ))
+    }
 
-        Ok(Self {
+    pub fn from_slot_and_date(id: ColleId, slot: &ColleSlot, date: Date) -> Self {
+        let (start, end) = slot.horaire;
+        Self {
             id,
-            room,
-            start: date.with_hms(start, 0, 0)?.assume_utc(),
-            end: date.with_hms(end, 0, 0)?.assume_utc(),
-            prof,
-        })
+            room: slot.room.clone(),
+            start: date.with_time(start).assume_utc(),
+            end: date.with_time(end).assume_utc(),
+            prof: slot.prof.clone(),
+        }
     }
 
+    /// Colles are naive local times, written with the TZID of the calendar config
     pub fn to_ics_event(&self) -> Result<Event<'_>> {
-        let [start, end]: [String; 2] = [self.start, self.end]
-            .iter()
-            .map(|date| {
-                Ok(date
-                    .format(&Iso8601::DATE_TIME)?
-                    .chars()
-                    .filter(|c| c.is_alphanumeric())
-                    .collect::<String>())
-            })
-            .collect::<Result<Vec<String>, eyre::Report>>()?
-            .try_into()
-            .unwrap();
+        const ICS_TIME: &[BorrowedFormatItem<'_>] =
+            format_description!("[year][month][day]T[hour][minute][second]");
+        let timezone = &config().calendar.timezone;
+
+        let stamp = format!("{}Z", OffsetDateTime::now_utc().format(ICS_TIME)?);
+        let mut event = Event::new(Uuid::new_v4().to_string(), stamp);
 
-        let mut event = Event::new(Uuid::new_v4().to_string(), start.clone());
+        let mut start = DtStart::new(self.start.format(ICS_TIME)?);
+        start.add(TzIDParam::new(timezone.clone()));
+        let mut end = DtEnd::new(self.end.format(ICS_TIME)?);
+        end.add(TzIDParam::new(timezone.clone()));
 
         event.push(Organizer::new(self.prof.to_string()));
-        event.push(DtStart::new(start));
-        event.push(DtEnd::new(end));
+        event.push(start);
+        event.push(end);
         event.push(ICS_CATEGORY.clone());
         event.push(Summary::new(format!(
             "Colle {} avec {}",
@@ -201,6 +408,40 @@ impl Colle {
     }
 }
 
+/// e.g. : 17h-18h or 17h30-18h25, [None] if the end is not after the start
+pub fn parse_horaire(s: &str) -> Option<(Time, Time)> {
+    let (start, end) = s.split_once('-')?;
+    let (start, end) = (parse_time(start.trim())?, parse_time(end.trim())?);
+    (end > start).then_some((start, end))
+}
+
+/// e.g. : 17h, 17h30 or 17:30
+fn parse_time(s: &str) -> Option<Time> {
+    let (hour, minute) = s.split_once(['h', ':'])?;
+    let minute = if minute.is_empty() {
+        0
+    } else {
+        minute.parse().ok()?
+    };
+    Time::from_hms(hour.parse().ok()?, minute, 0).ok()
+}
+
+/// e.g. : 17h, 17h30
+fn format_time(time: Time) -> String {
+    match time.minute() {
+        0 => format!("{}h", time.hour()),
+        minute => format!("{}h{:02}", time.hour(), minute),
+    }
+}
+
+fn get_prof(profs: &mut HashMap<Arc<str>, Arc<Prof>>, name: &str) -> Arc<Prof> {
+    let name: Arc<str> = Arc::from(name);
+    profs
+        .entry(name.clone())
+        .or_insert_with(|| Arc::new(Prof::new(name)))
+        .clone()
+}
+
 impl PartialOrd for Colle {
     #[inline]
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
@@ -1,39 +1,86 @@
-use std::sync::Arc;
-
 use super::Context;
 use crate::{
-    colle::ColleStringFormat,
-    debug,
-    group::GroupId,
-    guild_data::{GuildData, SavedData},
-    recurrent_message::{SemaineTPMessage, ToutesLesCollesMessage},
+    GlobalData,
+    absence::{Absence, Absences},
+    class::Class,
+    colle::{self, Colle, ColleData, ColleId, ColleStringFormat},
+    config::{GuildConfig, GuildConfigKey},
+    diff::{AnnouncementChannel, ColloscopeDiff},
+    error::{AdminChannel, WattouError},
+    exercise::{self, Exercise, ExerciseFilter, Exercises},
+    fairness::FairnessReport,
+    generator::{Generator, GeneratorGroup},
+    ghost::{Ghost, OpenPlaces},
+    group::Group,
+    guild_data::{GuildData, SavedData, SavedDataWithDefault},
+    index::{ColleQuery, IndexedColle},
+    journal::{self, JournalEntry, Journals},
+    permissions::{self, CommandCategory, PermissionChoice, PermissionConfig, Rule, RuleTarget},
+    prof::ProfLinks,
+    programme::{self, Programme, ProgrammeChannel, Programmes},
+    purge::{self, Purge, PurgeFilter},
+    rattrapage::{RattrapageSlot, Rattrapages},
+    recurrent_message::{ClassBoards, SemaineTPMessage, ToutesLesCollesMessage},
+    roster::{self, Roster, RosterMember},
     subscriber::SubscriberData,
+    utils::{self, easy_comp_string},
+    weeks::{DATE_FORMAT, Weeks},
 };
-use color_eyre::Result;
-use poise::CreateReply;
-use serenity::all::CreateAttachment;
+use color_eyre::{Report, Result};
+use poise::{ChoiceParameter, CreateReply};
+use serenity::all::{
+    Attachment, AutocompleteChoice, ButtonStyle, ComponentInteractionCollector, CreateActionRow,
+    CreateAllowedMentions, CreateAttachment, CreateButton, CreateInteractionResponse, GuildId,
+    Mention, ResolvedValue, Role, User, UserId,
+};
+use std::sync::Arc;
+use time::{Date, OffsetDateTime};
+use tracing::{error, info};
 
-#[poise::command(slash_command, guild_only)]
+#[poise::command(slash_command, guild_only, category = "Consultation")]
 pub async fn mes_colles(
     ctx: Context<'_>,
-    #[description = "Groupe de colle"]
+    #[description = "Groupe de colle, par défaut le tien"]
     #[rename = "groupe"]
-    group_id: GroupId,
+    #[autocomplete = "autocomplete_group"]
+    group_id: Option<String>,
+    #[description = "Classe, par défaut celle de ton rôle"]
+    #[rename = "classe"]
+    #[autocomplete = "autocomplete_class"]
+    class: Option<String>,
 ) -> Result<()> {
     ctx.defer_ephemeral().await?;
     let guild_data = GuildData::from_ctx(ctx)?;
-    let group = guild_data.get_group(group_id)?;
+    let class = guild_data.class_for_ctx(ctx, class.as_deref()).await?;
+    let group = guild_data
+        .group_for_ctx(ctx, class, group_id.as_deref())
+        .await?;
+    let mut colles = class
+        .query(&ColleQuery::upcoming().group(&group.id).limit(5))
+        .into_iter()
+        .map(|indexed| indexed.colle.clone())
+        .chain(own_rattrapages(ctx, &guild_data, class, group).await?)
+        .collect::<Vec<_>>();
+    colles.sort();
+    let programmes = Programmes::read_or_default(guild_data.guild_id)?;
 
     ctx.send(
         CreateReply::default()
             .ephemeral(true)
             .content(format!(
-                "Prochaines colles pour le groupe {}: \n- {}",
-                group.id,
-                group
-                    .get_next_colles(5)
+                "Prochaines colles pour le groupe {}{}: \n- {}",
+                group,
+                class_suffix(&guild_data, class),
+                colles
                     .iter()
-                    .map(|colle| colle.format(ColleStringFormat::Explicit, vec![]))
+                    .take(5)
+                    .map(|colle| {
+                        let line = colle.format(ColleStringFormat::Explicit, vec![]);
+                        match programmes.for_colle(class, colle) {
+                            Some(programme) => format!("{line}\n  {}", programme.excerpt()),
+                            None => line,
+                        }
+                    })
                     .collect::<Vec<_>>()
                     .join("\n- ")
             ))
@@ -43,22 +90,33 @@ pub async fn mes_colles(
     Ok(())
 }
 
-#[poise::command(slash_command, guild_only)]
+#[poise::command(slash_command, guild_only, category = "Consultation")]
 pub async fn colles_calendrier(
     ctx: Context<'_>,
-    #[description = "Groupe de colle"]
+    #[description = "Groupe de colle, par défaut le tien"]
     #[rename = "groupe"]
-    group_id: GroupId,
+    #[autocomplete = "autocomplete_group"]
+    group_id: Option<String>,
+    #[description = "Classe, par défaut celle de ton rôle"]
+    #[rename = "classe"]
+    #[autocomplete = "autocomplete_class"]
+    class: Option<String>,
 ) -> Result<()> {
     ctx.defer_ephemeral().await?;
     let data = GuildData::from_ctx(ctx)?;
+    let class = data.class_for_ctx(ctx, class.as_deref()).await?;
+    let group = data.group_for_ctx(ctx, class, group_id.as_deref()).await?;
 
     ctx.send(
         CreateReply::default()
             .ephemeral(true)
             .attachment(CreateAttachment::bytes(
-                data.get_group(group_id)?.ics_calendar()?,
-                format!("Calendrier de colles group {}.ics", group_id),
+                class.ics_calendar(&group.id, own_rattrapages(ctx, &data, class, group).await?)?,
+                format!(
+                    "Calendrier de colles group {}{}.ics",
+                    group.id,
+                    class_suffix(&data, class)
+                ),
             ))
             .content("Importe le fichier dans ton calendrier pour y ajouter les colles !"),
     )
@@ -66,22 +124,47 @@ pub async fn colles_calendrier(
     Ok(())
 }
 
-#[poise::command(slash_command, guild_only)]
-pub async fn toutes_les_colles(ctx: Context<'_>) -> Result<()> {
+/// Message tenu à jour avec les prochaines colles, de toutes les classes si aucune n'est donnée
+#[poise::command(slash_command, guild_only, category = "Administration")]
+pub async fn toutes_les_colles(
+    ctx: Context<'_>,
+    #[description = "Uniquement cette classe"]
+    #[rename = "classe"]
+    #[autocomplete = "autocomplete_class"]
+    class: Option<String>,
+) -> Result<()> {
     ctx.defer().await?;
     let data = GuildData::from_ctx(ctx)?;
-    let handle = ctx.say(data.prochaines_colles_msg()).await?;
 
+    let Some(class) = class else {
+        let handle = ctx.say(data.prochaines_colles_msg()?).await?;
+        let message = handle.message().await?;
+        ToutesLesCollesMessage::from(&message).save(data.guild_id)?;
+        info!(
+            message_id = %message.id,
+            channel_id = %message.channel_id,
+            "new toutes les colles message"
+        );
+        return Ok(());
+    };
+
+    let class = data.class(Some(&class))?;
+    let handle = ctx.say(data.class_colles_msg(class)?).await?;
     let message = handle.message().await?;
-    ToutesLesCollesMessage::from(&message).save(data.guild_id)?;
-    debug!(
-        "new toutes les colles msg : {} {}",
-        message.id, message.channel_id,
+    ClassBoards::update(data.guild_id, |boards| {
+        boards.set(&class.name, &message);
+        Ok(())
+    })?;
+    info!(
+        message_id = %message.id,
+        channel_id = %message.channel_id,
+        class = %class.name,
+        "new class toutes les colles message"
     );
     Ok(())
 }
 
-#[poise::command(slash_command, guild_only)]
+#[poise::command(slash_command, guild_only, category = "Administration")]
 pub async fn semaine_tp(ctx: Context<'_>) -> Result<()> {
     ctx.defer_ephemeral().await?;
     let data = GuildData::from_ctx(ctx)?;
@@ -90,133 +173,3117 @@ pub async fn semaine_tp(ctx: Context<'_>) -> Result<()> {
 
     let message = handle.message().await?;
     SemaineTPMessage::from(&message).save(data.guild_id)?;
-    debug!("new semaine tp msg : {} {}", message.id, message.channel_id);
+    info!(
+        message_id = %message.id,
+        channel_id = %message.channel_id,
+        "new semaine tp message"
+    );
     Ok(())
 }
 
-#[poise::command(slash_command)]
+#[poise::command(slash_command, category = "Consultation")]
 pub async fn rappel(
     ctx: Context<'_>,
     #[description = "Groupe de colle"]
     #[rename = "groupe"]
-    group: usize,
+    #[autocomplete = "autocomplete_group"]
+    group_id: String,
+    #[description = "Classe, par défaut celle de ton rôle"]
+    #[rename = "classe"]
+    #[autocomplete = "autocomplete_class"]
+    class: Option<String>,
 ) -> Result<()> {
     ctx.defer_ephemeral().await?;
     let data = GuildData::from_ctx(ctx)?;
+    let class = data.class_for_ctx(ctx, class.as_deref()).await?;
+    let group = class.get_group(&group_id)?;
+    if class.current_ghost(&group.id).is_some() {
+        Err(WattouError::GhostGroup(group.to_string()))?
+    }
 
     let user_id = ctx.author().id;
-    let mut subscribers = data.subscribers()?;
-    if let Some(current) = subscribers.get(&user_id) {
-        if current.group_id == group {
-            let c = current.clone();
-            subscribers.remove(data.guild_id, &user_id)?;
-
-            ctx.say(format!("Rappel désactivé")).await?;
-
-            debug!(
-                "{} unsubscribed from group reminders {:?}",
-                ctx.author().id,
-                c
+    let previous = {
+        let subscribers = data.subscribers()?;
+        let mut subscribers = subscribers.write().await;
+        let previous = subscribers.get(&user_id).cloned();
+        if previous
+            .as_ref()
+            .is_some_and(|current| current.is_for(class, group))
+        {
+            subscribers.remove(&user_id);
+        } else {
+            subscribers.set(user_id, SubscriberData::new_default(&data, class, group));
+        }
+        subscribers.save(data.guild_id)?;
+        previous
+    };
+
+    if let Some(current) = previous {
+        if current.is_for(class, group) {
+            ctx.say("Rappel désactivé").await?;
+
+            info!(
+                user_id = %ctx.author().id,
+                group = %current.group_id,
+                "unsubscribed from group reminders"
             );
             return Ok(());
         }
 
-        ctx.say(format!("Rappel désactivé pour le groupe {}", group))
-            .await?;
+        ctx.say(format!(
+            "Rappel désactivé pour le groupe {}",
+            current.group_id
+        ))
+        .await?;
     }
 
-    ctx.say(format!("Tu auras désormais un rappel de prendre ton carnet de colle à chaque fois que le groupe {} a colle d'anglais !\nRefais la commande pour désactiver", group)).await?;
-
-    subscribers.set(data.guild_id, user_id, SubscriberData::new_default(group))?;
+    let subject = data.config()?.reminder_subject();
+    ctx.say(format!(
+        "Tu auras désormais un rappel de prendre ton carnet de colle à chaque fois que le groupe {}{} a colle {} !\nRefais la commande pour désactiver",
+        group,
+        class_suffix(&data, class),
+        ColleId::subject_name(subject).map_or(subject.to_string(), utils::de)
+    ))
+    .await?;
 
-    debug!(
-        "{} subscribed to group reminders {}",
-        ctx.author().id,
-        group
+    info!(
+        user_id = %ctx.author().id,
+        class = %class.name,
+        group = %group.id,
+        "subscribed to group reminders"
     );
 
     Ok(())
 }
 
-#[poise::command(slash_command)]
-pub async fn clear(ctx: Context<'_>, limit: u8) -> Result<()> {
+/// Publishes the files of the guild, announces the changes of the colles not over yet and then
+/// refreshes the board, returns the published data and a summary of the changes
+async fn reload_and_announce(
+    ctx: Context<'_>,
+    guild_id: GuildId,
+) -> Result<(Arc<GuildData>, String)> {
+    // Only the published snapshot is compared, loading it here would read the new files
+    let previous = GuildData::cached(guild_id);
+    let data = GuildData::reload(guild_id)?;
+    let changes = match previous {
+        Some(previous) => {
+            let diff = ColloscopeDiff::new(&previous, &data);
+            let reached = diff.announce(&data, ctx.http()).await?;
+            format!(
+                "\n{} changement(s) pour les colles à venir de {} groupe(s), {} élève(s) prévenu(s)",
+                diff.change_count(),
+                diff.groups.len(),
+                reached
+            )
+        }
+        None => String::new(),
+    };
+    // The changes are already announced, a board that cannot be edited must not hide them
+    if let Err(e) = data.try_edit_toutes_les_colles_msg(ctx.http()).await {
+        error!(error = ?e, "failed to edit the colles board after a reload");
+    }
+    Ok((data, changes))
+}
+
+/// Relit les fichiers du colloscope du serveur
+#[poise::command(
+    slash_command,
+    guild_only,
+    category = "Administration",
+    default_member_permissions = "MANAGE_GUILD"
+)]
+pub async fn recharger(
+    ctx: Context<'_>,
+    #[description = "Affiche les changements sans appliquer les nouveaux fichiers"]
+    #[rename = "apercu"]
+    preview: Option<bool>,
+) -> Result<()> {
     ctx.defer_ephemeral().await?;
-    let messages = ctx
-        .http()
-        .get_messages(ctx.channel_id(), None, Some(limit))
-        .await?;
+    let guild_id = ctx
+        .guild_id()
+        .ok_or(WattouError::CommandCanOnlyBeUsedInGuilds)?;
 
-    for message in messages {
-        message.delete(ctx).await?;
+    // Only the published snapshot is compared, loading it here would read the new files
+    let previous = GuildData::cached(guild_id);
+    if preview.unwrap_or(false) {
+        let files = GuildData::new(guild_id)?;
+        let Some(current) = previous else {
+            ctx.say("Les fichiers sont valides, aucun colloscope n'est chargé pour les comparer")
+                .await?;
+            return Ok(());
+        };
+        let diff = ColloscopeDiff::new(&current, &files);
+        let (content, attachment) =
+            diff.message("# Aperçu des changements\nLes nouveaux fichiers ne sont pas encore appliqués, `/recharger` les applique.");
+        let mut reply = CreateReply::default().ephemeral(true).content(content);
+        if let Some(attachment) = attachment {
+            reply = reply.attachment(attachment);
+        }
+        ctx.send(reply).await?;
+        return Ok(());
     }
 
-    ctx.say(format!("{} messages supprimé(s)", limit)).await?;
+    let (data, changes) = reload_and_announce(ctx, guild_id).await?;
 
+    ctx.say(format!(
+        "Colloscope rechargé : {} classe(s), {} groupes, {} colleurs{changes}",
+        data.classes.len(),
+        data.classes
+            .iter()
+            .map(|class| class.active_period().groups.len())
+            .sum::<usize>(),
+        data.profs.len()
+    ))
+    .await?;
+    info!(
+        user_id = %ctx.author().id,
+        guild_id = %guild_id,
+        "reloaded guild data"
+    );
     Ok(())
 }
 
-#[poise::command(slash_command)]
-pub async fn colles_de_prof(
+/// Élèves de chaque groupe
+#[poise::command(
+    slash_command,
+    guild_only,
+    subcommands(
+        "groupe_choisir",
+        "groupe_quitter",
+        "groupe_membres",
+        "groupe_importer",
+        "groupe_mentionner",
+        "groupe_mp",
+        "groupe_delegue"
+    )
+)]
+pub async fn groupe(_ctx: Context<'_>) -> Result<()> {
+    Ok(())
+}
+
+/// Indique ton groupe, les commandes l'utiliseront par défaut
+#[poise::command(
+    slash_command,
+    guild_only,
+    category = "Consultation",
+    rename = "choisir"
+)]
+pub async fn groupe_choisir(
     ctx: Context<'_>,
-    #[rename = "prof"]
-    #[autocomplete = "autocomplete_prof"]
-    prof_str: String,
-    #[rename = "limite"] limit: Option<usize>,
+    #[description = "Groupe de colle"]
+    #[rename = "groupe"]
+    #[autocomplete = "autocomplete_group"]
+    group_id: String,
+    #[description = "Nom affiché à la place de ta mention"]
+    #[rename = "nom"]
+    name: Option<String>,
+    #[description = "Classe, par défaut celle de ton rôle"]
+    #[rename = "classe"]
+    #[autocomplete = "autocomplete_class"]
+    class: Option<String>,
 ) -> Result<()> {
     ctx.defer_ephemeral().await?;
-    let limit = limit.and_then(|l| (l < 100).then_some(l)).unwrap_or(5);
     let data = GuildData::from_ctx(ctx)?;
+    let class = data.class_for_ctx(ctx, class.as_deref()).await?;
+    let group = class.get_group(&group_id)?;
+    let period = class.active_period();
 
-    let Some(prof) = ctx
-        .data()
-        .lock()
-        .unwrap()
-        .profs
-        .get(&Arc::from(prof_str))
-        .map(|p| p.clone())
-    else {
-        todo!("impl error");
+    Roster::update(data.guild_id, |roster| {
+        roster.set(
+            class,
+            period,
+            ctx.author().id,
+            RosterMember {
+                group_id: group.id.to_string(),
+                name,
+            },
+        );
+        Ok(())
+    })?;
+
+    ctx.say(format!(
+        "Tu es dans le groupe {}{}",
+        group,
+        class_suffix(&data, class)
+    ))
+    .await?;
+    info!(
+        user_id = %ctx.author().id,
+        class = %class.name,
+        period = %period.name,
+        group = %group.id,
+        "declared group"
+    );
+    Ok(())
+}
+
+/// Retire ton nom de la liste des élèves de ton groupe
+#[poise::command(
+    slash_command,
+    guild_only,
+    category = "Consultation",
+    rename = "quitter"
+)]
+pub async fn groupe_quitter(
+    ctx: Context<'_>,
+    #[description = "Classe, par défaut celle de ton rôle"]
+    #[rename = "classe"]
+    #[autocomplete = "autocomplete_class"]
+    class: Option<String>,
+) -> Result<()> {
+    ctx.defer_ephemeral().await?;
+    let data = GuildData::from_ctx(ctx)?;
+    let class = data.class_for_ctx(ctx, class.as_deref()).await?;
+    let period = class.active_period();
+
+    let removed = Roster::update(data.guild_id, |roster| {
+        Ok(roster.remove(class, period, ctx.author().id))
+    })?;
+
+    ctx.say(if removed {
+        "Tu n'es plus dans aucun groupe"
+    } else {
+        "Tu n'étais dans aucun groupe pour cette période"
+    })
+    .await?;
+    Ok(())
+}
+
+/// Qui est dans un groupe, par défaut le tien
+#[poise::command(
+    slash_command,
+    guild_only,
+    category = "Consultation",
+    rename = "membres"
+)]
+pub async fn groupe_membres(
+    ctx: Context<'_>,
+    #[description = "Groupe de colle, par défaut le tien"]
+    #[rename = "groupe"]
+    #[autocomplete = "autocomplete_group"]
+    group_id: Option<String>,
+    #[description = "Classe, par défaut celle de ton rôle"]
+    #[rename = "classe"]
+    #[autocomplete = "autocomplete_class"]
+    class: Option<String>,
+) -> Result<()> {
+    ctx.defer_ephemeral().await?;
+    let data = GuildData::from_ctx(ctx)?;
+    let class = data.class_for_ctx(ctx, class.as_deref()).await?;
+    let group = data.group_for_ctx(ctx, class, group_id.as_deref()).await?;
+    let roster = Roster::read_or_default(data.guild_id)?;
+
+    let members = roster.members(class, group);
+    let content = if members.is_empty() {
+        format!(
+            "Personne ne s'est inscrit dans le groupe {}{}",
+            group,
+            class_suffix(&data, class)
+        )
+    } else {
+        format!(
+            "Groupe {}{} :\n- {}",
+            group,
+            class_suffix(&data, class),
+            members
+                .iter()
+                .map(|(user_id, member)| match &member.name {
+                    Some(name) => format!("{name} ({})", Mention::from(*user_id)),
+                    None => Mention::from(*user_id).to_string(),
+                })
+                .collect::<Vec<_>>()
+                .join("\n- ")
+        )
     };
 
     ctx.send(
         CreateReply::default()
             .ephemeral(true)
+            .content(content)
+            .allowed_mentions(CreateAllowedMentions::new()),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Remplace la liste des élèves d'une période par celle d'un fichier
+#[poise::command(
+    slash_command,
+    guild_only,
+    category = "Administration",
+    rename = "importer"
+)]
+pub async fn groupe_importer(
+    ctx: Context<'_>,
+    #[description = "Une ligne par élève : groupe, identifiant ou mention, nom optionnel"]
+    #[rename = "fichier"]
+    file: Attachment,
+    #[description = "Période, par défaut celle en cours"]
+    #[rename = "periode"]
+    period: Option<String>,
+    #[description = "Classe, par défaut celle de ton rôle"]
+    #[rename = "classe"]
+    #[autocomplete = "autocomplete_class"]
+    class: Option<String>,
+) -> Result<()> {
+    ctx.defer_ephemeral().await?;
+    let data = GuildData::from_ctx(ctx)?;
+    let class = data.class_for_ctx(ctx, class.as_deref()).await?;
+    let period = match period {
+        Some(name) => class
+            .periods
+            .iter()
+            .find(|period| easy_comp_string(&period.name) == easy_comp_string(&name))
+            .ok_or(WattouError::PeriodNotFound(name))?,
+        None => class.active_period(),
+    };
+
+    let content = String::from_utf8_lossy(&file.download().await?).into_owned();
+    let members = Roster::parse_import(&content, period)?;
+    let count = members.len();
+    Roster::update(data.guild_id, |roster| {
+        roster.import(class, period, members);
+        Ok(())
+    })?;
+
+    ctx.say(format!(
+        "{} élève(s) importé(s){} pour la période {}",
+        count,
+        class_suffix(&data, class),
+        period.name
+    ))
+    .await?;
+    info!(
+        user_id = %ctx.author().id,
+        class = %class.name,
+        period = %period.name,
+        count,
+        "imported roster"
+    );
+    Ok(())
+}
+
+/// Envoie un message dans ce salon en mentionnant les élèves d'un groupe
+#[poise::command(
+    slash_command,
+    guild_only,
+    category = "Administration",
+    rename = "mentionner"
+)]
+pub async fn groupe_mentionner(
+    ctx: Context<'_>,
+    #[description = "Groupe de colle"]
+    #[rename = "groupe"]
+    #[autocomplete = "autocomplete_group"]
+    group_id: String,
+    #[description = "Message"] message: String,
+    #[description = "Classe, par défaut celle de ton rôle"]
+    #[rename = "classe"]
+    #[autocomplete = "autocomplete_class"]
+    class: Option<String>,
+) -> Result<()> {
+    ctx.defer().await?;
+    let data = GuildData::from_ctx(ctx)?;
+    let class = data.class_for_ctx(ctx, class.as_deref()).await?;
+    let group = class.get_group(&group_id)?;
+    let user_ids = Roster::read_or_default(data.guild_id)?
+        .members(class, group)
+        .into_iter()
+        .map(|(user_id, _)| user_id)
+        .collect::<Vec<_>>();
+    if user_ids.is_empty() {
+        ctx.say(format!("Personne ne s'est inscrit dans le groupe {group}"))
+            .await?;
+        return Ok(());
+    }
+
+    ctx.send(
+        CreateReply::default()
             .content(format!(
-                "Prochaines colles pour {}: \n- {}",
-                prof.name(),
-                prof.get_next_colles_in_guild(data, limit)
+                "{} {}",
+                user_ids
                     .iter()
-                    .map(|(groupe_id, colle)| colle
-                        .format(ColleStringFormat::ForProf, vec![groupe_id.to_string()]))
+                    .map(|user_id| Mention::from(*user_id).to_string())
                     .collect::<Vec<_>>()
-                    .join("\n- ")
+                    .join(" "),
+                message
             ))
-            .reply(true),
+            .allowed_mentions(CreateAllowedMentions::new().users(user_ids)),
     )
     .await?;
+    Ok(())
+}
+
+/// Envoie un message privé à chaque élève d'un groupe
+#[poise::command(slash_command, guild_only, category = "Administration", rename = "mp")]
+pub async fn groupe_mp(
+    ctx: Context<'_>,
+    #[description = "Groupe de colle"]
+    #[rename = "groupe"]
+    #[autocomplete = "autocomplete_group"]
+    group_id: String,
+    #[description = "Message"] message: String,
+    #[description = "Classe, par défaut celle de ton rôle"]
+    #[rename = "classe"]
+    #[autocomplete = "autocomplete_class"]
+    class: Option<String>,
+) -> Result<()> {
+    ctx.defer_ephemeral().await?;
+    let data = GuildData::from_ctx(ctx)?;
+    let class = data.class_for_ctx(ctx, class.as_deref()).await?;
+    let group = class.get_group(&group_id)?;
+    let user_ids = Roster::read_or_default(data.guild_id)?
+        .members(class, group)
+        .into_iter()
+        .map(|(user_id, _)| user_id)
+        .collect::<Vec<_>>();
 
+    let sent = roster::send_dms(ctx.http(), &user_ids, &message).await;
+    ctx.say(format!(
+        "Message envoyé à {}/{} élève(s) du groupe {}",
+        sent,
+        user_ids.len(),
+        group
+    ))
+    .await?;
+    info!(
+        user_id = %ctx.author().id,
+        class = %class.name,
+        group = %group.id,
+        sent,
+        "sent DMs to group"
+    );
     Ok(())
 }
 
-pub async fn autocomplete_prof(ctx: Context<'_>, partial: &str) -> Vec<String> {
-    let input = easy_comp_string(partial);
-    ctx.data()
-        .lock()
-        .unwrap()
-        .profs
+/// Ajoute ou retire un délégué de la classe, prévenu des absences
+#[poise::command(
+    slash_command,
+    guild_only,
+    category = "Administration",
+    rename = "delegue"
+)]
+pub async fn groupe_delegue(
+    ctx: Context<'_>,
+    #[description = "Membre"]
+    #[rename = "membre"]
+    member: User,
+    #[description = "Classe, par défaut celle de ton rôle"]
+    #[rename = "classe"]
+    #[autocomplete = "autocomplete_class"]
+    class: Option<String>,
+) -> Result<()> {
+    ctx.defer_ephemeral().await?;
+    let data = GuildData::from_ctx(ctx)?;
+    let class = data.class_for_ctx(ctx, class.as_deref()).await?;
+
+    let delegate = Roster::update(data.guild_id, |roster| {
+        Ok(roster.toggle_delegate(class, member.id))
+    })?;
+
+    ctx.send(
+        CreateReply::default()
+            .ephemeral(true)
+            .content(format!(
+                "{} {} délégué{}",
+                Mention::from(member.id),
+                if delegate { "est" } else { "n'est plus" },
+                class_suffix(&data, class)
+            ))
+            .allowed_mentions(CreateAllowedMentions::new()),
+    )
+    .await?;
+    info!(
+        user_id = %ctx.author().id,
+        class = %class.name,
+        delegate_id = %member.id,
+        delegate,
+        "edited class delegates"
+    );
+    Ok(())
+}
+
+/// Préviens ton colleur et les délégués que tu seras absent à une colle
+#[poise::command(slash_command, guild_only, category = "Consultation")]
+pub async fn absence(
+    ctx: Context<'_>,
+    #[description = "Colle manquée"]
+    #[autocomplete = "autocomplete_own_colle"]
+    colle: String,
+    #[description = "Raison de l'absence"]
+    #[rename = "raison"]
+    #[max_length = 200]
+    reason: String,
+    #[description = "Groupe de colle, par défaut le tien, un autre groupe pour les admins"]
+    #[rename = "groupe"]
+    #[autocomplete = "autocomplete_group"]
+    group_id: Option<String>,
+    #[description = "Classe, par défaut celle de ton rôle"]
+    #[rename = "classe"]
+    #[autocomplete = "autocomplete_class"]
+    class: Option<String>,
+) -> Result<()> {
+    ctx.defer_ephemeral().await?;
+    let data = GuildData::from_ctx(ctx)?;
+    let class = data.class_for_ctx(ctx, class.as_deref()).await?;
+    let group = data.group_for_ctx(ctx, class, group_id.as_deref()).await?;
+    // Students declare their own absences only, admins may declare one for any group
+    if group_id.is_some()
+        && !permissions::is_admin(ctx).await?
+        && data.group_for_ctx(ctx, class, None).await?.id != group.id
+    {
+        Err(WattouError::AbsenceInOtherGroup(group.to_string()))?
+    }
+    let indexed = class
+        .query(&ColleQuery::upcoming().group(&group.id))
+        .into_iter()
+        .find(|indexed| indexed.key() == colle)
+        .ok_or(WattouError::ColleNotFound)?;
+
+    let user_id = ctx.author().id;
+    let absence = Absence::new(class, indexed, user_id, reason);
+    Absences::update(data.guild_id, |absences| absences.add(absence.clone()))?;
+
+    let roster = Roster::read_or_default(data.guild_id)?;
+    let student = roster
+        .members(class, group)
+        .into_iter()
+        .find(|(id, _)| *id == user_id)
+        .and_then(|(_, member)| member.name.clone())
+        .unwrap_or_else(|| ctx.author().name.clone());
+    let prof = ProfLinks::read_or_default(data.guild_id)?.get(&indexed.colle.prof);
+    let delegates = roster
+        .delegates(class)
         .iter()
-        .filter_map(|(_, p)| {
-            let name = p.name();
-            easy_comp_string(name)
-                .contains(&input)
-                .then_some(name.to_owned())
-        })
-        .collect::<Vec<_>>()
+        .copied()
+        .filter(|id| *id != user_id && Some(*id) != prof)
+        .collect::<Vec<_>>();
+    let recipients = prof
+        .into_iter()
+        .chain(delegates.clone())
+        .collect::<Vec<_>>();
+    roster::send_dms(ctx.http(), &recipients, &absence.notification(&student)).await;
+
+    ctx.say(format!(
+        "Absence enregistrée pour la colle {}\n{}{}",
+        absence.colle,
+        if prof.is_some() {
+            "Ton colleur a été prévenu"
+        } else {
+            "Ton colleur n'est pas lié au bot, préviens-le toi-même"
+        },
+        match delegates.len() {
+            0 => String::new(),
+            n => format!(", {n} délégué(s) aussi"),
+        }
+    ))
+    .await?;
+    info!(
+        user_id = %user_id,
+        class = %class.name,
+        group = %group.id,
+        colle = absence.colle_key,
+        notified = recipients.len(),
+        "declared absence"
+    );
+    Ok(())
+}
+
+/// Colles manquées qui doivent encore être rattrapées
+#[poise::command(slash_command, guild_only, category = "Administration")]
+pub async fn rattrapages(
+    ctx: Context<'_>,
+    #[description = "Classe, par défaut celle de ton rôle"]
+    #[rename = "classe"]
+    #[autocomplete = "autocomplete_class"]
+    class: Option<String>,
+) -> Result<()> {
+    ctx.defer_ephemeral().await?;
+    let data = GuildData::from_ctx(ctx)?;
+    let class = data.class_for_ctx(ctx, class.as_deref()).await?;
+    let absences = Absences::read_or_default(data.guild_id)?;
+
+    let absences = absences.needing_rattrapage(class);
+    let mut content = if absences.is_empty() {
+        format!("Aucune colle à rattraper{}", class_suffix(&data, class))
+    } else {
+        format!("Colles à rattraper{} :", class_suffix(&data, class))
+    };
+    // Discord messages are limited to 2000 characters, the absences that do not fit are counted
+    let others = |count: usize| format!("\net {count} autre(s)");
+    for (shown, absence) in absences.iter().enumerate() {
+        let line = format!(
+            "\n- {} (groupe {}) : {} ({})",
+            Mention::from(absence.user_id),
+            absence.group_id,
+            absence.colle,
+            absence.reason
+        );
+        let left = absences.len() - shown - 1;
+        let footer = if left > 0 {
+            others(left).chars().count()
+        } else {
+            0
+        };
+        if content.chars().count() + line.chars().count() + footer > 2000 {
+            content += &others(absences.len() - shown);
+            break;
+        }
+        content += &line;
+    }
+
+    ctx.send(
+        CreateReply::default()
+            .ephemeral(true)
+            .content(content)
+            .allowed_mentions(CreateAllowedMentions::new()),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Colles en dehors du colloscope pour rattraper une colle manquée
+#[poise::command(
+    slash_command,
+    guild_only,
+    category = "Consultation",
+    subcommands(
+        "rattrapage_liste",
+        "rattrapage_proposer",
+        "rattrapage_supprimer",
+        "rattrapage_reserver",
+        "rattrapage_annuler"
+    )
+)]
+pub async fn rattrapage(_ctx: Context<'_>) -> Result<()> {
+    Ok(())
+}
+
+/// Prochains créneaux de rattrapage et leurs places
+#[poise::command(slash_command, guild_only, category = "Consultation", rename = "liste")]
+pub async fn rattrapage_liste(
+    ctx: Context<'_>,
+    #[description = "Classe, par défaut celle de ton rôle"]
+    #[rename = "classe"]
+    #[autocomplete = "autocomplete_class"]
+    class: Option<String>,
+) -> Result<()> {
+    ctx.defer_ephemeral().await?;
+    let data = GuildData::from_ctx(ctx)?;
+    let class = data.class_for_ctx(ctx, class.as_deref()).await?;
+    let rattrapages = Rattrapages::read_or_default(data.guild_id)?;
+
+    let slots = rattrapages.upcoming(class);
+    let content = if slots.is_empty() {
+        format!("Aucun rattrapage prévu{}", class_suffix(&data, class))
+    } else {
+        format!(
+            "Rattrapages{} :\n- {}",
+            class_suffix(&data, class),
+            slots
+                .iter()
+                .take(20)
+                .map(|slot| {
+                    let mut line = slot.describe(&data.profs);
+                    if !slot.booked.is_empty() {
+                        line += &format!(
+                            ", réservé par {}",
+                            slot.booked
+                                .iter()
+                                .map(|user_id| Mention::from(*user_id).to_string())
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        );
+                    }
+                    line
+                })
+                .collect::<Vec<_>>()
+                .join("\n- ")
+        )
+    };
+
+    ctx.send(
+        CreateReply::default()
+            .ephemeral(true)
+            .content(content)
+            .allowed_mentions(CreateAllowedMentions::new()),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Propose un créneau de rattrapage, réservé aux colleurs
+#[poise::command(
+    slash_command,
+    guild_only,
+    category = "Consultation",
+    rename = "proposer"
+)]
+pub async fn rattrapage_proposer(
+    ctx: Context<'_>,
+    #[description = "Jour du rattrapage, par exemple 13-1-2025"] date: String,
+    #[description = "Horaire, par exemple 17h-18h"] horaire: String,
+    #[description = "Salle"]
+    #[rename = "salle"]
+    room: String,
+    #[description = "Matière, par exemple M ou Maths"]
+    #[rename = "matiere"]
+    subject: String,
+    #[description = "Nombre d'élèves, 1 par défaut"]
+    #[rename = "places"]
+    #[min = 1]
+    #[max = 10]
+    capacity: Option<usize>,
+    #[description = "Classe, par défaut celle de ton rôle"]
+    #[rename = "classe"]
+    #[autocomplete = "autocomplete_class"]
+    class: Option<String>,
+) -> Result<()> {
+    ctx.defer_ephemeral().await?;
+    let data = GuildData::from_ctx(ctx)?;
+    let class = data.class_for_ctx(ctx, class.as_deref()).await?;
+    let prof = ProfLinks::read_or_default(data.guild_id)?
+        .prof_of(ctx.author().id)
+        .map(str::to_string)
+        .ok_or(WattouError::NotAColleur)?;
+    let day = Date::parse(&date, DATE_FORMAT).map_err(|_| WattouError::InvalidDate(date))?;
+    let (start, end) =
+        colle::parse_horaire(&horaire).ok_or(WattouError::InvalidHoraire(horaire))?;
+    let subject = ColleId::parse_subject(&subject).ok_or(WattouError::UnknownSubject(subject))?;
+
+    let slot = RattrapageSlot {
+        id: 0,
+        class: class.name.to_string(),
+        prof,
+        subject,
+        room,
+        start: day.with_time(start).assume_utc().unix_timestamp(),
+        end: day.with_time(end).assume_utc().unix_timestamp(),
+        capacity: capacity.unwrap_or(1),
+        booked: vec![],
+    };
+    if !slot.is_upcoming() {
+        Err(WattouError::InvalidDate(day.format(DATE_FORMAT)?))?
+    }
+    let slot = Rattrapages::update(data.guild_id, |rattrapages| {
+        rattrapages.remove_past();
+        Ok(rattrapages.add(slot))
+    })?;
+
+    ctx.say(format!(
+        "Rattrapage proposé{} : {}",
+        class_suffix(&data, class),
+        slot.describe(&data.profs)
+    ))
+    .await?;
+    info!(
+        user_id = %ctx.author().id,
+        class = %class.name,
+        rattrapage = slot.id,
+        "proposed rattrapage"
+    );
+    Ok(())
+}
+
+/// Supprime un de tes créneaux de rattrapage, les élèves inscrits sont prévenus
+#[poise::command(
+    slash_command,
+    guild_only,
+    category = "Consultation",
+    rename = "supprimer"
+)]
+pub async fn rattrapage_supprimer(
+    ctx: Context<'_>,
+    #[description = "Numéro du rattrapage"]
+    #[rename = "numero"]
+    #[autocomplete = "autocomplete_own_rattrapage"]
+    id: u32,
+) -> Result<()> {
+    ctx.defer_ephemeral().await?;
+    let data = GuildData::from_ctx(ctx)?;
+    let prof = ProfLinks::read_or_default(data.guild_id)?
+        .prof_of(ctx.author().id)
+        .map(str::to_string)
+        .ok_or(WattouError::NotAColleur)?;
+
+    let slot = Rattrapages::update(data.guild_id, |rattrapages| {
+        if rattrapages.get(id)?.prof != prof {
+            Err(WattouError::RattrapageNotFound)?
+        }
+        rattrapages.remove(id)
+    })?;
+    Absences::update(data.guild_id, |absences| {
+        absences.unassign(slot.id, None);
+        Ok(())
+    })?;
+    roster::send_dms(
+        ctx.http(),
+        &slot.booked,
+        &format!(
+            "❌ Le rattrapage {} est annulé, réserve un autre créneau avec `/rattrapage reserver`",
+            slot.describe(&data.profs)
+        ),
+    )
+    .await;
+
+    ctx.say(format!(
+        "Rattrapage supprimé, {} élève(s) prévenu(s)",
+        slot.booked.len()
+    ))
+    .await?;
+    info!(
+        user_id = %ctx.author().id,
+        rattrapage = slot.id,
+        booked = slot.booked.len(),
+        "removed rattrapage"
+    );
+    Ok(())
+}
+
+/// Most slots shown by `/rattrapage reserver`, 5 rows of 5 buttons
+const RATTRAPAGE_BUTTONS: usize = 25;
+
+/// Réserve un rattrapage pour une de tes colles manquées
+#[poise::command(
+    slash_command,
+    guild_only,
+    category = "Consultation",
+    rename = "reserver"
+)]
+pub async fn rattrapage_reserver(
+    ctx: Context<'_>,
+    #[description = "Classe, par défaut celle de ton rôle"]
+    #[rename = "classe"]
+    #[autocomplete = "autocomplete_class"]
+    class: Option<String>,
+) -> Result<()> {
+    ctx.defer_ephemeral().await?;
+    let data = GuildData::from_ctx(ctx)?;
+    let class = data.class_for_ctx(ctx, class.as_deref()).await?;
+    let user_id = ctx.author().id;
+
+    let subjects = Absences::read_or_default(data.guild_id)?
+        .pending_for(class, user_id)
+        .iter()
+        .filter_map(|absence| absence.subject())
+        .collect::<Vec<_>>();
+    if subjects.is_empty() {
+        Err(WattouError::NoMissedColle)?
+    }
+    let slots = Rattrapages::read_or_default(data.guild_id)?
+        .available_for(class, &subjects, user_id)
+        .into_iter()
+        .take(RATTRAPAGE_BUTTONS)
+        .cloned()
+        .collect::<Vec<_>>();
+    if slots.is_empty() {
+        ctx.say("Aucun créneau de rattrapage disponible pour l'instant, réessaie plus tard")
+            .await?;
+        return Ok(());
+    }
+
+    let prefix = format!("{}-rattrapage-", ctx.id());
+    let handle = ctx
+        .send(
+            CreateReply::default()
+                .ephemeral(true)
+                .content(format!(
+                    "Choisis un créneau :\n- {}",
+                    slots
+                        .iter()
+                        .map(|slot| slot.describe(&data.profs))
+                        .collect::<Vec<_>>()
+                        .join("\n- ")
+                ))
+                .components(
+                    slots
+                        .chunks(5)
+                        .map(|row| {
+                            CreateActionRow::Buttons(
+                                row.iter()
+                                    .map(|slot| {
+                                        CreateButton::new(format!("{prefix}{}", slot.id))
+                                            .label(format!("n°{}", slot.id))
+                                            .style(ButtonStyle::Primary)
+                                    })
+                                    .collect(),
+                            )
+                        })
+                        .collect(),
+                ),
+        )
+        .await?;
+
+    let filter_prefix = prefix.clone();
+    let interaction = ComponentInteractionCollector::new(ctx.serenity_context())
+        .author_id(user_id)
+        .channel_id(ctx.channel_id())
+        .timeout(std::time::Duration::from_secs(120))
+        .filter(move |interaction| interaction.data.custom_id.starts_with(&filter_prefix))
+        .await;
+    handle
+        .edit(ctx, CreateReply::default().components(vec![]))
+        .await?;
+    let Some(interaction) = interaction else {
+        return Ok(());
+    };
+    interaction
+        .create_response(ctx, CreateInteractionResponse::Acknowledge)
+        .await?;
+    let slot_id = interaction
+        .data
+        .custom_id
+        .strip_prefix(&prefix)
+        .and_then(|id| id.parse::<u32>().ok())
+        .ok_or(WattouError::RattrapageNotFound)?;
+
+    // The absence may have been made up since the buttons were shown
+    let subject = Rattrapages::read_or_default(data.guild_id)?
+        .get(slot_id)?
+        .subject;
+    if !Absences::read_or_default(data.guild_id)?
+        .pending_for(class, user_id)
+        .iter()
+        .any(|absence| absence.subject() == Some(subject))
+    {
+        Err(WattouError::NoMissedColle)?
+    }
+
+    let slot = Rattrapages::update(data.guild_id, |rattrapages| {
+        rattrapages.book(slot_id, user_id)
+    })?;
+    let assigned = Absences::update(data.guild_id, |absences| {
+        Ok(absences.assign(class, user_id, slot.subject, slot.id))
+    })?;
+    if !assigned {
+        Rattrapages::update(data.guild_id, |rattrapages| {
+            rattrapages.cancel_booking(slot.id, user_id)
+        })?;
+        Err(WattouError::NoMissedColle)?
+    }
+
+    let description = slot.describe(&data.profs);
+    let prof = data
+        .profs
+        .get(slot.prof.as_str())
+        .and_then(|prof| ProfLinks::read_or_default(data.guild_id).ok()?.get(prof));
+    roster::send_dms(
+        ctx.http(),
+        &prof.into_iter().collect::<Vec<_>>(),
+        &format!(
+            "📅 {} a réservé le rattrapage {description}",
+            Mention::from(user_id)
+        ),
+    )
+    .await;
+
+    ctx.say(format!("Rattrapage réservé : {description}"))
+        .await?;
+    info!(
+        user_id = %user_id,
+        class = %class.name,
+        rattrapage = slot.id,
+        "booked rattrapage"
+    );
+    Ok(())
 }
 
-fn easy_comp_string(s: &str) -> String {
-    s.chars()
-        .filter(|c| c.is_alphanumeric())
-        .collect::<String>()
-        .to_lowercase()
+/// Annule ta réservation d'un rattrapage, la colle manquée est de nouveau à rattraper
+#[poise::command(
+    slash_command,
+    guild_only,
+    category = "Consultation",
+    rename = "annuler"
+)]
+pub async fn rattrapage_annuler(
+    ctx: Context<'_>,
+    #[description = "Numéro du rattrapage"]
+    #[rename = "numero"]
+    #[autocomplete = "autocomplete_booked_rattrapage"]
+    id: u32,
+) -> Result<()> {
+    ctx.defer_ephemeral().await?;
+    let data = GuildData::from_ctx(ctx)?;
+    let user_id = ctx.author().id;
+
+    let slot = Rattrapages::update(data.guild_id, |rattrapages| {
+        rattrapages.cancel_booking(id, user_id)
+    })?;
+    Absences::update(data.guild_id, |absences| {
+        absences.unassign(slot.id, Some(user_id));
+        Ok(())
+    })?;
+
+    ctx.say(format!(
+        "Réservation annulée : {}",
+        slot.describe(&data.profs)
+    ))
+    .await?;
+    info!(user_id = %user_id, rattrapage = slot.id, "cancelled rattrapage booking");
+    Ok(())
+}
+
+/// Carnet privé des notes de colle, visible de toi seul
+#[poise::command(
+    slash_command,
+    guild_only,
+    category = "Consultation",
+    subcommands(
+        "mes_notes_activer",
+        "mes_notes_desactiver",
+        "mes_notes_noter",
+        "mes_notes_voir",
+        "mes_notes_exporter"
+    )
+)]
+pub async fn mes_notes(_ctx: Context<'_>) -> Result<()> {
+    Ok(())
+}
+
+/// Le bot te demande en message privé la note de chaque colle une fois terminée
+#[poise::command(
+    slash_command,
+    guild_only,
+    category = "Consultation",
+    rename = "activer"
+)]
+pub async fn mes_notes_activer(
+    ctx: Context<'_>,
+    #[description = "Groupe de colle, par défaut le tien"]
+    #[rename = "groupe"]
+    #[autocomplete = "autocomplete_group"]
+    group_id: Option<String>,
+    #[description = "Classe, par défaut celle de ton rôle"]
+    #[rename = "classe"]
+    #[autocomplete = "autocomplete_class"]
+    class: Option<String>,
+) -> Result<()> {
+    ctx.defer_ephemeral().await?;
+    let data = GuildData::from_ctx(ctx)?;
+    let class = data.class_for_ctx(ctx, class.as_deref()).await?;
+    let group = data.group_for_ctx(ctx, class, group_id.as_deref()).await?;
+
+    Journals::update(data.guild_id, |journals| {
+        journals.enable(ctx.author().id, class, group);
+        Ok(())
+    })?;
+
+    ctx.say(format!(
+        "Carnet de notes activé pour le groupe {}{}, je te demanderai ta note après chaque colle",
+        group,
+        class_suffix(&data, class)
+    ))
+    .await?;
+    info!(user_id = %ctx.author().id, class = %class.name, group = %group.id, "enabled journal");
+    Ok(())
+}
+
+/// Arrête les demandes de notes, tes notes sont gardées sauf si tu les effaces
+#[poise::command(
+    slash_command,
+    guild_only,
+    category = "Consultation",
+    rename = "desactiver"
+)]
+pub async fn mes_notes_desactiver(
+    ctx: Context<'_>,
+    #[description = "Effacer aussi toutes tes notes"]
+    #[rename = "effacer"]
+    erase: Option<bool>,
+) -> Result<()> {
+    ctx.defer_ephemeral().await?;
+    let data = GuildData::from_ctx(ctx)?;
+    let erase = erase.unwrap_or(false);
+
+    let had_journal = Journals::update(data.guild_id, |journals| {
+        Ok(journals.disable(ctx.author().id, erase))
+    })?;
+
+    ctx.say(match (had_journal, erase) {
+        (false, _) => "Tu n'avais pas de carnet de notes",
+        (true, false) => "Carnet de notes désactivé, tes notes sont gardées",
+        (true, true) => "Carnet de notes désactivé et notes effacées",
+    })
+    .await?;
+    info!(user_id = %ctx.author().id, erase, "disabled journal");
+    Ok(())
+}
+
+/// Note une colle terminée, seul toi verras la note
+#[poise::command(slash_command, guild_only, category = "Consultation", rename = "noter")]
+pub async fn mes_notes_noter(
+    ctx: Context<'_>,
+    #[description = "Colle terminée"]
+    #[autocomplete = "autocomplete_recent_colle"]
+    colle: String,
+    #[description = "Note sur 20"]
+    #[min = 0]
+    #[max = 20]
+    note: f32,
+    #[description = "Remarques, par exemple les points à retravailler"]
+    #[max_length = 500]
+    remarques: Option<String>,
+    #[description = "Groupe de colle, par défaut le tien"]
+    #[rename = "groupe"]
+    #[autocomplete = "autocomplete_group"]
+    group_id: Option<String>,
+    #[description = "Classe, par défaut celle de ton rôle"]
+    #[rename = "classe"]
+    #[autocomplete = "autocomplete_class"]
+    class: Option<String>,
+) -> Result<()> {
+    ctx.defer_ephemeral().await?;
+    let data = GuildData::from_ctx(ctx)?;
+    let class = data.class_for_ctx(ctx, class.as_deref()).await?;
+    let group = journal_group(ctx, &data, class, group_id.as_deref()).await?;
+    let indexed = journal::recent_colles(class, group)
+        .into_iter()
+        .find(|indexed| indexed.key() == colle)
+        .ok_or(WattouError::ColleNotFound)?;
+
+    let entry = JournalEntry::new(indexed, note, remarques);
+    let description = entry.colle.clone();
+    Journals::update(data.guild_id, |journals| {
+        journals.record(ctx.author().id, class, group, entry);
+        Ok(())
+    })?;
+
+    ctx.say(format!(
+        "Note de {note}/20 enregistrée pour la colle {description}"
+    ))
+    .await?;
+    info!(user_id = %ctx.author().id, class = %class.name, "recorded journal grade");
+    Ok(())
+}
+
+/// Tes moyennes par matière, par colleur et leur évolution
+#[poise::command(slash_command, guild_only, category = "Consultation", rename = "voir")]
+pub async fn mes_notes_voir(ctx: Context<'_>) -> Result<()> {
+    ctx.defer_ephemeral().await?;
+    let data = GuildData::from_ctx(ctx)?;
+    let journals = Journals::read_or_default(data.guild_id)?;
+    let journal = journals
+        .get(ctx.author().id)
+        .ok_or(WattouError::NoJournal)?;
+
+    ctx.send(
+        CreateReply::default()
+            .ephemeral(true)
+            .content(journal.summary()),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Toutes tes notes dans un fichier CSV
+#[poise::command(
+    slash_command,
+    guild_only,
+    category = "Consultation",
+    rename = "exporter"
+)]
+pub async fn mes_notes_exporter(ctx: Context<'_>) -> Result<()> {
+    ctx.defer_ephemeral().await?;
+    let data = GuildData::from_ctx(ctx)?;
+    let journals = Journals::read_or_default(data.guild_id)?;
+    let journal = journals
+        .get(ctx.author().id)
+        .ok_or(WattouError::NoJournal)?;
+
+    ctx.send(
+        CreateReply::default()
+            .ephemeral(true)
+            .attachment(CreateAttachment::bytes(
+                journal.to_csv(),
+                "notes de colle.csv",
+            ))
+            .content(format!("{} note(s) exportée(s)", journal.entries.len())),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Programmes de colle de chaque semaine
+#[poise::command(
+    slash_command,
+    guild_only,
+    subcommands(
+        "programme_voir",
+        "programme_publier",
+        "programme_supprimer",
+        "programme_salon"
+    )
+)]
+pub async fn programme(_ctx: Context<'_>) -> Result<()> {
+    Ok(())
+}
+
+/// Programmes d'une semaine, avec leurs fichiers
+#[poise::command(slash_command, guild_only, category = "Consultation", rename = "voir")]
+pub async fn programme_voir(
+    ctx: Context<'_>,
+    #[description = "Semaine : date, numéro ou nom du fichier weeks, par défaut celle en cours"]
+    #[rename = "semaine"]
+    week: Option<String>,
+    #[description = "Matière, par défaut toutes"]
+    #[rename = "matiere"]
+    subject: Option<String>,
+    #[description = "Classe, par défaut celle de ton rôle"]
+    #[rename = "classe"]
+    #[autocomplete = "autocomplete_class"]
+    class: Option<String>,
+) -> Result<()> {
+    ctx.defer_ephemeral().await?;
+    let data = GuildData::from_ctx(ctx)?;
+    let class = data.class_for_ctx(ctx, class.as_deref()).await?;
+    let monday = programme::parse_week(class, week.as_deref())?;
+    let subject = subject
+        .map(|subject| ColleId::parse_subject(&subject).ok_or(WattouError::UnknownSubject(subject)))
+        .transpose()?;
+
+    let programmes = Programmes::read_or_default(data.guild_id)?;
+    let programmes = programmes
+        .of_week(class, monday)
+        .into_iter()
+        .filter(|programme| subject.is_none_or(|subject| programme.subject == subject))
+        .collect::<Vec<_>>();
+    if programmes.is_empty() {
+        ctx.say(format!(
+            "Aucun programme publié pour la semaine du {}{}",
+            monday.format(DATE_FORMAT)?,
+            class_suffix(&data, class)
+        ))
+        .await?;
+        return Ok(());
+    }
+
+    for programme in programmes {
+        let mut reply = CreateReply::default()
+            .ephemeral(true)
+            .content(programme.message(&class_suffix(&data, class)));
+        for attachment in programme.attachments(data.guild_id).await {
+            reply = reply.attachment(attachment);
+        }
+        ctx.send(reply).await?;
+    }
+    Ok(())
+}
+
+/// Publie le programme d'une matière pour une semaine, remplace le précédent
+#[poise::command(
+    slash_command,
+    guild_only,
+    category = "Enseignement",
+    rename = "publier"
+)]
+#[allow(clippy::too_many_arguments)]
+pub async fn programme_publier(
+    ctx: Context<'_>,
+    #[description = "Matière, par exemple M ou Maths"]
+    #[rename = "matiere"]
+    subject: String,
+    #[description = "Chapitres, démonstrations à connaître…"]
+    #[rename = "texte"]
+    #[max_length = 1500]
+    text: String,
+    #[description = "Semaine : date, numéro ou nom du fichier weeks, par défaut celle en cours"]
+    #[rename = "semaine"]
+    week: Option<String>,
+    #[description = "Fichier joint"]
+    #[rename = "fichier"]
+    file: Option<Attachment>,
+    #[description = "Deuxième fichier joint"]
+    #[rename = "fichier2"]
+    file_2: Option<Attachment>,
+    #[description = "Troisième fichier joint"]
+    #[rename = "fichier3"]
+    file_3: Option<Attachment>,
+    #[description = "Classe, par défaut celle de ton rôle"]
+    #[rename = "classe"]
+    #[autocomplete = "autocomplete_class"]
+    class: Option<String>,
+) -> Result<()> {
+    ctx.defer_ephemeral().await?;
+    let data = GuildData::from_ctx(ctx)?;
+    let class = data.class_for_ctx(ctx, class.as_deref()).await?;
+    let monday = programme::parse_week(class, week.as_deref())?;
+    let subject = ColleId::parse_subject(&subject).ok_or(WattouError::UnknownSubject(subject))?;
+
+    let mut files = vec![];
+    for file in [file, file_2, file_3].into_iter().flatten() {
+        files.push((file.filename.clone(), file.download().await?));
+    }
+    let mut programme = Programme::new(class, monday, subject, text, ctx.author().id);
+    // The files of the replaced programme are in the same folder
+    programme.replace_files(data.guild_id, &files)?;
+    Programmes::update(data.guild_id, |programmes| {
+        Ok(programmes.set(programme.clone()))
+    })?;
+
+    let posted_soon = programme::monday_of(OffsetDateTime::now_utc().date()) == monday
+        && ProgrammeChannel::read(data.guild_id).is_some();
+    ctx.say(format!(
+        "{} publié{}{}",
+        programme.title(),
+        class_suffix(&data, class),
+        if posted_soon {
+            ", il sera envoyé dans le salon des programmes à la prochaine actualisation"
+        } else {
+            ""
+        }
+    ))
+    .await?;
+    info!(
+        user_id = %ctx.author().id,
+        class = %class.name,
+        subject = %subject,
+        files = programme.files.len(),
+        "published programme"
+    );
+    Ok(())
+}
+
+/// Supprime le programme d'une matière pour une semaine
+#[poise::command(
+    slash_command,
+    guild_only,
+    category = "Enseignement",
+    rename = "supprimer"
+)]
+pub async fn programme_supprimer(
+    ctx: Context<'_>,
+    #[description = "Matière, par exemple M ou Maths"]
+    #[rename = "matiere"]
+    subject: String,
+    #[description = "Semaine : date, numéro ou nom du fichier weeks, par défaut celle en cours"]
+    #[rename = "semaine"]
+    week: Option<String>,
+    #[description = "Classe, par défaut celle de ton rôle"]
+    #[rename = "classe"]
+    #[autocomplete = "autocomplete_class"]
+    class: Option<String>,
+) -> Result<()> {
+    ctx.defer_ephemeral().await?;
+    let data = GuildData::from_ctx(ctx)?;
+    let class = data.class_for_ctx(ctx, class.as_deref()).await?;
+    let monday = programme::parse_week(class, week.as_deref())?;
+    let subject = ColleId::parse_subject(&subject).ok_or(WattouError::UnknownSubject(subject))?;
+
+    let programme = Programmes::update(data.guild_id, |programmes| {
+        programmes.remove(class, monday, subject)
+    })?;
+    programme.remove_files(data.guild_id);
+
+    ctx.say(format!(
+        "{} supprimé{}",
+        programme.title(),
+        class_suffix(&data, class)
+    ))
+    .await?;
+    info!(
+        user_id = %ctx.author().id,
+        class = %class.name,
+        subject = %subject,
+        "removed programme"
+    );
+    Ok(())
+}
+
+/// Envoie les programmes dans ce salon au début de chaque semaine
+#[poise::command(
+    slash_command,
+    guild_only,
+    category = "Administration",
+    rename = "salon",
+    required_permissions = "MANAGE_GUILD"
+)]
+pub async fn programme_salon(ctx: Context<'_>) -> Result<()> {
+    ctx.defer_ephemeral().await?;
+    let guild_id = ctx
+        .guild_id()
+        .ok_or(WattouError::CommandCanOnlyBeUsedInGuilds)?;
+
+    ProgrammeChannel(ctx.channel_id()).save(guild_id)?;
+    ctx.say("Les programmes seront désormais envoyés dans ce salon au début de chaque semaine")
+        .await?;
+    info!(
+        guild_id = %guild_id,
+        channel_id = %ctx.channel_id(),
+        "new programme channel"
+    );
+    Ok(())
+}
+
+/// Banque des exercices donnés en colle
+#[poise::command(
+    slash_command,
+    guild_only,
+    category = "Consultation",
+    subcommands(
+        "exercice_partager",
+        "exercice_chercher",
+        "exercice_voir",
+        "exercice_supprimer",
+        "exercice_retirer"
+    )
+)]
+pub async fn exercice(_ctx: Context<'_>) -> Result<()> {
+    Ok(())
+}
+
+/// Partage un exercice donné lors d'une de tes colles passées
+#[poise::command(
+    slash_command,
+    guild_only,
+    category = "Consultation",
+    rename = "partager"
+)]
+#[allow(clippy::too_many_arguments)]
+pub async fn exercice_partager(
+    ctx: Context<'_>,
+    #[description = "Colle terminée"]
+    #[autocomplete = "autocomplete_shareable_colle"]
+    colle: String,
+    #[description = "Énoncé de l'exercice"]
+    #[rename = "enonce"]
+    #[max_length = 1500]
+    text: String,
+    #[description = "Chapitre, par défaut celui du programme de la semaine"]
+    #[rename = "chapitre"]
+    chapter: Option<String>,
+    #[description = "Photo ou fichier de l'énoncé"]
+    #[rename = "fichier"]
+    file: Option<Attachment>,
+    #[description = "Groupe de colle, par défaut le tien"]
+    #[rename = "groupe"]
+    #[autocomplete = "autocomplete_group"]
+    group_id: Option<String>,
+    #[description = "Classe, par défaut celle de ton rôle"]
+    #[rename = "classe"]
+    #[autocomplete = "autocomplete_class"]
+    class: Option<String>,
+) -> Result<()> {
+    ctx.defer_ephemeral().await?;
+    let data = GuildData::from_ctx(ctx)?;
+    let class = data.class_for_ctx(ctx, class.as_deref()).await?;
+    let group = data.group_for_ctx(ctx, class, group_id.as_deref()).await?;
+    let indexed = exercise::shareable_colles(class, group)
+        .into_iter()
+        .find(|indexed| indexed.key() == colle)
+        .ok_or(WattouError::ColleNotFound)?;
+
+    let programmes = Programmes::read_or_default(data.guild_id)?;
+    let mut exercise = Exercise::new(class, indexed, &programmes, chapter, text, ctx.author().id);
+    exercise.id = Exercises::update(data.guild_id, |exercises| Ok(exercises.next_id()))?;
+    if let Some(file) = file {
+        exercise.file = Some(utils::save_file(
+            &exercise.folder(data.guild_id),
+            &file.filename,
+            &file.download().await?,
+        )?);
+    }
+    Exercises::update(data.guild_id, |exercises| {
+        exercises.add(exercise.clone());
+        Ok(())
+    })?;
+
+    ctx.say(format!("Merci ! Exercice partagé : {}", exercise.title()))
+        .await?;
+    info!(
+        user_id = %ctx.author().id,
+        class = %class.name,
+        exercise = exercise.id,
+        "shared exercise"
+    );
+    Ok(())
+}
+
+/// Exercices déjà donnés, par colleur, matière ou chapitre
+#[poise::command(
+    slash_command,
+    guild_only,
+    category = "Consultation",
+    rename = "chercher"
+)]
+pub async fn exercice_chercher(
+    ctx: Context<'_>,
+    #[description = "Colleur"]
+    #[rename = "colleur"]
+    #[autocomplete = "autocomplete_prof"]
+    prof: Option<String>,
+    #[description = "Matière, par exemple M ou Maths"]
+    #[rename = "matiere"]
+    subject: Option<String>,
+    #[description = "Chapitre ou mot de l'énoncé"]
+    #[rename = "chapitre"]
+    chapter: Option<String>,
+    #[description = "Classe, par défaut celle de ton rôle"]
+    #[rename = "classe"]
+    #[autocomplete = "autocomplete_class"]
+    class: Option<String>,
+) -> Result<()> {
+    ctx.defer_ephemeral().await?;
+    let data = GuildData::from_ctx(ctx)?;
+    let class = data.class_for_ctx(ctx, class.as_deref()).await?;
+    if let Some(prof) = &prof
+        && !data.profs.contains_key(prof.as_str())
+    {
+        Err(WattouError::ProfNotFound(prof.clone()))?
+    }
+    let subject = subject
+        .map(|subject| ColleId::parse_subject(&subject).ok_or(WattouError::UnknownSubject(subject)))
+        .transpose()?;
+
+    let exercises = Exercises::read_or_default(data.guild_id)?;
+    let found = exercises.search(
+        class,
+        &ExerciseFilter {
+            prof: prof.as_deref(),
+            subject,
+            chapter: chapter.as_deref(),
+        },
+    );
+    let content = if found.is_empty() {
+        "Aucun exercice trouvé, partage les tiens avec `/exercice partager` !".to_string()
+    } else {
+        format!(
+            "{} exercice(s){}, `/exercice voir` affiche l'énoncé complet :\n- {}",
+            found.len(),
+            class_suffix(&data, class),
+            found
+                .iter()
+                .take(EXERCISES_SHOWN)
+                .map(|exercise| exercise.summary())
+                .collect::<Vec<_>>()
+                .join("\n- ")
+        )
+    };
+
+    ctx.say(content).await?;
+    Ok(())
+}
+
+/// Most exercises listed by `/exercice chercher`, to fit in a message
+const EXERCISES_SHOWN: usize = 8;
+
+/// Énoncé complet d'un exercice
+#[poise::command(slash_command, guild_only, category = "Consultation", rename = "voir")]
+pub async fn exercice_voir(
+    ctx: Context<'_>,
+    #[description = "Numéro de l'exercice"]
+    #[rename = "numero"]
+    id: u32,
+) -> Result<()> {
+    ctx.defer_ephemeral().await?;
+    let data = GuildData::from_ctx(ctx)?;
+    let exercises = Exercises::read_or_default(data.guild_id)?;
+    let exercise = exercises.get(id)?;
+
+    let mut reply = CreateReply::default()
+        .ephemeral(true)
+        .content(exercise.message());
+    if let Some(attachment) = exercise.attachment(data.guild_id).await {
+        reply = reply.attachment(attachment);
+    }
+    ctx.send(reply).await?;
+    Ok(())
+}
+
+/// Supprime un exercice que tu as partagé
+#[poise::command(
+    slash_command,
+    guild_only,
+    category = "Consultation",
+    rename = "supprimer"
+)]
+pub async fn exercice_supprimer(
+    ctx: Context<'_>,
+    #[description = "Numéro de l'exercice"]
+    #[rename = "numero"]
+    id: u32,
+) -> Result<()> {
+    ctx.defer_ephemeral().await?;
+    let data = GuildData::from_ctx(ctx)?;
+
+    let exercise = Exercises::update(data.guild_id, |exercises| {
+        exercises.remove(id, Some(ctx.author().id))
+    })?;
+    exercise.remove_file(data.guild_id);
+
+    ctx.say(format!("Exercice supprimé : {}", exercise.title()))
+        .await?;
+    info!(user_id = %ctx.author().id, exercise = exercise.id, "removed exercise");
+    Ok(())
+}
+
+/// Retire un exercice partagé par n'importe qui, par exemple un énoncé inapproprié
+#[poise::command(slash_command, guild_only, category = "Modération", rename = "retirer")]
+pub async fn exercice_retirer(
+    ctx: Context<'_>,
+    #[description = "Numéro de l'exercice"]
+    #[rename = "numero"]
+    id: u32,
+) -> Result<()> {
+    ctx.defer_ephemeral().await?;
+    let data = GuildData::from_ctx(ctx)?;
+
+    let exercise = Exercises::update(data.guild_id, |exercises| exercises.remove(id, None))?;
+    exercise.remove_file(data.guild_id);
+
+    ctx.say(format!(
+        "Exercice retiré : {}, partagé par {}",
+        exercise.title(),
+        Mention::from(exercise.author)
+    ))
+    .await?;
+    info!(
+        user_id = %ctx.author().id,
+        author = %exercise.author,
+        exercise = exercise.id,
+        "moderator removed exercise"
+    );
+    Ok(())
+}
+
+/// Lien entre les colleurs et leur compte Discord
+#[poise::command(
+    slash_command,
+    guild_only,
+    category = "Administration",
+    subcommands("colleur_lier", "colleur_delier")
+)]
+pub async fn colleur(_ctx: Context<'_>) -> Result<()> {
+    Ok(())
+}
+
+/// Indique le compte Discord d'un colleur, qui sera prévenu des absences
+#[poise::command(
+    slash_command,
+    guild_only,
+    category = "Administration",
+    rename = "lier"
+)]
+pub async fn colleur_lier(
+    ctx: Context<'_>,
+    #[autocomplete = "autocomplete_prof"]
+    #[rename = "prof"]
+    prof_str: String,
+    #[description = "Compte Discord du colleur"]
+    #[rename = "membre"]
+    member: User,
+) -> Result<()> {
+    edit_prof_link(ctx, prof_str, Some(member.id)).await
+}
+
+/// Le colleur ne reçoit plus de messages du bot
+#[poise::command(
+    slash_command,
+    guild_only,
+    category = "Administration",
+    rename = "delier"
+)]
+pub async fn colleur_delier(
+    ctx: Context<'_>,
+    #[autocomplete = "autocomplete_prof"]
+    #[rename = "prof"]
+    prof_str: String,
+) -> Result<()> {
+    edit_prof_link(ctx, prof_str, None).await
+}
+
+async fn edit_prof_link(ctx: Context<'_>, prof_str: String, user_id: Option<UserId>) -> Result<()> {
+    ctx.defer_ephemeral().await?;
+    let data = GuildData::from_ctx(ctx)?;
+    let Some(prof) = data.profs.get(prof_str.as_str()).cloned() else {
+        Err(WattouError::ProfNotFound(prof_str))?
+    };
+
+    ProfLinks::update(data.guild_id, |links| {
+        links.set(&prof, user_id);
+        Ok(())
+    })?;
+
+    ctx.send(
+        CreateReply::default()
+            .ephemeral(true)
+            .content(match user_id {
+                Some(user_id) => format!("{} est lié à {}", prof, Mention::from(user_id)),
+                None => format!("{prof} n'est plus lié à aucun compte"),
+            })
+            .allowed_mentions(CreateAllowedMentions::new()),
+    )
+    .await?;
+    info!(
+        user_id = %ctx.author().id,
+        prof = prof.name(),
+        linked = ?user_id,
+        "edited colleur link"
+    );
+    Ok(())
+}
+
+/// Groupes sans élèves, dont les colles ne comptent plus
+#[poise::command(
+    slash_command,
+    guild_only,
+    category = "Administration",
+    subcommands("groupe_fantome_marquer", "groupe_fantome_retirer")
+)]
+pub async fn groupe_fantome(_ctx: Context<'_>) -> Result<()> {
+    Ok(())
+}
+
+/// Marque un groupe comme fantôme, ses colles peuvent devenir des places libres
+#[poise::command(
+    slash_command,
+    guild_only,
+    category = "Administration",
+    rename = "marquer"
+)]
+pub async fn groupe_fantome_marquer(
+    ctx: Context<'_>,
+    #[description = "Groupe de colle"]
+    #[rename = "groupe"]
+    #[autocomplete = "autocomplete_group"]
+    group_id: String,
+    #[description = "Premier jour sans élèves, par exemple 13-1-2025, par défaut toute la période"]
+    #[rename = "depuis"]
+    since: Option<String>,
+    #[description = "Les élèves des autres groupes peuvent prendre ses colles"]
+    #[rename = "places_libres"]
+    open_places: Option<bool>,
+    #[description = "Classe, par défaut celle de ton rôle"]
+    #[rename = "classe"]
+    #[autocomplete = "autocomplete_class"]
+    class: Option<String>,
+) -> Result<()> {
+    ctx.defer_ephemeral().await?;
+    let data = GuildData::from_ctx(ctx)?;
+    let class = data.class_for_ctx(ctx, class.as_deref()).await?;
+    let group = class.get_group(&group_id)?;
+    let since = since
+        .map(|date| Date::parse(&date, DATE_FORMAT).map_err(|_| WattouError::InvalidDate(date)))
+        .transpose()?;
+
+    let period = since.map_or(class.active_period(), |since| class.period_at(since));
+    if !period.groups.iter().any(|g| g.id == group.id) {
+        Err(WattouError::NoGroupInClass(
+            data.guild_id,
+            class.name.to_string(),
+            group.id.to_string(),
+        ))?
+    }
+    let ghost = Ghost {
+        group_id: group.id.clone(),
+        since,
+        open_places: open_places.unwrap_or(false),
+    };
+    Ghost::write(&period.folder, &group.id, Some(&ghost))?;
+
+    let (_, changes) = reload_and_announce(ctx, data.guild_id).await?;
+
+    ctx.say(format!(
+        "Le groupe {}{} est fantôme {}{}{changes}",
+        group,
+        class_suffix(&data, class),
+        match since {
+            Some(since) => format!("à partir du {}", since.format(DATE_FORMAT)?),
+            None => format!("pour la période {}", period.name),
+        },
+        if ghost.open_places {
+            ", ses colles sont des places libres à prendre avec `/places_libres prendre`"
+        } else {
+            ""
+        }
+    ))
+    .await?;
+    info!(
+        user_id = %ctx.author().id,
+        class = %class.name,
+        period = %period.name,
+        line = ghost.to_line(),
+        "marked group as ghost"
+    );
+    Ok(())
+}
+
+/// Le groupe a de nouveau des élèves
+#[poise::command(
+    slash_command,
+    guild_only,
+    category = "Administration",
+    rename = "retirer"
+)]
+pub async fn groupe_fantome_retirer(
+    ctx: Context<'_>,
+    #[description = "Groupe de colle"]
+    #[rename = "groupe"]
+    #[autocomplete = "autocomplete_group"]
+    group_id: String,
+    #[description = "Classe, par défaut celle de ton rôle"]
+    #[rename = "classe"]
+    #[autocomplete = "autocomplete_class"]
+    class: Option<String>,
+) -> Result<()> {
+    ctx.defer_ephemeral().await?;
+    let data = GuildData::from_ctx(ctx)?;
+    let class = data.class_for_ctx(ctx, class.as_deref()).await?;
+    let group = class.get_group(&group_id)?;
+
+    let mut periods = 0;
+    for period in class.periods.iter() {
+        if period.ghosts.iter().any(|ghost| ghost.group_id == group.id) {
+            Ghost::write(&period.folder, &group.id, None)?;
+            periods += 1;
+        }
+    }
+    if periods == 0 {
+        ctx.say(format!("Le groupe {group} n'est pas fantôme"))
+            .await?;
+        return Ok(());
+    }
+
+    let (_, changes) = reload_and_announce(ctx, data.guild_id).await?;
+
+    ctx.say(format!(
+        "Le groupe {}{} n'est plus fantôme{changes}",
+        group,
+        class_suffix(&data, class)
+    ))
+    .await?;
+    info!(
+        user_id = %ctx.author().id,
+        class = %class.name,
+        group = %group.id,
+        "removed ghost group"
+    );
+    Ok(())
+}
+
+/// Colles des groupes fantômes que les élèves peuvent prendre
+#[poise::command(
+    slash_command,
+    guild_only,
+    category = "Consultation",
+    subcommands("places_libres_voir", "places_libres_prendre", "places_libres_liberer")
+)]
+pub async fn places_libres(_ctx: Context<'_>) -> Result<()> {
+    Ok(())
+}
+
+/// Liste les prochaines places libres
+#[poise::command(slash_command, guild_only, category = "Consultation", rename = "voir")]
+pub async fn places_libres_voir(
+    ctx: Context<'_>,
+    #[description = "Classe, par défaut celle de ton rôle"]
+    #[rename = "classe"]
+    #[autocomplete = "autocomplete_class"]
+    class: Option<String>,
+) -> Result<()> {
+    ctx.defer_ephemeral().await?;
+    let data = GuildData::from_ctx(ctx)?;
+    let class = data.class_for_ctx(ctx, class.as_deref()).await?;
+    let places = OpenPlaces::read_or_default(data.guild_id)?;
+
+    let open_places = class.open_places();
+    let content =
+        if open_places.is_empty() {
+            format!("Aucune place libre{}", class_suffix(&data, class))
+        } else {
+            format!(
+                "Places libres{} :\n- {}",
+                class_suffix(&data, class),
+                open_places
+                    .iter()
+                    .take(20)
+                    .map(|indexed| format!(
+                        "{}, {}",
+                        indexed.colle.format(ColleStringFormat::Explicit, vec![]),
+                        places.claimant(class, indexed).map_or(
+                            "libre".to_string(),
+                            |user_id| format!("prise par {}", Mention::from(user_id))
+                        )
+                    ))
+                    .collect::<Vec<_>>()
+                    .join("\n- ")
+            )
+        };
+
+    ctx.send(
+        CreateReply::default()
+            .ephemeral(true)
+            .content(content)
+            .allowed_mentions(CreateAllowedMentions::new()),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Prend une place libre, le colleur verra ton nom
+#[poise::command(
+    slash_command,
+    guild_only,
+    category = "Consultation",
+    rename = "prendre"
+)]
+pub async fn places_libres_prendre(
+    ctx: Context<'_>,
+    #[description = "Place libre"]
+    #[autocomplete = "autocomplete_open_place"]
+    place: String,
+    #[description = "Classe, par défaut celle de ton rôle"]
+    #[rename = "classe"]
+    #[autocomplete = "autocomplete_class"]
+    class: Option<String>,
+) -> Result<()> {
+    ctx.defer_ephemeral().await?;
+    let data = GuildData::from_ctx(ctx)?;
+    let class = data.class_for_ctx(ctx, class.as_deref()).await?;
+    let indexed = find_open_place(class, &place)?;
+
+    OpenPlaces::update(data.guild_id, |places| {
+        places.remove_past();
+        places.claim(class, indexed, ctx.author().id)
+    })?;
+
+    ctx.say(format!(
+        "Place prise : {}",
+        indexed.colle.format(ColleStringFormat::Explicit, vec![])
+    ))
+    .await?;
+    info!(
+        user_id = %ctx.author().id,
+        class = %class.name,
+        place,
+        "claimed open place"
+    );
+    Ok(())
+}
+
+/// Rend une place libre que tu avais prise
+#[poise::command(
+    slash_command,
+    guild_only,
+    category = "Consultation",
+    rename = "liberer"
+)]
+pub async fn places_libres_liberer(
+    ctx: Context<'_>,
+    #[description = "Place prise"]
+    #[autocomplete = "autocomplete_claimed_place"]
+    place: String,
+    #[description = "Classe, par défaut celle de ton rôle"]
+    #[rename = "classe"]
+    #[autocomplete = "autocomplete_class"]
+    class: Option<String>,
+) -> Result<()> {
+    ctx.defer_ephemeral().await?;
+    let data = GuildData::from_ctx(ctx)?;
+    let class = data.class_for_ctx(ctx, class.as_deref()).await?;
+    let indexed = find_open_place(class, &place)?;
+
+    OpenPlaces::update(data.guild_id, |places| {
+        places.release(class, indexed, ctx.author().id)
+    })?;
+
+    ctx.say(format!(
+        "Place rendue : {}",
+        indexed.colle.format(ColleStringFormat::Explicit, vec![])
+    ))
+    .await?;
+    info!(
+        user_id = %ctx.author().id,
+        class = %class.name,
+        place,
+        "released open place"
+    );
+    Ok(())
+}
+
+/// Upcoming place libre of the class with this [IndexedColle::key]
+fn find_open_place<'a>(class: &'a Class, key: &str) -> Result<&'a IndexedColle> {
+    Ok(class
+        .open_places()
+        .into_iter()
+        .find(|indexed| indexed.key() == key)
+        .ok_or(WattouError::PlaceNotFound)?)
+}
+
+/// Configuration du bot sur ce serveur
+#[poise::command(
+    slash_command,
+    guild_only,
+    category = "Administration",
+    subcommands("config_voir", "config_definir")
+)]
+pub async fn config(_ctx: Context<'_>) -> Result<()> {
+    Ok(())
+}
+
+/// Affiche la configuration effective du serveur
+#[poise::command(
+    slash_command,
+    guild_only,
+    category = "Administration",
+    rename = "voir"
+)]
+pub async fn config_voir(ctx: Context<'_>) -> Result<()> {
+    ctx.defer_ephemeral().await?;
+    let guild_id = ctx
+        .guild_id()
+        .ok_or(WattouError::CommandCanOnlyBeUsedInGuilds)?;
+
+    ctx.say(GuildConfig::read_or_default(guild_id)?.describe())
+        .await?;
+    Ok(())
+}
+
+/// Change un réglage pour ce serveur, sans valeur le réglage global est rétabli
+#[poise::command(
+    slash_command,
+    guild_only,
+    category = "Administration",
+    rename = "definir"
+)]
+pub async fn config_definir(
+    ctx: Context<'_>,
+    #[description = "Réglage"]
+    #[rename = "reglage"]
+    key: GuildConfigKey,
+    #[description = "Nouvelle valeur"]
+    #[rename = "valeur"]
+    value: Option<String>,
+) -> Result<()> {
+    ctx.defer_ephemeral().await?;
+    let guild_id = ctx
+        .guild_id()
+        .ok_or(WattouError::CommandCanOnlyBeUsedInGuilds)?;
+
+    let guild_config = GuildConfig::update(guild_id, |guild_config| {
+        guild_config
+            .set(key, value.as_deref())
+            .map_err(|e| WattouError::InvalidConfigValue(e.to_string()))?;
+        Ok(guild_config.clone())
+    })?;
+
+    ctx.say(guild_config.describe()).await?;
+    info!(guild_id = %guild_id, key = ?key, value, "edited guild config");
+    Ok(())
+}
+
+/// Fichiers `weeks` des classes
+#[poise::command(
+    slash_command,
+    guild_only,
+    category = "Administration",
+    subcommands("semaines_generer", "semaines_verifier")
+)]
+pub async fn semaines(_ctx: Context<'_>) -> Result<()> {
+    Ok(())
+}
+
+/// Génère un fichier weeks, un lundi par semaine hors vacances
+#[poise::command(
+    slash_command,
+    guild_only,
+    category = "Administration",
+    rename = "generer"
+)]
+pub async fn semaines_generer(
+    ctx: Context<'_>,
+    #[description = "Premier jour de colles, par exemple 2-9-2024"]
+    #[rename = "debut"]
+    first_day: String,
+    #[description = "Dernier jour de colles"]
+    #[rename = "fin"]
+    last_day: String,
+) -> Result<()> {
+    ctx.defer_ephemeral().await?;
+    let parse =
+        |date: String| Date::parse(&date, DATE_FORMAT).map_err(|_| WattouError::InvalidDate(date));
+    let (first_day, last_day) = (parse(first_day)?, parse(last_day)?);
+
+    let holidays = crate::config::config().calendar.holidays()?;
+    let weeks = Weeks::generate(first_day, last_day, &holidays);
+    let summary = match (weeks.0.first(), weeks.0.last()) {
+        (Some(first), Some(last)) => format!(
+            "{} semaines de colles, du {} au {}",
+            weeks.len(),
+            first.monday.format(DATE_FORMAT)?,
+            last.monday.format(DATE_FORMAT)?
+        ),
+        _ => "Aucune semaine de colles entre ces dates".to_string(),
+    };
+
+    ctx.send(
+        CreateReply::default()
+            .ephemeral(true)
+            .content(summary)
+            .attachment(CreateAttachment::bytes(
+                weeks.to_file(),
+                Class::FILE_NAME_WEEKS_INFO,
+            )),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Vérifie les fichiers weeks d'une classe : lundis, ordre des dates et semaines manquantes
+#[poise::command(
+    slash_command,
+    guild_only,
+    category = "Administration",
+    rename = "verifier"
+)]
+pub async fn semaines_verifier(
+    ctx: Context<'_>,
+    #[description = "Classe, par défaut celle de ton rôle"]
+    #[rename = "classe"]
+    #[autocomplete = "autocomplete_class"]
+    class: Option<String>,
+) -> Result<()> {
+    ctx.defer_ephemeral().await?;
+    let data = GuildData::from_ctx(ctx)?;
+    let class = data.class_for_ctx(ctx, class.as_deref()).await?;
+    let holidays = crate::config::config().calendar.holidays()?;
+
+    let mut content = format!("# Semaines{}", class_suffix(&data, class));
+    for period in class.periods.iter() {
+        if class.periods.len() > 1 {
+            content += &format!("\n## {}", period.name);
+        }
+        let issues = period.weeks.validate(&holidays);
+        if issues.is_empty() {
+            content += &format!("\n✅ {} semaines, aucun problème", period.weeks.len());
+        }
+        for issue in issues {
+            content += &format!("\n- ⚠️ {issue}");
+        }
+    }
+
+    ctx.say(content).await?;
+    Ok(())
+}
+
+/// Rapport d'équité du colloscope : colles, colleurs et écarts de chaque groupe
+#[poise::command(slash_command, guild_only, category = "Administration")]
+pub async fn equite(
+    ctx: Context<'_>,
+    #[description = "Période, par défaut celle en cours"]
+    #[rename = "periode"]
+    period: Option<String>,
+    #[description = "Classe, par défaut celle de ton rôle"]
+    #[rename = "classe"]
+    #[autocomplete = "autocomplete_class"]
+    class: Option<String>,
+) -> Result<()> {
+    ctx.defer_ephemeral().await?;
+    let data = GuildData::from_ctx(ctx)?;
+    let class = data.class_for_ctx(ctx, class.as_deref()).await?;
+    let period = match period {
+        Some(name) => class
+            .periods
+            .iter()
+            .find(|period| easy_comp_string(&period.name) == easy_comp_string(&name))
+            .ok_or(WattouError::PeriodNotFound(name))?,
+        None => class.active_period(),
+    };
+
+    let report = FairnessReport::new(period);
+    let title = format!("# Équité{}, {}", class_suffix(&data, class), period.name);
+    let content = format!("{title}\n{}", report.to_markdown());
+    // Discord messages are limited to 2000 characters, the details go in a file
+    let reply = if content.chars().count() <= 2000 {
+        CreateReply::default().content(content)
+    } else {
+        let mut summary = format!("{title}\n{}", report.outliers_markdown());
+        if summary.chars().count() > 2000 {
+            summary = format!(
+                "{title}\n{} point(s) d'attention, détaillés dans le fichier joint",
+                report.outliers.len()
+            );
+        }
+        CreateReply::default()
+            .content(summary)
+            .attachment(CreateAttachment::bytes(content, "equite.md"))
+    };
+
+    ctx.send(reply.ephemeral(true)).await?;
+    Ok(())
+}
+
+/// Génération du colloscope
+#[poise::command(
+    slash_command,
+    guild_only,
+    category = "Administration",
+    subcommands("colloscope_generer")
+)]
+pub async fn colloscope(_ctx: Context<'_>) -> Result<()> {
+    Ok(())
+}
+
+/// Propose un fichier colloscope à partir des colles et des semaines de la période
+#[poise::command(
+    slash_command,
+    guild_only,
+    category = "Administration",
+    rename = "generer"
+)]
+#[allow(clippy::too_many_arguments)]
+pub async fn colloscope_generer(
+    ctx: Context<'_>,
+    #[description = "Nombre de groupes, par défaut ceux du colloscope actuel"]
+    #[rename = "groupes"]
+    #[min = 1]
+    #[max = 60]
+    group_count: Option<usize>,
+    #[description = "Passages de chaque groupe chez chaque colleur, par défaut autant que les autres"]
+    #[rename = "passages"]
+    visits: Option<usize>,
+    #[description = "Cellules imposées, par exemple G1 3 M1+A2, G2 5 -"]
+    #[rename = "verrous"]
+    locks: Option<String>,
+    #[description = "Change le colloscope proposé"]
+    #[rename = "graine"]
+    seed: Option<u64>,
+    #[description = "Période, par défaut celle en cours"]
+    #[rename = "periode"]
+    period: Option<String>,
+    #[description = "Classe, par défaut celle de ton rôle"]
+    #[rename = "classe"]
+    #[autocomplete = "autocomplete_class"]
+    class: Option<String>,
+) -> Result<()> {
+    ctx.defer_ephemeral().await?;
+    let data = GuildData::from_ctx(ctx)?;
+    let class = data.class_for_ctx(ctx, class.as_deref()).await?;
+    let period = match period {
+        Some(name) => class
+            .periods
+            .iter()
+            .find(|period| easy_comp_string(&period.name) == easy_comp_string(&name))
+            .ok_or(WattouError::PeriodNotFound(name))?,
+        None => class.active_period(),
+    };
+
+    let colle_list = ColleData::parse_list(
+        &std::fs::read_to_string(period.folder.join(Class::FILE_NAME_COLLE_LIST))?,
+        &mut data.profs.clone(),
+    )?;
+    let generator = Generator::new(
+        &colle_list,
+        &period.weeks,
+        GeneratorGroup::of_period(period, group_count),
+        visits,
+    );
+    let locks = generator.parse_locks(locks.as_deref().unwrap_or_default(), &period.weeks)?;
+    let seed = seed.unwrap_or(1);
+    // The search takes a few seconds
+    let generated = tokio::task::spawn_blocking(move || generator.generate(&locks, seed)).await?;
+
+    let cost = &generated.cost;
+    let mut content = format!(
+        "# Colloscope proposé{}, {}\nScore d'équité : {:.0}/100",
+        class_suffix(&data, class),
+        period.name,
+        cost.fairness_score()
+    );
+    if cost.conflicts > 0 {
+        content += &format!(
+            "\n⚠️ {} conflit(s) : deux colles le même jour ou de la même matière la même semaine",
+            cost.conflicts
+        );
+    }
+    if cost.consecutive > 0 {
+        content += &format!(
+            "\n{} fois la même matière deux semaines de suite",
+            cost.consecutive
+        );
+    }
+    if cost.unassigned > 0 {
+        content += &format!("\n{} colle(s) sans groupe", cost.unassigned);
+    }
+    content += &format!(
+        "\nRelis-le, puis remplace le fichier `{}` de la période et utilise `/recharger`. Une autre `graine` donne un autre colloscope.",
+        Class::FILE_NAME_COLLOSCOPE
+    );
+
+    ctx.send(
+        CreateReply::default()
+            .ephemeral(true)
+            .content(content)
+            .attachment(CreateAttachment::bytes(
+                generated.colloscope,
+                Class::FILE_NAME_COLLOSCOPE,
+            )),
+    )
+    .await?;
+    info!(
+        class = %class.name,
+        period = %period.name,
+        seed,
+        conflicts = cost.conflicts,
+        "generated colloscope"
+    );
+    Ok(())
+}
+
+/// Définit ce salon comme celui où sont signalées les erreurs du bot
+#[poise::command(
+    slash_command,
+    guild_only,
+    category = "Administration",
+    required_permissions = "MANAGE_GUILD"
+)]
+pub async fn salon_admin(ctx: Context<'_>) -> Result<()> {
+    ctx.defer_ephemeral().await?;
+    let guild_id = ctx
+        .guild_id()
+        .ok_or(WattouError::CommandCanOnlyBeUsedInGuilds)?;
+
+    AdminChannel(ctx.channel_id()).save(guild_id)?;
+    ctx.say("Les erreurs seront désormais signalées dans ce salon")
+        .await?;
+    info!(
+        guild_id = %guild_id,
+        channel_id = %ctx.channel_id(),
+        "new admin channel"
+    );
+    Ok(())
+}
+
+/// Définit ce salon comme celui où sont annoncés les changements du colloscope
+#[poise::command(
+    slash_command,
+    guild_only,
+    category = "Administration",
+    required_permissions = "MANAGE_GUILD"
+)]
+pub async fn salon_annonces(ctx: Context<'_>) -> Result<()> {
+    ctx.defer_ephemeral().await?;
+    let guild_id = ctx
+        .guild_id()
+        .ok_or(WattouError::CommandCanOnlyBeUsedInGuilds)?;
+
+    AnnouncementChannel(ctx.channel_id()).save(guild_id)?;
+    ctx.say("Les changements du colloscope seront désormais annoncés dans ce salon")
+        .await?;
+    info!(
+        guild_id = %guild_id,
+        channel_id = %ctx.channel_id(),
+        "new announcement channel"
+    );
+    Ok(())
+}
+
+/// Qui peut utiliser quelles commandes
+#[poise::command(
+    slash_command,
+    guild_only,
+    category = "Administration",
+    subcommands(
+        "permissions_voir",
+        "permissions_autoriser_role",
+        "permissions_retirer_role",
+        "permissions_exiger",
+        "permissions_reinitialiser"
+    )
+)]
+pub async fn permissions(_ctx: Context<'_>) -> Result<()> {
+    Ok(())
+}
+
+/// Affiche qui peut utiliser chaque catégorie de commandes
+#[poise::command(
+    slash_command,
+    guild_only,
+    category = "Administration",
+    rename = "voir"
+)]
+pub async fn permissions_voir(ctx: Context<'_>) -> Result<()> {
+    ctx.defer_ephemeral().await?;
+    let guild_id = ctx
+        .guild_id()
+        .ok_or(WattouError::CommandCanOnlyBeUsedInGuilds)?;
+    let config = PermissionConfig::cached(guild_id)?;
+
+    let mut content = String::from("# Permissions");
+    for category in CommandCategory::ALL {
+        content += &format!(
+            "\n## {} : {}",
+            category.name(),
+            config.category_rule(category)
+        );
+        for command in all_commands(ctx)
+            .into_iter()
+            .filter(|command| CommandCategory::of(command) == category)
+        {
+            if let Some(rule) = config.commands.get(&command.qualified_name) {
+                content += &format!("\n- /{} : {}", command.qualified_name, rule);
+            }
+        }
+    }
+
+    ctx.send(
+        CreateReply::default()
+            .ephemeral(true)
+            .content(content)
+            .allowed_mentions(CreateAllowedMentions::new()),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Autorise un rôle à utiliser une catégorie de commandes ou une commande
+#[poise::command(
+    slash_command,
+    guild_only,
+    category = "Administration",
+    rename = "autoriser_role"
+)]
+pub async fn permissions_autoriser_role(
+    ctx: Context<'_>,
+    #[description = "Catégorie ou commande"]
+    #[autocomplete = "autocomplete_rule_target"]
+    cible: String,
+    role: Role,
+) -> Result<()> {
+    edit_rule(ctx, &cible, |rule| {
+        if !rule.roles.contains(&role.id) {
+            rule.roles.push(role.id);
+        }
+    })
+    .await
+}
+
+/// Retire un rôle des rôles autorisés
+#[poise::command(
+    slash_command,
+    guild_only,
+    category = "Administration",
+    rename = "retirer_role"
+)]
+pub async fn permissions_retirer_role(
+    ctx: Context<'_>,
+    #[description = "Catégorie ou commande"]
+    #[autocomplete = "autocomplete_rule_target"]
+    cible: String,
+    role: Role,
+) -> Result<()> {
+    edit_rule(ctx, &cible, |rule| rule.roles.retain(|id| *id != role.id)).await
+}
+
+/// Autorise les membres ayant une permission Discord
+#[poise::command(
+    slash_command,
+    guild_only,
+    category = "Administration",
+    rename = "exiger_permission"
+)]
+pub async fn permissions_exiger(
+    ctx: Context<'_>,
+    #[description = "Catégorie ou commande"]
+    #[autocomplete = "autocomplete_rule_target"]
+    cible: String,
+    permission: PermissionChoice,
+) -> Result<()> {
+    edit_rule(ctx, &cible, |rule| rule.permissions = permission.into()).await
+}
+
+/// Remet la règle par défaut
+#[poise::command(
+    slash_command,
+    guild_only,
+    category = "Administration",
+    rename = "reinitialiser"
+)]
+pub async fn permissions_reinitialiser(
+    ctx: Context<'_>,
+    #[description = "Catégorie ou commande"]
+    #[autocomplete = "autocomplete_rule_target"]
+    cible: String,
+) -> Result<()> {
+    ctx.defer_ephemeral().await?;
+    let guild_id = ctx
+        .guild_id()
+        .ok_or(WattouError::CommandCanOnlyBeUsedInGuilds)?;
+    let target = parse_rule_target(ctx, &cible)?;
+
+    PermissionConfig::edit(guild_id, |config| {
+        config.reset_rule(&target);
+        Ok(())
+    })?;
+
+    ctx.say(format!("Règle par défaut rétablie pour la {}", target))
+        .await?;
+    info!(guild_id = %guild_id, target = %target, "reset permission rule");
+    Ok(())
+}
+
+async fn edit_rule(ctx: Context<'_>, cible: &str, f: impl FnOnce(&mut Rule)) -> Result<()> {
+    ctx.defer_ephemeral().await?;
+    let guild_id = ctx
+        .guild_id()
+        .ok_or(WattouError::CommandCanOnlyBeUsedInGuilds)?;
+    let target = parse_rule_target(ctx, cible)?;
+    let category = match &target {
+        RuleTarget::Category(category) => *category,
+        RuleTarget::Command(name) => all_commands(ctx)
+            .into_iter()
+            .find(|command| &command.qualified_name == name)
+            .map(CommandCategory::of)
+            .unwrap_or(CommandCategory::Consultation),
+    };
+
+    let rule = PermissionConfig::edit(guild_id, |config| {
+        let mut rule = config.target_rule(&target, category);
+        f(&mut rule);
+        if category == CommandCategory::Administration && rule.is_empty() {
+            Err(WattouError::OpenAdministration(target.to_string()))?
+        }
+        config.set_rule(&target, rule.clone());
+        Ok(rule)
+    })?;
+
+    ctx.send(
+        CreateReply::default()
+            .ephemeral(true)
+            .content(format!("{} : {}", target, rule))
+            .allowed_mentions(CreateAllowedMentions::new()),
+    )
+    .await?;
+    info!(guild_id = %guild_id, target = %target, rule = ?rule, "edited permission rule");
+    Ok(())
+}
+
+/// Every command and subcommand of the bot
+fn all_commands<'a>(ctx: Context<'a>) -> Vec<&'a poise::Command<Arc<GlobalData>, Report>> {
+    fn flatten<'a>(
+        commands: &'a [poise::Command<Arc<GlobalData>, Report>],
+        out: &mut Vec<&'a poise::Command<Arc<GlobalData>, Report>>,
+    ) {
+        for command in commands {
+            out.push(command);
+            flatten(&command.subcommands, out);
+        }
+    }
+
+    let mut commands = Vec::new();
+    flatten(&ctx.framework().options().commands, &mut commands);
+    commands
+}
+
+fn parse_rule_target(ctx: Context<'_>, cible: &str) -> Result<RuleTarget> {
+    let names = all_commands(ctx)
+        .into_iter()
+        .map(|command| command.qualified_name.clone())
+        .collect::<Vec<_>>();
+    Ok(RuleTarget::parse(cible, &names)
+        .ok_or_else(|| WattouError::UnknownPermissionTarget(cible.to_string()))?)
+}
+
+pub async fn autocomplete_rule_target(ctx: Context<'_>, partial: &str) -> Vec<String> {
+    let input = easy_comp_string(partial);
+    CommandCategory::ALL
+        .iter()
+        .map(|category| category.name().to_string())
+        .chain(
+            all_commands(ctx)
+                .into_iter()
+                .filter(|command| command.subcommands.is_empty())
+                .map(|command| command.qualified_name.clone()),
+        )
+        .filter(|name| easy_comp_string(name).contains(&input))
+        .take(25)
+        .collect()
+}
+
+/// Supprime des messages de ce salon, les messages des colles et des TP sont conservés
+#[poise::command(slash_command, guild_only, category = "Modération")]
+pub async fn clear(
+    ctx: Context<'_>,
+    #[description = "Nombre maximum de messages à supprimer"]
+    #[rename = "limite"]
+    #[min = 1]
+    #[max = 500]
+    limit: u16,
+    #[description = "Uniquement les messages de bots"]
+    #[rename = "bots"]
+    bots_only: Option<bool>,
+    #[description = "Uniquement les messages de ce membre"]
+    #[rename = "membre"]
+    author: Option<User>,
+    #[description = "Uniquement les messages contenant ce texte"]
+    #[rename = "contenant"]
+    contains: Option<String>,
+    #[description = "Uniquement les messages avant celui-ci (id ou lien)"]
+    #[rename = "avant"]
+    before: Option<String>,
+    #[description = "Uniquement les messages après celui-ci (id ou lien)"]
+    #[rename = "apres"]
+    after: Option<String>,
+) -> Result<()> {
+    ctx.defer_ephemeral().await?;
+    let guild_id = ctx
+        .guild_id()
+        .ok_or(WattouError::CommandCanOnlyBeUsedInGuilds)?;
+
+    let parse_id = |s: Option<String>| {
+        s.map(|s| purge::parse_message_id(&s).ok_or(WattouError::InvalidMessageId(s)))
+            .transpose()
+    };
+    let purge = Purge {
+        channel_id: ctx.channel_id(),
+        filter: PurgeFilter {
+            bots_only: bots_only.unwrap_or(false),
+            author: author.map(|user| user.id),
+            contains,
+            before: parse_id(before)?,
+            after: parse_id(after)?,
+        },
+        limit: limit as usize,
+    };
+
+    let messages = purge.find_messages(ctx.http(), guild_id).await?;
+    if messages.is_empty() {
+        ctx.say("Aucun message ne correspond").await?;
+        return Ok(());
+    }
+
+    if messages.len() > CLEAR_CONFIRMATION_THRESHOLD
+        && !confirm(ctx, format!("Supprimer {} messages ?", messages.len())).await?
+    {
+        ctx.say("Suppression annulée").await?;
+        return Ok(());
+    }
+
+    let (deleted, error) = purge.delete(ctx.http(), messages).await;
+    info!(
+        channel_id = %ctx.channel_id(),
+        deleted,
+        filter = ?purge.filter,
+        "purged messages"
+    );
+    match error {
+        None => {
+            ctx.say(format!("{} message(s) supprimé(s)", deleted))
+                .await?;
+            Ok(())
+        }
+        // The error is then reported like the others
+        Some(error) => {
+            ctx.say(format!(
+                "{} message(s) supprimé(s) avant que la suppression échoue",
+                deleted
+            ))
+            .await?;
+            Err(error)
+        }
+    }
+}
+
+/// Above this many messages, `/clear` asks before deleting
+const CLEAR_CONFIRMATION_THRESHOLD: usize = 20;
+
+/// Asks the author to confirm with a button, returns false if they cancel or do not answer
+async fn confirm(ctx: Context<'_>, question: String) -> Result<bool> {
+    let id = ctx.id();
+    let confirm_id = format!("{id}-confirmer");
+    let cancel_id = format!("{id}-annuler");
+
+    let handle = ctx
+        .send(
+            CreateReply::default()
+                .ephemeral(true)
+                .content(question)
+                .components(vec![CreateActionRow::Buttons(vec![
+                    CreateButton::new(&confirm_id)
+                        .label("Confirmer")
+                        .style(ButtonStyle::Danger),
+                    CreateButton::new(&cancel_id)
+                        .label("Annuler")
+                        .style(ButtonStyle::Secondary),
+                ])]),
+        )
+        .await?;
+
+    let custom_ids = [confirm_id.clone(), cancel_id];
+    let interaction = ComponentInteractionCollector::new(ctx.serenity_context())
+        .author_id(ctx.author().id)
+        .channel_id(ctx.channel_id())
+        .timeout(std::time::Duration::from_secs(60))
+        .filter(move |interaction| custom_ids.contains(&interaction.data.custom_id))
+        .await;
+
+    handle
+        .edit(ctx, CreateReply::default().components(vec![]))
+        .await?;
+
+    let Some(interaction) = interaction else {
+        return Ok(false);
+    };
+    interaction
+        .create_response(ctx, CreateInteractionResponse::Acknowledge)
+        .await?;
+    Ok(interaction.data.custom_id == confirm_id)
+}
+
+#[poise::command(slash_command, category = "Consultation")]
+pub async fn colles_de_prof(
+    ctx: Context<'_>,
+    #[rename = "prof"]
+    #[autocomplete = "autocomplete_prof"]
+    prof_str: String,
+    #[rename = "limite"] limit: Option<usize>,
+) -> Result<()> {
+    ctx.defer_ephemeral().await?;
+    let limit = limit.and_then(|l| (l < 100).then_some(l)).unwrap_or(5);
+    let data = GuildData::from_ctx(ctx)?;
+
+    let Some(prof) = data.profs.get(prof_str.as_str()).cloned() else {
+        Err(WattouError::ProfNotFound(prof_str))?
+    };
+    let places = OpenPlaces::read_or_default(data.guild_id)?;
+
+    ctx.send(
+        CreateReply::default()
+            .ephemeral(true)
+            .content(format!(
+                "Prochaines colles pour {}: \n- {}",
+                prof.name(),
+                prof.get_next_colles_in_guild(&data, &places, limit)
+                    .iter()
+                    .map(|(class, indexed)| indexed.colle.format(
+                        ColleStringFormat::ForProf,
+                        vec![format!(
+                            "{}{}{}",
+                            indexed.group_id,
+                            class_suffix(&data, class),
+                            places
+                                .claimant(class, indexed)
+                                .map(|user_id| format!(
+                                    " (place libre prise par {})",
+                                    Mention::from(user_id)
+                                ))
+                                .unwrap_or_default()
+                        )]
+                    ))
+                    .collect::<Vec<_>>()
+                    .join("\n- ")
+            ))
+            .allowed_mentions(CreateAllowedMentions::new())
+            .reply(true),
+    )
+    .await?;
+
+    Ok(())
+}
+
+pub async fn autocomplete_prof(ctx: Context<'_>, partial: &str) -> Vec<String> {
+    let input = easy_comp_string(partial);
+    let Ok(data) = GuildData::from_ctx(ctx) else {
+        return vec![];
+    };
+
+    data.profs
+        .values()
+        .filter_map(|p| {
+            let name = p.name();
+            easy_comp_string(name)
+                .contains(&input)
+                .then_some(name.to_owned())
+        })
+        .collect::<Vec<_>>()
+}
+
+pub async fn autocomplete_class(ctx: Context<'_>, partial: &str) -> Vec<String> {
+    let input = easy_comp_string(partial);
+    let Ok(data) = GuildData::from_ctx(ctx) else {
+        return vec![];
+    };
+
+    data.classes
+        .iter()
+        .map(|class| class.name.to_string())
+        .filter(|name| easy_comp_string(name).contains(&input))
+        .collect()
+}
+
+/// Groups of the class given in the `classe` argument, or of the default class of the member
+pub async fn autocomplete_group(ctx: Context<'_>, partial: &str) -> Vec<AutocompleteChoice> {
+    let input = easy_comp_string(partial);
+    let Ok(data) = GuildData::from_ctx(ctx) else {
+        return vec![];
+    };
+    let class_arg = class_arg(ctx);
+    let Ok(class) = data.class_for_ctx(ctx, class_arg.as_deref()).await else {
+        return vec![];
+    };
+
+    class
+        .all_groups()
+        .into_iter()
+        .filter(|group| {
+            easy_comp_string(&group.id).contains(&input)
+                || group
+                    .name
+                    .as_deref()
+                    .is_some_and(|name| easy_comp_string(name).contains(&input))
+        })
+        .take(25)
+        .map(|group| AutocompleteChoice::new(group.to_string(), group.id.to_string()))
+        .collect()
+}
+
+/// Places libres of the class given in the `classe` argument, unclaimed or claimed by the author
+/// of the command
+pub async fn autocomplete_open_place(ctx: Context<'_>, partial: &str) -> Vec<AutocompleteChoice> {
+    place_choices(ctx, partial, |claimant| {
+        claimant.is_none_or(|user_id| user_id == ctx.author().id)
+    })
+    .await
+}
+
+/// Places libres claimed by the author of the command
+pub async fn autocomplete_claimed_place(
+    ctx: Context<'_>,
+    partial: &str,
+) -> Vec<AutocompleteChoice> {
+    place_choices(ctx, partial, |claimant| claimant == Some(ctx.author().id)).await
+}
+
+async fn place_choices(
+    ctx: Context<'_>,
+    partial: &str,
+    filter: impl Fn(Option<UserId>) -> bool,
+) -> Vec<AutocompleteChoice> {
+    let input = easy_comp_string(partial);
+    let Ok(data) = GuildData::from_ctx(ctx) else {
+        return vec![];
+    };
+    let class_arg = class_arg(ctx);
+    let (Ok(class), Ok(places)) = (
+        data.class_for_ctx(ctx, class_arg.as_deref()).await,
+        OpenPlaces::read_or_default(data.guild_id),
+    ) else {
+        return vec![];
+    };
+
+    class
+        .open_places()
+        .into_iter()
+        .filter(|indexed| filter(places.claimant(class, indexed)))
+        .map(|indexed| {
+            (
+                indexed.colle.format(ColleStringFormat::Explicit, vec![]),
+                indexed.key(),
+            )
+        })
+        .filter(|(name, _)| easy_comp_string(name).contains(&input))
+        .take(25)
+        .map(|(name, key)| AutocompleteChoice::new(name, key))
+        .collect()
+}
+
+/// Upcoming colles of the group of the author, or of the group given in the `groupe` argument
+pub async fn autocomplete_own_colle(ctx: Context<'_>, partial: &str) -> Vec<AutocompleteChoice> {
+    let input = easy_comp_string(partial);
+    let Ok(data) = GuildData::from_ctx(ctx) else {
+        return vec![];
+    };
+    let (class_arg, group_arg) = (class_arg(ctx), string_arg(ctx, "groupe"));
+    let Ok(class) = data.class_for_ctx(ctx, class_arg.as_deref()).await else {
+        return vec![];
+    };
+    let Ok(group) = data.group_for_ctx(ctx, class, group_arg.as_deref()).await else {
+        return vec![];
+    };
+
+    class
+        .query(&ColleQuery::upcoming().group(&group.id))
+        .into_iter()
+        .map(|indexed| {
+            (
+                indexed.colle.format(ColleStringFormat::Explicit, vec![]),
+                indexed.key(),
+            )
+        })
+        .filter(|(name, _)| easy_comp_string(name).contains(&input))
+        .take(25)
+        .map(|(name, key)| AutocompleteChoice::new(name, key))
+        .collect()
+}
+
+/// Upcoming rattrapages proposed by the author
+pub async fn autocomplete_own_rattrapage(
+    ctx: Context<'_>,
+    partial: &str,
+) -> Vec<AutocompleteChoice> {
+    let Ok(data) = GuildData::from_ctx(ctx) else {
+        return vec![];
+    };
+    let Some(prof) = ProfLinks::read_or_default(data.guild_id)
+        .ok()
+        .and_then(|links| links.prof_of(ctx.author().id).map(str::to_string))
+    else {
+        return vec![];
+    };
+    rattrapage_choices(&data, partial, |slot| slot.prof == prof)
+}
+
+/// Upcoming rattrapages booked by the author
+pub async fn autocomplete_booked_rattrapage(
+    ctx: Context<'_>,
+    partial: &str,
+) -> Vec<AutocompleteChoice> {
+    let Ok(data) = GuildData::from_ctx(ctx) else {
+        return vec![];
+    };
+    rattrapage_choices(&data, partial, |slot| {
+        slot.booked.contains(&ctx.author().id)
+    })
+}
+
+fn rattrapage_choices(
+    data: &GuildData,
+    partial: &str,
+    filter: impl Fn(&RattrapageSlot) -> bool,
+) -> Vec<AutocompleteChoice> {
+    let input = easy_comp_string(partial);
+    let Ok(rattrapages) = Rattrapages::read_or_default(data.guild_id) else {
+        return vec![];
+    };
+    rattrapages
+        .all_upcoming()
+        .into_iter()
+        .filter(|slot| filter(slot))
+        .map(|slot| (slot.describe(&data.profs), slot.id))
+        .filter(|(name, _)| easy_comp_string(name).contains(&input))
+        .take(25)
+        .map(|(name, id)| AutocompleteChoice::new(name, id))
+        .collect()
+}
+
+/// Colles of the journal group of the author over in the last month, not graded yet first
+pub async fn autocomplete_recent_colle(ctx: Context<'_>, partial: &str) -> Vec<AutocompleteChoice> {
+    let input = easy_comp_string(partial);
+    let Ok(data) = GuildData::from_ctx(ctx) else {
+        return vec![];
+    };
+    let (class_arg, group_arg) = (class_arg(ctx), string_arg(ctx, "groupe"));
+    let Ok(class) = data.class_for_ctx(ctx, class_arg.as_deref()).await else {
+        return vec![];
+    };
+    let Ok(group) = journal_group(ctx, &data, class, group_arg.as_deref()).await else {
+        return vec![];
+    };
+    let journals = Journals::read_or_default(data.guild_id).unwrap_or_default();
+    let journal = journals.get(ctx.author().id);
+
+    let mut colles = journal::recent_colles(class, group);
+    colles.sort_by_key(|indexed| journal.is_some_and(|journal| journal.is_graded(indexed)));
+    colles
+        .into_iter()
+        .map(|indexed| {
+            (
+                indexed.colle.format(ColleStringFormat::Explicit, vec![]),
+                indexed.key(),
+            )
+        })
+        .filter(|(name, _)| easy_comp_string(name).contains(&input))
+        .take(25)
+        .map(|(name, key)| AutocompleteChoice::new(name, key))
+        .collect()
+}
+
+/// Colles of the group of the author whose exercises can still be shared, most recent first
+pub async fn autocomplete_shareable_colle(
+    ctx: Context<'_>,
+    partial: &str,
+) -> Vec<AutocompleteChoice> {
+    let input = easy_comp_string(partial);
+    let Ok(data) = GuildData::from_ctx(ctx) else {
+        return vec![];
+    };
+    let (class_arg, group_arg) = (class_arg(ctx), string_arg(ctx, "groupe"));
+    let Ok(class) = data.class_for_ctx(ctx, class_arg.as_deref()).await else {
+        return vec![];
+    };
+    let Ok(group) = data.group_for_ctx(ctx, class, group_arg.as_deref()).await else {
+        return vec![];
+    };
+
+    exercise::shareable_colles(class, group)
+        .into_iter()
+        .map(|indexed| {
+            (
+                indexed.colle.format(ColleStringFormat::Explicit, vec![]),
+                indexed.key(),
+            )
+        })
+        .filter(|(name, _)| easy_comp_string(name).contains(&input))
+        .take(25)
+        .map(|(name, key)| AutocompleteChoice::new(name, key))
+        .collect()
+}
+
+/// Value typed in the `classe` argument of the command being autocompleted
+fn class_arg(ctx: Context<'_>) -> Option<String> {
+    string_arg(ctx, "classe")
+}
+
+/// Value typed in an argument of the command being autocompleted
+fn string_arg(ctx: Context<'_>, name: &str) -> Option<String> {
+    match ctx {
+        poise::Context::Application(ctx) => ctx.args.iter().find_map(|arg| match &arg.value {
+            ResolvedValue::String(value) if arg.name == name => Some(value.to_string()),
+            _ => None,
+        }),
+        poise::Context::Prefix(_) => None,
+    }
+}
+
+/// Group of the journal of the author: the given one, their own, or the one they enabled
+/// their journal for
+async fn journal_group<'a>(
+    ctx: Context<'_>,
+    data: &GuildData,
+    class: &'a Class,
+    group_id: Option<&str>,
+) -> Result<&'a Group> {
+    match data.group_for_ctx(ctx, class, group_id).await {
+        Err(e) if group_id.is_none() => Journals::read_or_default(data.guild_id)?
+            .get(ctx.author().id)
+            .filter(|journal| journal.class == *class.name)
+            .and_then(|journal| class.group(&journal.group_id))
+            .ok_or(e),
+        result => result,
+    }
+}
+
+/// Rattrapages booked by the author, when `group` is their own group
+async fn own_rattrapages(
+    ctx: Context<'_>,
+    data: &GuildData,
+    class: &Class,
+    group: &Group,
+) -> Result<Vec<Colle>> {
+    let own = data
+        .group_for_ctx(ctx, class, None)
+        .await
+        .is_ok_and(|own| own.id == group.id);
+    if !own {
+        return Ok(vec![]);
+    }
+    Ok(Rattrapages::read_or_default(data.guild_id)?.colles_of(class, ctx.author().id, &data.profs))
+}
+
+/// " (MPSI)" when the guild has several classes, nothing otherwise
+fn class_suffix(data: &GuildData, class: &Class) -> String {
+    if data.has_several_classes() {
+        format!(" ({})", class.name)
+    } else {
+        String::new()
+    }
 }
@@ -0,0 +1,548 @@
+//! Configuration of the bot, read from a TOML file at startup.
+//!
+//! The file is `wattou.toml` by default, another one can be given with `--config <path>` or the
+//! `WATTOU_CONFIG` environment variable. Every field is optional.
+use crate::{
+    guild_data::SavedData,
+    logging::LogSettings,
+    storage::StorageKind,
+    weeks::{DATE_FORMAT, Holiday},
+};
+use color_eyre::{Result, eyre::eyre};
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use serenity::all::{ActivityData, ActivityType};
+use std::{env, fs, path::PathBuf};
+use time::{Date, Month, OffsetDateTime, UtcOffset, Weekday, macros::format_description};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Folder with one sub folder per guild
+    pub data_dir: PathBuf,
+    pub storage: StorageKind,
+    pub logging: LogSettings,
+    pub scheduler: SchedulerConfig,
+    pub reminders: ReminderConfig,
+    pub board: BoardConfig,
+    pub presence: PresenceConfig,
+    pub features: FeaturesConfig,
+    pub calendar: CalendarConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SchedulerConfig {
+    /// Minutes between two refreshes of the boards and reminders
+    pub refresh_interval_minutes: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReminderConfig {
+    /// Reminders are sent when the colle starts in less than this many hours
+    pub hours_before: u64,
+    /// Subject letter of the colles that need a reminder, e.g. `A` for English
+    pub subject: char,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BoardConfig {
+    /// Upcoming colles shown for each group on the `/toutes_les_colles` message
+    pub colles_per_group: usize,
+    /// Whether groups listed in the `ghosts` file are on the board
+    pub show_ghosts: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PresenceConfig {
+    pub activity: String,
+    pub kind: PresenceKind,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PresenceKind {
+    Playing,
+    Listening,
+    Watching,
+    Competing,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FeaturesConfig {
+    /// `/semaine_tp` and its message
+    pub semaine_tp: bool,
+    /// `/rappel` and the reminders sent in DM
+    pub reminders: bool,
+    /// `/mes_notes` and the grades asked in DM after each colle
+    pub journal: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CalendarConfig {
+    /// Used to generate and check the `weeks` files
+    pub holidays: Vec<HolidayConfig>,
+    /// Time zone of the colles in the `.ics` calendars, the days start at midnight there, e.g.
+    /// `Europe/Paris`
+    pub timezone: String,
+    /// Fixed offset from UTC of the time zone, e.g. `+04:00`, needed when the time zone does not
+    /// follow the central European rules
+    pub utc_offset: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HolidayConfig {
+    pub name: String,
+    /// First day without colles, e.g. `19-10-2024`
+    pub start: String,
+    /// Last day without colles
+    pub end: String,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            data_dir: "data".into(),
+            storage: StorageKind::Files,
+            logging: LogSettings::default(),
+            scheduler: SchedulerConfig::default(),
+            reminders: ReminderConfig::default(),
+            board: BoardConfig::default(),
+            presence: PresenceConfig::default(),
+            features: FeaturesConfig::default(),
+            calendar: CalendarConfig::default(),
+        }
+    }
+}
+
+impl Default for SchedulerConfig {
+    fn default() -> Self {
+        Self {
+            refresh_interval_minutes: 30,
+        }
+    }
+}
+
+impl Default for ReminderConfig {
+    fn default() -> Self {
+        Self {
+            hours_before: 30,
+            subject: 'A',
+        }
+    }
+}
+
+impl Default for BoardConfig {
+    fn default() -> Self {
+        Self {
+            colles_per_group: 2,
+            show_ghosts: true,
+        }
+    }
+}
+
+impl Default for PresenceConfig {
+    fn default() -> Self {
+        Self {
+            activity: "les aventures de wattou".to_string(),
+            kind: PresenceKind::Watching,
+        }
+    }
+}
+
+impl Default for FeaturesConfig {
+    fn default() -> Self {
+        Self {
+            semaine_tp: true,
+            reminders: true,
+            journal: true,
+        }
+    }
+}
+
+impl Default for CalendarConfig {
+    fn default() -> Self {
+        Self {
+            holidays: vec![],
+            timezone: "Europe/Paris".to_string(),
+            utc_offset: None,
+        }
+    }
+}
+
+impl CalendarConfig {
+    /// Time zones on UTC+1 in winter and UTC+2 in summer
+    const CENTRAL_EUROPEAN: [&'static str; 8] = [
+        "Europe/Paris",
+        "Europe/Brussels",
+        "Europe/Luxembourg",
+        "Europe/Monaco",
+        "Europe/Andorra",
+        "Europe/Zurich",
+        "Europe/Berlin",
+        "CET",
+    ];
+
+    /// Offset of a time zone without summer time, `None` for the central European ones
+    fn fixed_offset(&self) -> Result<Option<UtcOffset>> {
+        if let Some(offset) = &self.utc_offset {
+            return UtcOffset::parse(
+                offset.trim(),
+                format_description!("[offset_hour sign:mandatory]:[offset_minute]"),
+            )
+            .map(Some)
+            .map_err(|e| eyre!("calendar.utc_offset {offset} invalide ({e}), par exemple +04:00"));
+        }
+        if Self::CENTRAL_EUROPEAN.contains(&self.timezone.as_str()) {
+            return Ok(None);
+        }
+        if self.timezone.eq_ignore_ascii_case("UTC") {
+            return Ok(Some(UtcOffset::UTC));
+        }
+        Err(eyre!(
+            "calendar.timezone {} : donnez aussi son décalage avec calendar.utc_offset, par \
+             exemple \"+04:00\"",
+            self.timezone
+        ))
+    }
+
+    /// Offset of the time zone at this instant, the days of the colles start at midnight there
+    pub fn offset_at(&self, instant: OffsetDateTime) -> UtcOffset {
+        match self.fixed_offset() {
+            Ok(Some(offset)) => return offset,
+            Ok(None) => {}
+            // Checked when loading the config
+            Err(_) => return UtcOffset::UTC,
+        }
+
+        // Summer time from the last Sunday of March to the last Sunday of October, at 1h UTC
+        let year = instant.year();
+        let last_sunday = |next_month| {
+            Date::from_calendar_date(year, next_month, 1)
+                .map(|first| first.prev_occurrence(Weekday::Sunday).with_hms(1, 0, 0))
+        };
+        let summer = match (last_sunday(Month::April), last_sunday(Month::November)) {
+            (Ok(Ok(start)), Ok(Ok(end))) => {
+                let instant = instant.to_offset(UtcOffset::UTC);
+                start.assume_utc() <= instant && instant < end.assume_utc()
+            }
+            _ => false,
+        };
+        UtcOffset::from_hms(if summer { 2 } else { 1 }, 0, 0).unwrap_or(UtcOffset::UTC)
+    }
+
+    pub fn holidays(&self) -> Result<Vec<Holiday>> {
+        self.holidays
+            .iter()
+            .map(|holiday| {
+                let parse = |date: &str| {
+                    Date::parse(date, DATE_FORMAT).map_err(|e| {
+                        eyre!(
+                            "calendar.holidays {} : date {date} invalide ({e})",
+                            holiday.name
+                        )
+                    })
+                };
+                let (start, end) = (parse(&holiday.start)?, parse(&holiday.end)?);
+                if end < start {
+                    Err(eyre!(
+                        "calendar.holidays {} se termine avant de commencer",
+                        holiday.name
+                    ))?
+                }
+                Ok(Holiday {
+                    name: holiday.name.clone(),
+                    start,
+                    end,
+                })
+            })
+            .collect()
+    }
+}
+
+impl PresenceConfig {
+    pub fn activity_data(&self) -> ActivityData {
+        ActivityData {
+            name: self.activity.clone(),
+            kind: match self.kind {
+                PresenceKind::Playing => ActivityType::Playing,
+                PresenceKind::Listening => ActivityType::Listening,
+                PresenceKind::Watching => ActivityType::Watching,
+                PresenceKind::Competing => ActivityType::Competing,
+            },
+            state: None,
+            url: None,
+        }
+    }
+}
+
+/// Upper bounds shared by the bot wide config and the guild overrides
+const MAX_REMINDER_HOURS: u64 = 24 * 14;
+const MAX_COLLES_PER_GROUP: usize = 10;
+
+fn validate_reminder_hours(hours: u64) -> Result<()> {
+    if !(1..=MAX_REMINDER_HOURS).contains(&hours) {
+        Err(eyre!(
+            "reminders.hours_before doit être entre 1 et {MAX_REMINDER_HOURS}, pas {hours}"
+        ))?
+    }
+    Ok(())
+}
+
+fn validate_colles_per_group(count: usize) -> Result<()> {
+    if !(1..=MAX_COLLES_PER_GROUP).contains(&count) {
+        Err(eyre!(
+            "board.colles_per_group doit être entre 1 et {MAX_COLLES_PER_GROUP}, pas {count}"
+        ))?
+    }
+    Ok(())
+}
+
+fn validate_subject(subject: char) -> Result<()> {
+    if !subject.is_ascii_uppercase() {
+        Err(eyre!(
+            "reminders.subject doit être la lettre majuscule d'une matière, pas {subject}"
+        ))?
+    }
+    Ok(())
+}
+
+impl Config {
+    pub const ENV_VAR: &'static str = "WATTOU_CONFIG";
+    pub const DEFAULT_PATH: &'static str = "wattou.toml";
+
+    /// Path given with `--config`, then `WATTOU_CONFIG`, then [Config::DEFAULT_PATH]
+    pub fn path() -> (PathBuf, bool) {
+        let mut args = env::args().skip(1);
+        while let Some(arg) = args.next() {
+            if arg == "--config"
+                && let Some(path) = args.next()
+            {
+                return (path.into(), true);
+            }
+            if let Some(path) = arg.strip_prefix("--config=") {
+                return (path.into(), true);
+            }
+        }
+
+        match env::var(Self::ENV_VAR) {
+            Ok(path) => (path.into(), true),
+            Err(_) => (Self::DEFAULT_PATH.into(), false),
+        }
+    }
+
+    /// Reads the config file, a missing default file means the default config, then applies
+    /// the environment variables
+    pub fn load() -> Result<Self> {
+        let (path, explicit) = Self::path();
+        let mut config = if !explicit && !fs::exists(&path)? {
+            Self::default()
+        } else {
+            let content = fs::read_to_string(&path)
+                .map_err(|e| eyre!("Impossible de lire {}: {e}", path.display()))?;
+            toml::from_str(&content)
+                .map_err(|e| eyre!("Configuration {} invalide: {e}", path.display()))?
+        };
+        config.apply_env()?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// `WATTOU_STORAGE`, `WATTOU_LOG_OUTPUT`, `WATTOU_LOG_FORMAT`, `WATTOU_LOG_DIR` and
+    /// `WATTOU_LOG_ROTATION` win over the file, `RUST_LOG` is read by [LogSettings]
+    fn apply_env(&mut self) -> Result<()> {
+        if let Some(storage) = env_value("WATTOU_STORAGE")? {
+            self.storage = storage;
+        }
+        if let Some(output) = env_value("WATTOU_LOG_OUTPUT")? {
+            self.logging.output = output;
+        }
+        if let Some(format) = env_value("WATTOU_LOG_FORMAT")? {
+            self.logging.format = format;
+        }
+        if let Ok(directory) = env::var("WATTOU_LOG_DIR") {
+            self.logging.directory = directory.into();
+        }
+        if let Some(rotation) = env_value("WATTOU_LOG_ROTATION")? {
+            self.logging.rotation = rotation;
+        }
+        Ok(())
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.data_dir.as_os_str().is_empty() {
+            Err(eyre!("data_dir ne peut pas être vide"))?
+        }
+        if self.scheduler.refresh_interval_minutes == 0 {
+            Err(eyre!(
+                "scheduler.refresh_interval_minutes doit être supérieur à 0"
+            ))?
+        }
+        validate_reminder_hours(self.reminders.hours_before)?;
+        validate_subject(self.reminders.subject)?;
+        validate_colles_per_group(self.board.colles_per_group)?;
+        if self.presence.activity.trim().is_empty() {
+            Err(eyre!("presence.activity ne peut pas être vide"))?
+        }
+        self.logging.validate()?;
+        self.calendar.holidays()?;
+        if self.calendar.timezone.trim().is_empty() {
+            Err(eyre!("calendar.timezone ne peut pas être vide"))?
+        }
+        self.calendar.fixed_offset()?;
+        Ok(())
+    }
+}
+
+/// Value of an environment variable parsed like the same field of the config file
+fn env_value<T: DeserializeOwned>(name: &str) -> Result<Option<T>> {
+    let Ok(value) = env::var(name) else {
+        return Ok(None);
+    };
+    serde_json::from_value(serde_json::Value::String(value.trim().to_lowercase()))
+        .map(Some)
+        .map_err(|e| eyre!("{name} invalide {value}: {e}"))
+}
+
+static CONFIG: OnceCell<Config> = OnceCell::new();
+
+pub fn init(config: Config) -> Result<()> {
+    CONFIG
+        .set(config)
+        .map_err(|_| eyre!("La configuration est déjà initialisée"))
+}
+
+/// The loaded config, or the default one before [init] is called
+pub fn config() -> &'static Config {
+    CONFIG.get_or_init(Config::default)
+}
+
+/// Settings of the bot wide config that a guild can change for itself
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GuildConfig {
+    pub reminder_hours_before: Option<u64>,
+    pub reminder_subject: Option<char>,
+    pub board_colles_per_group: Option<usize>,
+    pub board_show_ghosts: Option<bool>,
+}
+
+impl SavedData for GuildConfig {
+    const FILE_NAME: &'static str = "config.json";
+
+    fn ser(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    fn de(value: &str) -> Result<Self> {
+        Ok(serde_json::from_str(value)?)
+    }
+}
+
+/// Keys of [GuildConfig] for `/config definir`
+#[derive(Debug, Clone, Copy, poise::ChoiceParameter)]
+pub enum GuildConfigKey {
+    #[name = "Heures avant la colle pour le rappel"]
+    ReminderHoursBefore,
+    #[name = "Matière des rappels"]
+    ReminderSubject,
+    #[name = "Colles par groupe sur le tableau"]
+    BoardCollesPerGroup,
+    #[name = "Groupes fantômes sur le tableau (true ou false)"]
+    BoardShowGhosts,
+}
+
+impl GuildConfig {
+    pub fn reminder_hours_before(&self) -> u64 {
+        self.reminder_hours_before
+            .unwrap_or(config().reminders.hours_before)
+    }
+
+    pub fn reminder_subject(&self) -> char {
+        self.reminder_subject.unwrap_or(config().reminders.subject)
+    }
+
+    pub fn board_colles_per_group(&self) -> usize {
+        self.board_colles_per_group
+            .unwrap_or(config().board.colles_per_group)
+    }
+
+    pub fn board_show_ghosts(&self) -> bool {
+        self.board_show_ghosts.unwrap_or(config().board.show_ghosts)
+    }
+
+    /// Sets the override, or removes it when `value` is [None]
+    pub fn set(&mut self, key: GuildConfigKey, value: Option<&str>) -> Result<()> {
+        match key {
+            GuildConfigKey::ReminderHoursBefore => {
+                self.reminder_hours_before = value.map(str::parse).transpose()?;
+                self.reminder_hours_before
+                    .map(validate_reminder_hours)
+                    .transpose()?;
+            }
+            GuildConfigKey::ReminderSubject => {
+                self.reminder_subject = value.map(str::parse).transpose()?;
+                self.reminder_subject.map(validate_subject).transpose()?;
+            }
+            GuildConfigKey::BoardCollesPerGroup => {
+                self.board_colles_per_group = value.map(str::parse).transpose()?;
+                self.board_colles_per_group
+                    .map(validate_colles_per_group)
+                    .transpose()?;
+            }
+            GuildConfigKey::BoardShowGhosts => {
+                self.board_show_ghosts = value.map(str::parse).transpose()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Effective config of the guild, marking the values it overrides
+    pub fn describe(&self) -> String {
+        let mark = |overridden: bool| if overridden { " (serveur)" } else { "" };
+        let config = config();
+        format!(
+            "# Configuration\n\
+            - Rappel {} h avant la colle{}\n\
+            - Matière des rappels : {}{}\n\
+            - Colles par groupe sur le tableau : {}{}\n\
+            - Groupes fantômes sur le tableau : {}{}\n\
+            - Actualisation toutes les {} minutes\n\
+            - Fonctionnalités : semaine TP {}, rappels {}, carnet de notes {}",
+            self.reminder_hours_before(),
+            mark(self.reminder_hours_before.is_some()),
+            self.reminder_subject(),
+            mark(self.reminder_subject.is_some()),
+            self.board_colles_per_group(),
+            mark(self.board_colles_per_group.is_some()),
+            if self.board_show_ghosts() {
+                "✅"
+            } else {
+                "❌"
+            },
+            mark(self.board_show_ghosts.is_some()),
+            config.scheduler.refresh_interval_minutes,
+            if config.features.semaine_tp {
+                "✅"
+            } else {
+                "❌"
+            },
+            if config.features.reminders {
+                "✅"
+            } else {
+                "❌"
+            },
+            if config.features.journal {
+                "✅"
+            } else {
+                "❌"
+            },
+        )
+    }
+}
@@ -0,0 +1,519 @@
+//! Changes of the colloscope between two versions of the data files: colles added, removed,
+//! moved to another time, or with another room or colleur.
+//!
+//! `/recharger` posts them in the announcement channel and sends them to the subscribers of the
+//! changed groups, `/recharger apercu:True` shows them without applying the new files.
+use crate::{
+    class::Class,
+    colle::Colle,
+    group::{Group, GroupId},
+    guild_data::{GuildData, SavedData},
+    index::ColleQuery,
+    roster,
+    utils::{Jour, month_to_short_fr},
+};
+use color_eyre::Result;
+use serenity::all::{ChannelId, CreateAttachment, CreateMessage, Http};
+use std::{
+    fmt::{self, Display, Formatter},
+    sync::Arc,
+};
+use tracing::info;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ColleChange {
+    Added(Colle),
+    Removed(Colle),
+    /// Same colle id at another time
+    Moved {
+        before: Colle,
+        after: Colle,
+    },
+    /// Same colle at the same time with another room or colleur
+    Changed {
+        before: Colle,
+        after: Colle,
+    },
+}
+
+/// e.g. : Lundi 13 Jan 14h-15h
+fn when(colle: &Colle) -> String {
+    format!(
+        "{} {} {} {}",
+        Jour::from(colle.start.weekday()),
+        colle.start.day(),
+        month_to_short_fr(colle.start.month()),
+        colle.horaire()
+    )
+}
+
+impl Display for ColleChange {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Added(colle) => write!(f, "➕ {colle}"),
+            Self::Removed(colle) => write!(f, "❌ {colle}"),
+            Self::Moved { before, after } => write!(
+                f,
+                "🔀 {before} → {} avec {} en {}",
+                when(after),
+                after.prof,
+                after.room
+            ),
+            Self::Changed { before, after } => {
+                let mut changes = vec![];
+                if before.room != after.room {
+                    changes.push(format!("salle {} → {}", before.room, after.room));
+                }
+                if before.prof.name() != after.prof.name() {
+                    changes.push(format!("colleur {} → {}", before.prof, after.prof));
+                }
+                write!(f, "✏️ {before} : {}", changes.join(", "))
+            }
+        }
+    }
+}
+
+/// Changes of the colles of a group, in the order of the colles
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GroupDiff {
+    pub class: Arc<str>,
+    pub group_id: GroupId,
+    /// e.g. : G7 (Rouge)
+    pub group: String,
+    pub changes: Vec<ColleChange>,
+}
+
+impl GroupDiff {
+    /// Changes from the colles of `old` to the ones of `new`, [None] when nothing changed
+    pub fn new(old: Option<&Group>, new: Option<&Group>) -> Option<Self> {
+        let group = new.or(old)?;
+        let mut old_colles = old.map(|group| group.colles.clone()).unwrap_or_default();
+        let mut new_colles = new.map(|group| group.colles.clone()).unwrap_or_default();
+        let mut changes = vec![];
+
+        // Colles that did not change at all
+        old_colles.retain(|colle| {
+            let position = new_colles.iter().position(|other| {
+                other.id == colle.id
+                    && other.start == colle.start
+                    && other.end == colle.end
+                    && other.room == colle.room
+                    && other.prof.name() == colle.prof.name()
+            });
+            position.map(|i| new_colles.remove(i)).is_none()
+        });
+        // Then the ones still at the same time
+        old_colles.retain(|colle| {
+            let Some(i) = new_colles
+                .iter()
+                .position(|other| other.id == colle.id && other.start == colle.start)
+            else {
+                return true;
+            };
+            changes.push(ColleChange::Changed {
+                before: colle.clone(),
+                after: new_colles.remove(i),
+            });
+            false
+        });
+        // Then the ones of the same id at another time, in the order of the colles
+        old_colles.sort();
+        new_colles.sort();
+        old_colles.retain(|colle| {
+            let Some(i) = new_colles.iter().position(|other| other.id == colle.id) else {
+                return true;
+            };
+            changes.push(ColleChange::Moved {
+                before: colle.clone(),
+                after: new_colles.remove(i),
+            });
+            false
+        });
+        changes.extend(old_colles.into_iter().map(ColleChange::Removed));
+        changes.extend(new_colles.into_iter().map(ColleChange::Added));
+
+        changes.sort_by_key(|change| match change {
+            ColleChange::Added(colle)
+            | ColleChange::Removed(colle)
+            | ColleChange::Moved { before: colle, .. }
+            | ColleChange::Changed { before: colle, .. } => colle.start,
+        });
+        (!changes.is_empty()).then(|| Self {
+            class: group.class.clone(),
+            group_id: group.id.clone(),
+            group: group.to_string(),
+            changes,
+        })
+    }
+
+    /// The group then one line per change
+    pub fn to_markdown(&self, class_suffix: &str) -> String {
+        let mut s = format!("**{}**{class_suffix}", self.group);
+        for change in &self.changes {
+            s += &format!("\n- {change}");
+        }
+        s
+    }
+}
+
+/// Changes of the groups matched by id, groups only in `new` coming last
+pub fn diff_groups(old: &[Group], new: &[Group]) -> Vec<GroupDiff> {
+    fn find<'a>(groups: &'a [Group], id: &GroupId) -> Option<&'a Group> {
+        groups.iter().find(|group| group.id == *id)
+    }
+    old.iter()
+        .map(|group| (Some(group), find(new, &group.id)))
+        .chain(
+            new.iter()
+                .filter(|group| find(old, &group.id).is_none())
+                .map(|group| (None, Some(group))),
+        )
+        .filter_map(|(old, new)| GroupDiff::new(old, new))
+        .collect()
+}
+
+/// The groups of every period of the class with their colles not over yet, ghosts excluded
+fn upcoming_groups(class: &Class) -> Vec<Group> {
+    class
+        .all_groups()
+        .into_iter()
+        .map(|group| Group {
+            colles: class
+                .query(&ColleQuery::upcoming().group(&group.id))
+                .into_iter()
+                .map(|indexed| indexed.colle.clone())
+                .collect(),
+            ..group.clone()
+        })
+        .collect()
+}
+
+/// Changes of the colles not over yet between two versions of the data of a guild
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColloscopeDiff {
+    pub groups: Vec<GroupDiff>,
+    several_classes: bool,
+}
+
+impl ColloscopeDiff {
+    /// Discord messages are limited to 2000 characters
+    const MAX_MESSAGE_LEN: usize = 2000;
+    /// Most changes sent to a subscriber, the others are in the announcement channel
+    const MAX_CHANGES_SENT: usize = 10;
+
+    pub fn new(old: &GuildData, new: &GuildData) -> Self {
+        let mut names = old
+            .classes
+            .iter()
+            .chain(new.classes.iter())
+            .map(|class| class.name.clone())
+            .collect::<Vec<_>>();
+        names.sort();
+        names.dedup();
+
+        let groups = names
+            .iter()
+            .flat_map(|name| {
+                let groups = |data: &GuildData| {
+                    data.classes
+                        .iter()
+                        .find(|class| class.name == *name)
+                        .map(upcoming_groups)
+                        .unwrap_or_default()
+                };
+                diff_groups(&groups(old), &groups(new))
+            })
+            .collect();
+        Self {
+            groups,
+            several_classes: new.has_several_classes() || old.has_several_classes(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.groups.is_empty()
+    }
+
+    pub fn change_count(&self) -> usize {
+        self.groups.iter().map(|group| group.changes.len()).sum()
+    }
+
+    fn class_suffix(&self, group: &GroupDiff) -> String {
+        if self.several_classes {
+            format!(" ({})", group.class)
+        } else {
+            String::new()
+        }
+    }
+
+    pub fn to_markdown(&self) -> String {
+        if self.is_empty() {
+            return "Aucun changement pour les colles à venir".to_string();
+        }
+        self.groups
+            .iter()
+            .map(|group| group.to_markdown(&self.class_suffix(group)))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// The title and the changes, in an attached file when they do not fit in a message
+    pub fn message(&self, title: &str) -> (String, Option<CreateAttachment>) {
+        let content = format!("{title}\n{}", self.to_markdown());
+        if content.chars().count() <= Self::MAX_MESSAGE_LEN {
+            return (content, None);
+        }
+        (
+            format!(
+                "{title}\n{} changement(s) pour {} groupe(s), détaillés dans le fichier joint",
+                self.change_count(),
+                self.groups.len()
+            ),
+            Some(CreateAttachment::bytes(content, "changements.md")),
+        )
+    }
+
+    /// Message sent to the subscribers of the group, the changes that do not fit are counted
+    fn subscriber_message(&self, group: &GroupDiff) -> String {
+        let mut s = format!(
+            "Le colloscope a changé pour le groupe {}{} :",
+            group.group,
+            self.class_suffix(group)
+        );
+        let others = |count: usize| {
+            format!(
+                "\net {count} autre(s) changement(s), `/mes_colles` donne tes prochaines colles"
+            )
+        };
+        let mut shown = 0;
+        for change in group.changes.iter().take(Self::MAX_CHANGES_SENT) {
+            let line = format!("\n- {change}");
+            let left = group.changes.len() - shown - 1;
+            let footer = if left > 0 {
+                others(left).chars().count()
+            } else {
+                0
+            };
+            if s.chars().count() + line.chars().count() + footer > Self::MAX_MESSAGE_LEN {
+                break;
+            }
+            s += &line;
+            shown += 1;
+        }
+        if shown < group.changes.len() {
+            s += &others(group.changes.len() - shown);
+        }
+        s.chars().take(Self::MAX_MESSAGE_LEN).collect()
+    }
+
+    /// Posts the changes in the announcement channel and sends them to the subscribers of the
+    /// changed groups, returns the number of subscribers reached
+    pub async fn announce(&self, guild_data: &GuildData, http: &Http) -> Result<usize> {
+        if self.is_empty() {
+            return Ok(0);
+        }
+
+        if let Some(channel) = AnnouncementChannel::read(guild_data.guild_id) {
+            let (content, attachment) = self.message("# Changements du colloscope");
+            let mut message = CreateMessage::new().content(content);
+            if let Some(attachment) = attachment {
+                message = message.add_file(attachment);
+            }
+            channel?.0.send_message(http, message).await?;
+        }
+
+        // Subscribers of each changed group
+        let mut recipients = vec![vec![]; self.groups.len()];
+        for (user_id, data) in guild_data.subscribers()?.read().await.iter() {
+            let Some(position) = self.groups.iter().position(|group| {
+                guild_data
+                    .class(data.class.as_deref())
+                    .ok()
+                    .filter(|class| class.name == group.class)
+                    .and_then(|class| class.group(&data.group_id))
+                    .is_some_and(|current| current.id == group.group_id)
+            }) else {
+                continue;
+            };
+            recipients[position].push(*user_id);
+        }
+
+        let mut reached = 0;
+        for (group, user_ids) in self.groups.iter().zip(recipients) {
+            if !user_ids.is_empty() {
+                reached += roster::send_dms(http, &user_ids, &self.subscriber_message(group)).await;
+            }
+        }
+
+        info!(
+            groups = self.groups.len(),
+            changes = self.change_count(),
+            subscribers = reached,
+            "announced colloscope changes"
+        );
+        Ok(reached)
+    }
+}
+
+/// Channel where the changes of the colloscope are posted on reload
+pub struct AnnouncementChannel(pub ChannelId);
+
+impl SavedData for AnnouncementChannel {
+    const FILE_NAME: &'static str = "announcement_channel";
+
+    fn ser(&self) -> String {
+        self.0.to_string()
+    }
+
+    fn de(value: &str) -> Result<Self> {
+        Ok(Self(value.trim().parse()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{colle::ColleId, prof::Prof};
+    use serenity::all::GuildId;
+    use time::{Duration, OffsetDateTime, macros::datetime};
+
+    const MONDAY: OffsetDateTime = datetime!(2024-09-02 17:00 UTC);
+
+    fn colle(id: u8, day: i64, room: &str, prof: &str) -> Colle {
+        let start = MONDAY + Duration::days(day);
+        Colle {
+            id: ColleId('M', id),
+            prof: Arc::new(Prof::new(prof.into())),
+            room: room.into(),
+            start,
+            end: start + Duration::hours(1),
+        }
+    }
+
+    fn group(id: &str, colles: Vec<Colle>) -> Group {
+        Group {
+            guild_id: GuildId::new(1),
+            class: "MPSI".into(),
+            id: id.into(),
+            name: None,
+            colles,
+        }
+    }
+
+    fn changes(old: Vec<Colle>, new: Vec<Colle>) -> Vec<ColleChange> {
+        GroupDiff::new(Some(&group("G1", old)), Some(&group("G1", new)))
+            .map(|diff| diff.changes)
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn same_colles_have_no_diff() {
+        let colles = vec![colle(1, 0, "207", "Dupont"), colle(2, 1, "208", "Martin")];
+        assert_eq!(changes(colles.clone(), colles), vec![]);
+    }
+
+    #[test]
+    fn colle_at_another_time_is_moved() {
+        let before = colle(1, 0, "207", "Dupont");
+        let after = colle(1, 2, "207", "Dupont");
+        assert_eq!(
+            changes(vec![before.clone()], vec![after.clone()]),
+            vec![ColleChange::Moved { before, after }]
+        );
+    }
+
+    #[test]
+    fn room_or_colleur_at_the_same_time_is_changed() {
+        let before = colle(1, 0, "207", "Dupont");
+        let after = colle(1, 0, "305", "Martin");
+        let changes = changes(vec![before.clone()], vec![after.clone()]);
+        assert_eq!(
+            changes,
+            vec![ColleChange::Changed {
+                before,
+                after: after.clone()
+            }]
+        );
+        let line = changes[0].to_string();
+        assert!(line.contains("salle 207 → 305"), "{line}");
+        assert!(line.contains("colleur Dupont → Martin"), "{line}");
+    }
+
+    #[test]
+    fn other_colles_are_added_or_removed() {
+        let kept = colle(1, 0, "207", "Dupont");
+        let removed = colle(2, 1, "208", "Martin");
+        let added = colle(3, 3, "209", "Durand");
+        assert_eq!(
+            changes(
+                vec![kept.clone(), removed.clone()],
+                vec![kept, added.clone()]
+            ),
+            vec![ColleChange::Removed(removed), ColleChange::Added(added)]
+        );
+    }
+
+    #[test]
+    fn groups_are_matched_by_id() {
+        let colles = vec![colle(1, 0, "207", "Dupont")];
+        let diffs = diff_groups(
+            &[group("G1", colles.clone()), group("G2", colles.clone())],
+            &[group("G2", colles.clone()), group("G3", colles.clone())],
+        );
+        let ids = diffs
+            .iter()
+            .map(|diff| diff.group_id.as_ref())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, ["G1", "G3"]);
+        assert_eq!(
+            diffs[0].changes,
+            vec![ColleChange::Removed(colles[0].clone())]
+        );
+        assert_eq!(
+            diffs[1].changes,
+            vec![ColleChange::Added(colles[0].clone())]
+        );
+    }
+
+    #[test]
+    fn subscriber_message_counts_the_changes_that_do_not_fit() {
+        let long_room = "2".repeat(300);
+        let added = (0..20)
+            .map(|day| colle(1, day, &long_room, "Dupont"))
+            .collect::<Vec<_>>();
+        let diff = ColloscopeDiff {
+            groups: diff_groups(&[], &[group("G1", added)]),
+            several_classes: false,
+        };
+
+        let message = diff.subscriber_message(&diff.groups[0]);
+        assert!(message.chars().count() <= ColloscopeDiff::MAX_MESSAGE_LEN);
+        let shown = message.matches("\n- ").count();
+        assert!(
+            shown > 0 && shown < ColloscopeDiff::MAX_CHANGES_SENT,
+            "{message}"
+        );
+        assert!(
+            message.contains(&format!("et {} autre(s) changement(s)", 20 - shown)),
+            "{message}"
+        );
+    }
+
+    #[test]
+    fn subscriber_message_sends_at_most_ten_changes() {
+        let added = (0..15)
+            .map(|day| colle(1, day, "207", "Dupont"))
+            .collect::<Vec<_>>();
+        let diff = ColloscopeDiff {
+            groups: diff_groups(&[], &[group("G1", added)]),
+            several_classes: false,
+        };
+
+        let message = diff.subscriber_message(&diff.groups[0]);
+        assert_eq!(
+            message.matches("\n- ").count(),
+            ColloscopeDiff::MAX_CHANGES_SENT
+        );
+        assert!(message.contains("et 5 autre(s) changement(s)"), "{message}");
+    }
+}
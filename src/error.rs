@@ -1,27 +1,368 @@
-use crate::group::GroupId;
-use serenity::all::GuildId;
+use crate::{Context, GlobalData, guild_data::GuildData, guild_data::SavedData, logging};
+use color_eyre::{Report, Result};
+use poise::{CreateReply, FrameworkError};
+use serenity::all::{ChannelId, CreateMessage, GuildId, Http, Mention};
+use std::sync::Arc;
 use thiserror::Error;
+use tracing::{Instrument, error, warn};
+use uuid::Uuid;
 
 #[derive(Debug, PartialEq, Eq, Error)]
 pub enum WattouError {
     #[error("Cette commande ne peut pas être utilisée en mp")]
     CommandCanOnlyBeUsedInGuilds,
-    #[error("Le groupe {1} n'exsite pas pour le serveur {0}")]
-    NoGroupForGuild(GuildId, GroupId),
+    #[error("Le groupe {2} n'existe pas en {1}")]
+    NoGroupInClass(GuildId, String, String),
+    #[error("La classe {1} n'existe pas")]
+    ClassNotFound(GuildId, String),
+    #[error("Ce serveur a plusieurs classes, précise laquelle : {0}")]
+    ClassRequired(String),
     #[error("Aucune données trouvées pour le serveur {0}")]
     NoDataForGuild(GuildId),
     #[error("Échec du parsing du message")]
     MessageParsingFailed,
     #[error("Groupe introuvable")]
     GroupNotFound,
+    #[error("Le colleur {0} n'existe pas")]
+    ProfNotFound(String),
+    #[error("Tu n'as pas la permission d'utiliser cette commande, elle est réservée à : {0}")]
+    MissingPermission(String),
+    #[error("La {0} ne peut pas être ouverte à tout le monde")]
+    OpenAdministration(String),
+    #[error("{0} n'est ni une catégorie ni une commande")]
+    UnknownPermissionTarget(String),
+    #[error("« {0} » n'est pas un identifiant ou un lien de message")]
+    InvalidMessageId(String),
+    #[error("« {0} » n'est pas une date au format jour-mois-année, par exemple 2-9-2024")]
+    InvalidDate(String),
+    #[error("Valeur invalide : {0}")]
+    InvalidConfigValue(String),
+    #[error("Colloscope ligne {line}, colonne {column} : {reason}")]
+    InvalidColloscope {
+        line: usize,
+        column: usize,
+        reason: String,
+    },
+    #[error("Fichier weeks ligne {line} : {reason}")]
+    InvalidWeeks { line: usize, reason: String },
+    #[error("Fichier ghosts ligne {line} : {reason}")]
+    InvalidGhosts { line: usize, reason: String },
+    #[error("Le groupe {0} est un groupe fantôme")]
+    GhostGroup(String),
+    #[error("Cette place libre n'existe pas ou n'est plus disponible")]
+    PlaceNotFound,
+    #[error("La place {0} est déjà prise")]
+    PlaceAlreadyTaken(String),
+    #[error("Tu n'as pas pris cette place")]
+    PlaceNotClaimed,
+    #[error("Cette colle n'existe pas ou est déjà passée")]
+    ColleNotFound,
+    #[error("Tu as déjà déclaré ton absence à la colle {0}")]
+    AlreadyAbsent(String),
+    #[error("Tu ne peux déclarer une absence que pour ton groupe, pas le groupe {0}")]
+    AbsenceInOtherGroup(String),
+    #[error("Ce rattrapage n'existe pas ou est déjà passé")]
+    RattrapageNotFound,
+    #[error("Le rattrapage n°{0} est complet")]
+    RattrapageFull(u32),
+    #[error("Tu n'as aucune colle manquée à rattraper")]
+    NoMissedColle,
+    #[error("Seuls les colleurs peuvent proposer des rattrapages")]
+    NotAColleur,
+    #[error("« {0} » n'est pas un horaire, par exemple 17h-18h")]
+    InvalidHoraire(String),
+    #[error("« {0} » n'est pas une matière")]
+    UnknownSubject(String),
+    #[error("Tu n'as pas de carnet de notes")]
+    NoJournal,
+    #[error("La semaine « {0} » n'existe pas")]
+    WeekNotFound(String),
+    #[error("Aucun programme publié pour cette matière cette semaine")]
+    ProgrammeNotFound,
+    #[error("Cet exercice n'existe pas")]
+    ExerciseNotFound,
+    #[error("Verrou invalide {0}")]
+    InvalidLock(String),
+    #[error("La période {0} n'existe pas")]
+    PeriodNotFound(String),
+    #[error("Je ne sais pas dans quel groupe tu es, précise-le")]
+    GroupRequired,
+    #[error("Liste des élèves ligne {line} : {reason}")]
+    InvalidRoster { line: usize, reason: String },
+    #[error("Le dossier {0} n'a aucune période avec un fichier periode et un colloscope")]
+    NoPeriod(String),
     #[error("Colle parsing failed {0}")]
     ColleParsingFailed(ColleParsingError),
+    #[error("Les données {0} ont été enregistrées au format {1} qui n'est pas supporté")]
+    UnsupportedDataVersion(&'static str, u32),
 }
 
 #[derive(Debug, PartialEq, Eq, Error)]
 pub enum ColleParsingError {
     #[error("Id parsing failed")]
     IdParsingFailed,
+    #[error("Invalid week selector, expected paires, impaires or a list of weeks")]
+    InvalidWeekSelector,
+    #[error("Unknown substitution, expected salle or colleur")]
+    UnknownSubstitution,
+    #[error("Expected the room in parentheses at the end of `{0}`")]
+    InvalidRoom(String),
+    #[error("Unknown day {day} in `{line}`, expected Lu, Ma, Me, Je, Ve, Sa or Di")]
+    UnknownDay { day: String, line: String },
+    #[error("Colle {0} has no slot")]
+    NoSlot(String),
     #[error("Unknown")]
     Unknown,
 }
+
+impl WattouError {
+    /// Errors caused by what the user asked, the others are bugs or broken data files
+    pub fn is_user_error(&self) -> bool {
+        match self {
+            Self::CommandCanOnlyBeUsedInGuilds
+            | Self::NoGroupInClass(..)
+            | Self::ClassNotFound(..)
+            | Self::ClassRequired(_)
+            | Self::NoDataForGuild(_)
+            | Self::GroupNotFound
+            | Self::ProfNotFound(_)
+            | Self::MissingPermission(_)
+            | Self::OpenAdministration(_)
+            | Self::UnknownPermissionTarget(_)
+            | Self::InvalidMessageId(_)
+            | Self::InvalidConfigValue(_)
+            | Self::InvalidDate(_)
+            | Self::GhostGroup(_)
+            | Self::PlaceNotFound
+            | Self::PlaceAlreadyTaken(_)
+            | Self::PlaceNotClaimed
+            | Self::GroupRequired
+            | Self::PeriodNotFound(_)
+            | Self::InvalidLock(_)
+            | Self::ColleNotFound
+            | Self::NoJournal
+            | Self::WeekNotFound(_)
+            | Self::ProgrammeNotFound
+            | Self::ExerciseNotFound
+            | Self::RattrapageNotFound
+            | Self::RattrapageFull(_)
+            | Self::NoMissedColle
+            | Self::NotAColleur
+            | Self::InvalidHoraire(_)
+            | Self::UnknownSubject(_)
+            | Self::AlreadyAbsent(_)
+            | Self::AbsenceInOtherGroup(_)
+            | Self::InvalidRoster { .. } => true,
+            Self::MessageParsingFailed
+            | Self::ColleParsingFailed(_)
+            | Self::InvalidColloscope { .. }
+            | Self::InvalidWeeks { .. }
+            | Self::InvalidGhosts { .. }
+            | Self::NoPeriod(_)
+            | Self::UnsupportedDataVersion(..) => false,
+        }
+    }
+
+    /// How the user can fix the error
+    pub fn hint(&self) -> Option<String> {
+        match self {
+            Self::NoGroupInClass(guild_id, class, _) => {
+                GuildData::get_from_id(*guild_id).ok().and_then(|data| {
+                    data.class(Some(class))
+                        .ok()
+                        .map(|class| format!("groupes disponibles : {}", class.groups_summary()))
+                })
+            }
+            Self::ClassNotFound(guild_id, _) => GuildData::get_from_id(*guild_id)
+                .ok()
+                .map(|data| format!("classes disponibles : {}", data.classes_summary())),
+            Self::NoDataForGuild(_) => Some(
+                "un administrateur doit d'abord déposer les fichiers du colloscope".to_string(),
+            ),
+            Self::ProfNotFound(_) => {
+                Some("choisis un colleur parmi ceux proposés par l'autocomplétion".to_string())
+            }
+            Self::UnknownPermissionTarget(_) => Some(
+                "catégories : Consultation, Administration, Modération, Enseignement, ou le nom d'une commande"
+                    .to_string(),
+            ),
+            Self::OpenAdministration(_) => Some(
+                "autorise plutôt un rôle avec `/permissions autoriser_role`".to_string(),
+            ),
+            Self::PlaceNotFound | Self::PlaceAlreadyTaken(_) => {
+                Some("`/places_libres voir` liste les places encore libres".to_string())
+            }
+            Self::WeekNotFound(_) => Some(
+                "indique une date, par exemple 13-1-2025, ou un numéro de semaine du colloscope"
+                    .to_string(),
+            ),
+            Self::InvalidLock(_) => Some(
+                "par exemple `G1 3 M1+A2, G2 5 -` pour les colles M1 et A2 du groupe G1 en semaine 3 et aucune colle pour G2 en semaine 5"
+                    .to_string(),
+            ),
+            Self::NoJournal => Some(
+                "active-le avec `/mes_notes activer` ou note une colle avec `/mes_notes noter`"
+                    .to_string(),
+            ),
+            Self::NoMissedColle => Some("déclare d'abord ton absence avec `/absence`".to_string()),
+            Self::NotAColleur => Some(
+                "un administrateur doit lier ton compte à ton nom avec `/colleur lier`".to_string(),
+            ),
+            Self::UnknownSubject(_) => Some("par exemple M, Maths, P ou Physique".to_string()),
+            Self::GroupRequired => {
+                Some("ou indique-le une fois pour toutes avec `/groupe choisir`".to_string())
+            }
+            Self::CommandCanOnlyBeUsedInGuilds => {
+                Some("utilise-la dans un salon du serveur".to_string())
+            }
+            _ => None,
+        }
+    }
+
+    pub fn user_message(&self) -> String {
+        match self.hint() {
+            Some(hint) => format!("{self}, {hint}"),
+            None => self.to_string(),
+        }
+    }
+}
+
+/// Channel where unexpected errors of the guild are reported
+pub struct AdminChannel(pub ChannelId);
+
+impl SavedData for AdminChannel {
+    const FILE_NAME: &'static str = "admin_channel";
+
+    fn ser(&self) -> String {
+        self.0.to_string()
+    }
+
+    fn de(value: &str) -> Result<Self> {
+        Ok(Self(value.trim().parse()?))
+    }
+}
+
+impl AdminChannel {
+    /// Longest report that fits in a Discord message along with its header
+    const MAX_REPORT_LEN: usize = 1800;
+
+    pub async fn report(
+        guild_id: GuildId,
+        http: &Http,
+        header: &str,
+        report: &Report,
+    ) -> Result<()> {
+        let Some(channel) = Self::read(guild_id) else {
+            return Ok(());
+        };
+
+        let mut report = format!("{report:?}");
+        if report.len() > Self::MAX_REPORT_LEN {
+            let mut end = Self::MAX_REPORT_LEN;
+            while !report.is_char_boundary(end) {
+                end -= 1;
+            }
+            report.truncate(end);
+            report.push_str("\n…");
+        }
+
+        channel?
+            .0
+            .send_message(
+                http,
+                CreateMessage::new().content(format!("{header}\n```\n{report}\n```")),
+            )
+            .await?;
+        Ok(())
+    }
+}
+
+pub async fn on_error(error: FrameworkError<'_, Arc<GlobalData>, Report>) {
+    match error {
+        FrameworkError::Command { error, ctx, .. } => {
+            let span = logging::command_span(ctx).await;
+            if let Err(e) = handle_command_error(ctx, error).instrument(span).await {
+                error!(error = ?e, "failed to handle command error");
+            }
+            logging::command_failed(ctx).await;
+        }
+        FrameworkError::CommandCheckFailed { error, ctx, .. } => {
+            let result = match error {
+                Some(error) => handle_command_error(ctx, error).await,
+                None => {
+                    reply_ephemeral(
+                        ctx,
+                        "Tu ne peux pas utiliser cette commande ici".to_string(),
+                    )
+                    .await
+                }
+            };
+            if let Err(e) = result {
+                error!(error = ?e, "failed to handle command check error");
+            }
+        }
+        FrameworkError::ArgumentParse {
+            ctx, input, error, ..
+        } => {
+            warn!(input, error = %error, "failed to parse command argument");
+            let content = match input {
+                Some(input) => format!("L'argument « {input} » n'est pas valide"),
+                None => "Un argument de la commande n'est pas valide".to_string(),
+            };
+            if let Err(e) = reply_ephemeral(ctx, content).await {
+                error!(error = ?e, "failed to reply to argument error");
+            }
+        }
+        error => {
+            if let Err(e) = poise::builtins::on_error(error).await {
+                error!(error = ?e, "failed to handle framework error");
+            }
+        }
+    }
+}
+
+async fn handle_command_error(ctx: Context<'_>, error: Report) -> Result<()> {
+    if let Some(wattou_error) = error.downcast_ref::<WattouError>()
+        && wattou_error.is_user_error()
+    {
+        warn!(error = %wattou_error, "command failed");
+        return reply_ephemeral(ctx, wattou_error.user_message()).await;
+    }
+
+    let correlation_id = Uuid::new_v4().simple().to_string()[..8].to_string();
+    error!(
+        correlation_id,
+        command = %ctx.command().qualified_name,
+        error = ?error,
+        "unexpected command error"
+    );
+
+    reply_ephemeral(
+        ctx,
+        format!(
+            "Une erreur inattendue est survenue, les administrateurs ont été prévenus (référence `{correlation_id}`)"
+        ),
+    )
+    .await?;
+
+    if let Some(guild_id) = ctx.guild_id() {
+        let header = format!(
+            "⚠️ Erreur `{}` pendant `/{}` lancée par {}",
+            correlation_id,
+            ctx.command().qualified_name,
+            Mention::from(ctx.author().id)
+        );
+        AdminChannel::report(guild_id, ctx.http(), &header, &error).await?;
+    }
+    Ok(())
+}
+
+async fn reply_ephemeral(ctx: Context<'_>, content: String) -> Result<()> {
+    ctx.send(
+        CreateReply::default()
+            .ephemeral(true)
+            .content(format!("❌ {content}")),
+    )
+    .await?;
+    Ok(())
+}
@@ -0,0 +1,227 @@
+//! Exercise bank: exercises given in past colles, shared by the students with
+//! `/exercice partager` and found with `/exercice chercher` to prepare a colle with a colleur.
+//!
+//! Like the programmes, the text is saved with the other data of the guild and the files in the
+//! `exercices` folder of the guild.
+use crate::{
+    class::Class,
+    colle::ColleId,
+    error::WattouError,
+    group::Group,
+    guild_data::{GuildData, SavedData},
+    index::{ColleQuery, IndexedColle},
+    programme::{self, Programmes},
+    utils::{self, easy_comp_string, month_to_short_fr},
+};
+use color_eyre::Result;
+use serde::{Deserialize, Serialize};
+use serenity::all::{CreateAttachment, GuildId, UserId};
+use std::{fs, path::PathBuf, time::Duration};
+use time::OffsetDateTime;
+use tracing::warn;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Exercise {
+    pub id: u32,
+    pub class: String,
+    pub subject: char,
+    pub prof: String,
+    /// Unix timestamp of the start of the colle it was given in
+    pub start: i64,
+    /// [IndexedColle::key] of the colle it was given in
+    pub colle_key: String,
+    pub chapter: Option<String>,
+    pub text: String,
+    /// Name of the file saved in [Exercise::folder]
+    pub file: Option<String>,
+    pub author: UserId,
+}
+
+impl Exercise {
+    /// Longest text shown in the search results
+    const EXCERPT_LEN: usize = 120;
+
+    /// Exercise of the colle, the chapter defaults to the one of the programme of the week
+    pub fn new(
+        class: &Class,
+        indexed: &IndexedColle,
+        programmes: &Programmes,
+        chapter: Option<String>,
+        text: String,
+        author: UserId,
+    ) -> Self {
+        let chapter = chapter.or_else(|| {
+            programmes
+                .for_colle(class, &indexed.colle)
+                .and_then(|programme| programme.chapter())
+        });
+        Self {
+            id: 0,
+            class: class.name.to_string(),
+            subject: indexed.colle.id.0,
+            prof: indexed.colle.prof.name().to_string(),
+            start: indexed.colle.start.unix_timestamp(),
+            colle_key: indexed.key(),
+            chapter,
+            text,
+            file: None,
+            author,
+        }
+    }
+
+    /// Where the file of the exercise is saved
+    pub fn folder(&self, guild_id: GuildId) -> PathBuf {
+        GuildData::folder(guild_id)
+            .join("exercices")
+            .join(self.id.to_string())
+    }
+
+    /// e.g. : n°3 Maths avec Dupont, semaine du 13 Jan, Suites
+    pub fn title(&self) -> String {
+        let monday = programme::monday_of(
+            OffsetDateTime::from_unix_timestamp(self.start)
+                .unwrap_or(OffsetDateTime::UNIX_EPOCH)
+                .date(),
+        );
+        let mut title = format!(
+            "n°{} {} avec {}, semaine du {} {}",
+            self.id,
+            ColleId::subject_name(self.subject)
+                .map(str::to_string)
+                .unwrap_or_else(|| self.subject.to_string()),
+            self.prof,
+            monday.day(),
+            month_to_short_fr(monday.month())
+        );
+        if let Some(chapter) = &self.chapter {
+            title += &format!(", {chapter}");
+        }
+        title
+    }
+
+    /// Title and start of the text, shown in the search results
+    pub fn summary(&self) -> String {
+        format!(
+            "{} : {}",
+            self.title(),
+            utils::excerpt(&self.text, Self::EXCERPT_LEN)
+        )
+    }
+
+    pub fn message(&self) -> String {
+        format!("# Exercice {}\n{}", self.title(), self.text)
+    }
+
+    pub async fn attachment(&self, guild_id: GuildId) -> Option<CreateAttachment> {
+        let name = self.file.as_ref()?;
+        CreateAttachment::path(self.folder(guild_id).join(name))
+            .await
+            .inspect_err(|e| warn!(file = %name, error = %e, "missing exercise file"))
+            .ok()
+    }
+
+    /// Deletes the file of the exercise
+    pub fn remove_file(&self, guild_id: GuildId) {
+        let _ = fs::remove_dir_all(self.folder(guild_id));
+    }
+
+    fn matches(&self, filter: &ExerciseFilter) -> bool {
+        filter.prof.is_none_or(|prof| self.prof == prof)
+            && filter.subject.is_none_or(|subject| self.subject == subject)
+            && filter.chapter.is_none_or(|chapter| {
+                let chapter = easy_comp_string(chapter);
+                [self.chapter.as_deref().unwrap_or_default(), &self.text]
+                    .iter()
+                    .any(|s| easy_comp_string(s).contains(&chapter))
+            })
+    }
+}
+
+/// Filters of `/exercice chercher`, [None] matches everything
+#[derive(Debug, Default)]
+pub struct ExerciseFilter<'a> {
+    pub prof: Option<&'a str>,
+    pub subject: Option<char>,
+    /// Searched in the chapter and in the text
+    pub chapter: Option<&'a str>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Exercises {
+    next_id: u32,
+    exercises: Vec<Exercise>,
+}
+
+impl SavedData for Exercises {
+    const FILE_NAME: &'static str = "exercices.json";
+
+    fn ser(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+
+    fn de(value: &str) -> Result<Self> {
+        Ok(serde_json::from_str(value)?)
+    }
+}
+
+impl Exercises {
+    /// Reserves the id of a new exercise
+    pub fn next_id(&mut self) -> u32 {
+        self.next_id += 1;
+        self.next_id
+    }
+
+    pub fn add(&mut self, exercise: Exercise) {
+        self.exercises.push(exercise);
+    }
+
+    pub fn get(&self, id: u32) -> Result<&Exercise> {
+        Ok(self
+            .exercises
+            .iter()
+            .find(|exercise| exercise.id == id)
+            .ok_or(WattouError::ExerciseNotFound)?)
+    }
+
+    /// Exercises of the class matching the filter, most recent colle first
+    pub fn search(&self, class: &Class, filter: &ExerciseFilter) -> Vec<&Exercise> {
+        let mut exercises = self
+            .exercises
+            .iter()
+            .filter(|exercise| exercise.class == *class.name && exercise.matches(filter))
+            .collect::<Vec<_>>();
+        exercises.sort_by_key(|exercise| std::cmp::Reverse((exercise.start, exercise.id)));
+        exercises
+    }
+
+    /// Removes the exercise if it was shared by the user, or whoever shared it when [None]
+    pub fn remove(&mut self, id: u32, user_id: Option<UserId>) -> Result<Exercise> {
+        let position = self
+            .exercises
+            .iter()
+            .position(|exercise| {
+                exercise.id == id && user_id.is_none_or(|user_id| exercise.author == user_id)
+            })
+            .ok_or(WattouError::ExerciseNotFound)?;
+        Ok(self.exercises.remove(position))
+    }
+}
+
+/// How long after a colle its exercises can be shared
+pub const SHARING_WINDOW: Duration = Duration::from_secs(60 * 60 * 24 * 90);
+
+/// Colles of the group over in the last [SHARING_WINDOW], most recent first
+pub fn shareable_colles<'a>(class: &'a Class, group: &Group) -> Vec<&'a IndexedColle> {
+    let now = OffsetDateTime::now_utc();
+    let mut colles = class
+        .query(
+            &ColleQuery::new()
+                .group(&group.id)
+                .range(now - SHARING_WINDOW, now),
+        )
+        .into_iter()
+        .filter(|indexed| indexed.colle.end <= now)
+        .collect::<Vec<_>>();
+    colles.reverse();
+    colles
+}
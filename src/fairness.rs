@@ -0,0 +1,248 @@
+//! Fairness report of a colloscope: how the colles, the colleurs and the gaps between colles are
+//! shared between the groups of a period, with the groups that stand out from the others.
+//!
+//! Shown by `/equite` and by the offline validator.
+use crate::{class::Period, colle::ColleId, group::Group, weeks::DATE_FORMAT};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write,
+};
+use time::Date;
+
+/// What the colloscope gives to one group during the period
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GroupReport {
+    /// e.g. : G7 (Rouge)
+    pub group: String,
+    pub colles_by_subject: BTreeMap<char, usize>,
+    /// By subject then colleur, a colleur can teach several subjects
+    pub colles_by_prof: BTreeMap<(char, String), usize>,
+    /// Shortest and longest number of days between two colles of the subject
+    pub gaps: BTreeMap<char, (i64, i64)>,
+    /// Days with several colles
+    pub busy_days: Vec<Date>,
+}
+
+impl GroupReport {
+    pub fn new(group: &Group) -> Self {
+        let mut colles = group.colles.iter().collect::<Vec<_>>();
+        colles.sort_by_key(|colle| colle.start);
+
+        let mut colles_by_subject = BTreeMap::new();
+        let mut colles_by_prof = BTreeMap::new();
+        let mut previous_by_subject = BTreeMap::new();
+        let mut gaps = BTreeMap::new();
+        let mut days = BTreeMap::new();
+        for colle in colles {
+            let subject = colle.id.0;
+            *colles_by_subject.entry(subject).or_insert(0) += 1;
+            *colles_by_prof
+                .entry((subject, colle.prof.name().to_string()))
+                .or_insert(0) += 1;
+            *days.entry(colle.start.date()).or_insert(0) += 1;
+
+            let day = colle.start.date();
+            if let Some(previous) = previous_by_subject.insert(subject, day) {
+                let gap = (day - previous).whole_days();
+                let (min, max) = gaps.entry(subject).or_insert((gap, gap));
+                *min = gap.min(*min);
+                *max = gap.max(*max);
+            }
+        }
+
+        Self {
+            group: group.to_string(),
+            colles_by_subject,
+            colles_by_prof,
+            gaps,
+            busy_days: days
+                .into_iter()
+                .filter(|(_, count)| *count > 1)
+                .map(|(day, _)| day)
+                .collect(),
+        }
+    }
+
+    /// One line per kind of figure
+    pub fn to_lines(&self) -> Vec<String> {
+        let mut lines = vec![
+            format!(
+                "colles : {}",
+                join(
+                    self.colles_by_subject
+                        .iter()
+                        .map(|(subject, count)| format!("{} {count}", subject_name(*subject)))
+                )
+            ),
+            format!(
+                "colleurs : {}",
+                join(
+                    self.colles_by_prof
+                        .iter()
+                        .map(|((_, prof), count)| format!("{prof} {count}"))
+                )
+            ),
+        ];
+        if !self.gaps.is_empty() {
+            lines.push(format!(
+                "écarts : {}",
+                join(self.gaps.iter().map(|(subject, (min, max))| format!(
+                    "{} {min} à {max} j",
+                    subject_name(*subject)
+                )))
+            ));
+        }
+        if !self.busy_days.is_empty() {
+            lines.push(format!(
+                "plusieurs colles le même jour : {}",
+                join(
+                    self.busy_days
+                        .iter()
+                        .map(|day| day.format(DATE_FORMAT).unwrap_or_default())
+                )
+            ));
+        }
+        lines
+    }
+}
+
+fn subject_name(subject: char) -> String {
+    ColleId::subject_name(subject)
+        .map(str::to_string)
+        .unwrap_or_else(|| subject.to_string())
+}
+
+fn join(items: impl Iterator<Item = String>) -> String {
+    items.collect::<Vec<_>>().join(", ")
+}
+
+/// Fairness of the colloscope of a period
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FairnessReport {
+    pub period: String,
+    pub groups: Vec<GroupReport>,
+    /// Groups far from the other groups, e.g. with fewer colles of a subject
+    pub outliers: Vec<String>,
+}
+
+impl FairnessReport {
+    /// Share of the average number of colles a group can be away from it without standing out
+    const COUNT_TOLERANCE: f64 = 0.2;
+
+    /// Report of the groups of the period, ghosts for the whole period do not count
+    pub fn new(period: &Period) -> Self {
+        let groups = period
+            .groups
+            .iter()
+            .filter(|group| {
+                !period
+                    .ghosts
+                    .iter()
+                    .any(|ghost| ghost.group_id == group.id && ghost.since.is_none())
+            })
+            .map(GroupReport::new)
+            .collect::<Vec<_>>();
+
+        Self {
+            period: period.name.to_string(),
+            outliers: Self::outliers(&groups),
+            groups,
+        }
+    }
+
+    fn outliers(groups: &[GroupReport]) -> Vec<String> {
+        let mut outliers = vec![];
+        if groups.len() < 2 {
+            return outliers;
+        }
+
+        let subjects = groups
+            .iter()
+            .flat_map(|report| report.colles_by_subject.keys().copied())
+            .collect::<BTreeSet<_>>();
+        for subject in subjects {
+            let counts = groups
+                .iter()
+                .map(|report| {
+                    report
+                        .colles_by_subject
+                        .get(&subject)
+                        .copied()
+                        .unwrap_or_default()
+                })
+                .collect::<Vec<_>>();
+            let average = counts.iter().sum::<usize>() as f64 / counts.len() as f64;
+            let tolerance = (average * Self::COUNT_TOLERANCE).max(1.0);
+            for (report, count) in groups.iter().zip(counts) {
+                if (count as f64 - average).abs() > tolerance {
+                    outliers.push(format!(
+                        "{} a {count} colle(s) de {} contre {average:.1} en moyenne",
+                        report.group,
+                        subject_name(subject)
+                    ));
+                }
+            }
+
+            // A group seeing the same colleur much more than the others
+            let shares = groups
+                .iter()
+                .filter_map(|report| {
+                    let total = report.colles_by_subject.get(&subject).copied()?;
+                    let ((_, prof), count) = report
+                        .colles_by_prof
+                        .iter()
+                        .filter(|((prof_subject, _), _)| *prof_subject == subject)
+                        .max_by_key(|(_, count)| **count)?;
+                    (total > 0).then(|| (report, prof, *count as f64 / total as f64))
+                })
+                .collect::<Vec<_>>();
+            let average_share =
+                shares.iter().map(|(_, _, share)| share).sum::<f64>() / shares.len().max(1) as f64;
+            for (report, prof, share) in shares {
+                if share > 0.5 && share > average_share * 1.5 {
+                    outliers.push(format!(
+                        "{} a {:.0} % de ses colles de {} avec {prof}",
+                        report.group,
+                        share * 100.0,
+                        subject_name(subject)
+                    ));
+                }
+            }
+        }
+
+        for report in groups {
+            if !report.busy_days.is_empty() {
+                outliers.push(format!(
+                    "{} a plusieurs colles le même jour {} fois",
+                    report.group,
+                    report.busy_days.len()
+                ));
+            }
+        }
+        outliers
+    }
+
+    /// Figures of each group then the outliers
+    pub fn to_markdown(&self) -> String {
+        let mut s = String::new();
+        for report in &self.groups {
+            let _ = writeln!(s, "**{}**", report.group);
+            for line in report.to_lines() {
+                let _ = writeln!(s, "- {line}");
+            }
+        }
+        s += &self.outliers_markdown();
+        s
+    }
+
+    pub fn outliers_markdown(&self) -> String {
+        if self.outliers.is_empty() {
+            return "✅ Aucun groupe ne se démarque".to_string();
+        }
+        let mut s = "Points d'attention :".to_string();
+        for outlier in &self.outliers {
+            let _ = write!(s, "\n⚠️ {outlier}");
+        }
+        s
+    }
+}
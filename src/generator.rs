@@ -0,0 +1,597 @@
+//! Colloscope generator: gives the colles of the `colles` file to the groups, week by week, and
+//! writes the `colloscope` file with `/colloscope generer`.
+//!
+//! Each colle id of a week goes to at most one group. A group never has two colles on the same
+//! day nor two colles of the same subject in a week, and the search then avoids colles of the same
+//! subject two weeks in a row and balances the colleurs and the subjects between the groups.
+//! Locked cells are kept as given and ghost groups get no colles.
+use crate::{
+    class::{Class, Period},
+    colle::ColleData,
+    error::WattouError,
+    group::GroupId,
+    guild_data::WeekId,
+    weeks::Weeks,
+};
+use color_eyre::Result;
+use std::collections::{HashMap, HashSet};
+use time::Date;
+
+/// One colle id during one week, given to at most one group
+#[derive(Debug, Clone)]
+struct Unit {
+    id: String,
+    /// Index in [Generator::subjects]
+    subject: usize,
+    /// Index in [Generator::profs]
+    prof: usize,
+    days: Vec<Date>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GeneratorGroup {
+    pub id: GroupId,
+    pub name: Option<String>,
+    /// Ghost groups get no colles
+    pub ghost: bool,
+}
+
+/// Colles a group must have during a week, e.g. `G1 3 M1+A2`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lock {
+    /// Index in the groups of the generator
+    pub group: usize,
+    pub week: WeekId,
+    pub colles: Vec<String>,
+}
+
+/// What the generated colloscope breaks, see [Cost::total]
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Cost {
+    /// Two colles of a group on the same day, or of the same subject in a week
+    pub conflicts: usize,
+    /// Colles of the same subject two weeks in a row
+    pub consecutive: usize,
+    /// Colles given to no group
+    pub unassigned: usize,
+    /// Distance of the groups to the average number of colles by colleur and by subject
+    pub imbalance: f64,
+    assigned: usize,
+}
+
+impl Cost {
+    fn total(&self) -> f64 {
+        self.conflicts as f64 * 1000.0
+            + self.consecutive as f64 * 20.0
+            + self.unassigned as f64 * 10.0
+            + self.imbalance * 5.0
+    }
+
+    /// 100 when every group has as many colles of each subject and with each colleur as the
+    /// others
+    pub fn fairness_score(&self) -> f64 {
+        if self.assigned == 0 {
+            return 100.0;
+        }
+        100.0 * (1.0 - self.imbalance / (2.0 * self.assigned as f64)).clamp(0.0, 1.0)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Generated {
+    /// Content of the `colloscope` file
+    pub colloscope: String,
+    pub cost: Cost,
+}
+
+#[derive(Debug, Clone)]
+pub struct Generator {
+    groups: Vec<GeneratorGroup>,
+    /// Units of each week, the first week being week 1
+    units: Vec<Vec<Unit>>,
+    subjects: Vec<char>,
+    profs: Vec<String>,
+    /// Colles each group should have with each colleur, [None] for as many as the others
+    visits: Option<usize>,
+}
+
+impl GeneratorGroup {
+    /// Groups of the period, ghosts for the whole period included, extended or truncated to
+    /// `count` groups
+    pub fn of_period(period: &Period, count: Option<usize>) -> Vec<Self> {
+        let mut groups = period
+            .groups
+            .iter()
+            .map(|group| Self {
+                id: group.id.clone(),
+                name: group.name.clone(),
+                ghost: period
+                    .ghosts
+                    .iter()
+                    .any(|ghost| ghost.group_id == group.id && ghost.since.is_none()),
+            })
+            .collect::<Vec<_>>();
+        let Some(count) = count else {
+            return groups;
+        };
+        groups.truncate(count);
+
+        // New groups follow the ids of the others, e.g. G13 after G12
+        let prefix = groups
+            .last()
+            .map(|group| {
+                group
+                    .id
+                    .trim_end_matches(|c: char| c.is_ascii_digit())
+                    .to_string()
+            })
+            .unwrap_or_default();
+        for number in groups.len() + 1..=count {
+            groups.push(Self {
+                id: format!("{prefix}{number}").into(),
+                name: None,
+                ghost: false,
+            });
+        }
+        groups
+    }
+}
+
+impl Generator {
+    /// Steps of the search, enough for a year of a class of about twenty groups
+    const ITERATIONS: usize = 40_000;
+    const START_TEMPERATURE: f64 = 50.0;
+    const END_TEMPERATURE: f64 = 0.05;
+
+    pub fn new(
+        colle_list: &HashMap<String, ColleData>,
+        weeks: &Weeks,
+        groups: Vec<GeneratorGroup>,
+        visits: Option<usize>,
+    ) -> Self {
+        let mondays = weeks.mondays();
+        let mut ids = colle_list.keys().collect::<Vec<_>>();
+        ids.sort();
+
+        let (mut subjects, mut profs) = (vec![], vec![]);
+        let units = (1..=weeks.len())
+            .map(|week| {
+                ids.iter()
+                    .filter_map(|id| {
+                        let data = &colle_list[*id];
+                        let colles = data.colles(week, &mondays);
+                        let first = colles.first()?;
+                        Some(Unit {
+                            id: id.to_string(),
+                            subject: index(&mut subjects, data.id.0),
+                            prof: index(&mut profs, first.prof.name().to_string()),
+                            days: colles.iter().map(|colle| colle.start.date()).collect(),
+                        })
+                    })
+                    .collect()
+            })
+            .collect();
+
+        Self {
+            groups,
+            units,
+            subjects,
+            profs,
+            visits,
+        }
+    }
+
+    /// Parses locks separated by `,`, each being a group id, a week number or name and the
+    /// colles of the cell, e.g. `G1 3 M1+A2, G2 rentrée -`
+    pub fn parse_locks(&self, s: &str, weeks: &Weeks) -> Result<Vec<Lock>> {
+        let mut locks: Vec<Lock> = vec![];
+        for lock in s.split([',', ';']).map(str::trim).filter(|l| !l.is_empty()) {
+            let error = |reason: String| WattouError::InvalidLock(format!("« {lock} » : {reason}"));
+            let words = lock.split_whitespace().collect::<Vec<_>>();
+            let [group_id, week, cell] = words[..] else {
+                Err(error(
+                    "il faut un groupe, une semaine et des colles".to_string(),
+                ))?
+            };
+
+            let group = self
+                .groups
+                .iter()
+                .position(|group| &*group.id == group_id)
+                .ok_or_else(|| error(format!("groupe {group_id} inconnu")))?;
+            if self.groups[group].ghost {
+                Err(error(format!("le groupe {group_id} est fantôme")))?
+            }
+            let week = weeks
+                .find(week)
+                .ok_or_else(|| error(format!("semaine {week} inconnue")))?;
+            if locks
+                .iter()
+                .any(|other| other.group == group && other.week == week)
+            {
+                Err(error("cellule déjà verrouillée".to_string()))?
+            }
+
+            let colles = if cell == Class::EMPTY_CELL {
+                vec![]
+            } else {
+                cell.split('+').map(str::to_string).collect::<Vec<_>>()
+            };
+            for colle in &colles {
+                if !self.units[week - 1].iter().any(|unit| unit.id == *colle) {
+                    Err(error(format!("pas de colle {colle} en semaine {week}")))?
+                }
+                if locks
+                    .iter()
+                    .any(|other| other.week == week && other.colles.contains(colle))
+                {
+                    Err(error(format!("colle {colle} déjà verrouillée")))?
+                }
+            }
+            locks.push(Lock {
+                group,
+                week,
+                colles,
+            });
+        }
+        Ok(locks)
+    }
+
+    /// Searches a colloscope from a rotation of the groups, `seed` changes the result
+    pub fn generate(&self, locks: &[Lock], seed: u64) -> Generated {
+        let mut rng = Rng::new(seed);
+        let active = (0..self.groups.len())
+            .filter(|group| !self.groups[*group].ghost)
+            .collect::<Vec<_>>();
+        let locked = locks
+            .iter()
+            .map(|lock| (lock.group, lock.week - 1))
+            .collect::<HashSet<_>>();
+        // Groups that can get a colle during each week
+        let free = (0..self.units.len())
+            .map(|week| {
+                active
+                    .iter()
+                    .copied()
+                    .filter(|group| !locked.contains(&(*group, week)))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        let mut pinned = vec![];
+        let mut assignment = vec![];
+        for (week, units) in self.units.iter().enumerate() {
+            let mut week_pinned = vec![false; units.len()];
+            let mut week_assignment = vec![None; units.len()];
+            let mut rank_by_subject = vec![0; self.subjects.len()];
+            for (i, unit) in units.iter().enumerate() {
+                if let Some(lock) = locks
+                    .iter()
+                    .find(|lock| lock.week - 1 == week && lock.colles.contains(&unit.id))
+                {
+                    week_pinned[i] = true;
+                    week_assignment[i] = Some(lock.group);
+                    continue;
+                }
+                // The groups take turns on the colles of each subject
+                let rank = &mut rank_by_subject[unit.subject];
+                if !free[week].is_empty() {
+                    week_assignment[i] = Some(free[week][(*rank + week) % free[week].len()]);
+                }
+                *rank += 1;
+            }
+            pinned.push(week_pinned);
+            assignment.push(week_assignment);
+        }
+
+        let mut current = self.cost(&assignment).total();
+        let mut best = (current, assignment.clone());
+        for step in 0..Self::ITERATIONS {
+            let temperature = Self::START_TEMPERATURE
+                * (Self::END_TEMPERATURE / Self::START_TEMPERATURE)
+                    .powf(step as f64 / Self::ITERATIONS as f64);
+            let week = rng.below(self.units.len());
+            let units = self.units[week].len();
+            if units == 0 {
+                continue;
+            }
+            let unit = rng.below(units);
+            if pinned[week][unit] {
+                continue;
+            }
+
+            let previous = assignment[week].clone();
+            if rng.below(2) == 0 {
+                let other = rng.below(units);
+                if other == unit || pinned[week][other] {
+                    continue;
+                }
+                assignment[week].swap(unit, other);
+            } else {
+                let choice = rng.below(free[week].len() + 1);
+                assignment[week][unit] = free[week].get(choice).copied();
+            }
+
+            let cost = self.cost(&assignment).total();
+            if cost <= current || rng.unit() < ((current - cost) / temperature).exp() {
+                current = cost;
+                if cost < best.0 {
+                    best = (cost, assignment.clone());
+                }
+            } else {
+                assignment[week] = previous;
+            }
+        }
+
+        Generated {
+            colloscope: self.to_file(&best.1),
+            cost: self.cost(&best.1),
+        }
+    }
+
+    fn cost(&self, assignment: &[Vec<Option<usize>>]) -> Cost {
+        let (groups, subjects, profs, weeks) = (
+            self.groups.len(),
+            self.subjects.len(),
+            self.profs.len(),
+            self.units.len(),
+        );
+        let mut cost = Cost::default();
+        let mut by_prof = vec![0usize; groups * profs];
+        let mut by_subject = vec![0usize; groups * subjects];
+        let mut prof_totals = vec![0usize; profs];
+        let mut has_subject = vec![false; groups * subjects * weeks];
+
+        for (week, units) in self.units.iter().enumerate() {
+            let mut days: Vec<(usize, Date)> = vec![];
+            for (unit, group) in units.iter().zip(&assignment[week]) {
+                let Some(group) = *group else {
+                    cost.unassigned += 1;
+                    continue;
+                };
+                cost.assigned += 1;
+                for day in &unit.days {
+                    if days.contains(&(group, *day)) {
+                        cost.conflicts += 1;
+                    } else {
+                        days.push((group, *day));
+                    }
+                }
+                let week_subject = (group * subjects + unit.subject) * weeks + week;
+                if has_subject[week_subject] {
+                    cost.conflicts += 1;
+                }
+                has_subject[week_subject] = true;
+                by_prof[group * profs + unit.prof] += 1;
+                by_subject[group * subjects + unit.subject] += 1;
+                prof_totals[unit.prof] += 1;
+            }
+        }
+
+        let active = (0..groups)
+            .filter(|group| !self.groups[*group].ghost)
+            .collect::<Vec<_>>();
+        for group in &active {
+            for subject in 0..subjects {
+                let start = (group * subjects + subject) * weeks;
+                cost.consecutive += has_subject[start..start + weeks]
+                    .windows(2)
+                    .filter(|pair| pair[0] && pair[1])
+                    .count();
+            }
+        }
+
+        let count = active.len().max(1) as f64;
+        for (prof, total) in prof_totals.iter().enumerate() {
+            let target = self
+                .visits
+                .map_or(*total as f64 / count, |visits| visits as f64);
+            cost.imbalance += active
+                .iter()
+                .map(|group| (by_prof[group * profs + prof] as f64 - target).abs())
+                .sum::<f64>();
+        }
+        for subject in 0..subjects {
+            let average = active
+                .iter()
+                .map(|group| by_subject[group * subjects + subject])
+                .sum::<usize>() as f64
+                / count;
+            cost.imbalance += active
+                .iter()
+                .map(|group| (by_subject[group * subjects + subject] as f64 - average).abs())
+                .sum::<f64>();
+        }
+        cost
+    }
+
+    /// The `colloscope` file, one column per week
+    fn to_file(&self, assignment: &[Vec<Option<usize>>]) -> String {
+        let header = (1..=self.units.len())
+            .map(|week| week.to_string())
+            .collect::<Vec<_>>()
+            .join(" ");
+        let lines = self.groups.iter().enumerate().map(|(group, declaration)| {
+            let cells = self
+                .units
+                .iter()
+                .zip(assignment)
+                .map(|(units, week)| {
+                    let colles = units
+                        .iter()
+                        .zip(week)
+                        .filter(|(_, assigned)| **assigned == Some(group))
+                        .map(|(unit, _)| unit.id.as_str())
+                        .collect::<Vec<_>>();
+                    if colles.is_empty() {
+                        Class::EMPTY_CELL.to_string()
+                    } else {
+                        colles.join("+")
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join(" ");
+            match &declaration.name {
+                Some(name) => format!("[{} {name}] {cells}", declaration.id),
+                None => format!("[{}] {cells}", declaration.id),
+            }
+        });
+        std::iter::once(header)
+            .chain(lines)
+            .collect::<Vec<_>>()
+            .join("\n")
+            + "\n"
+    }
+}
+
+/// Position of the value, added at the end if missing
+fn index<T: PartialEq>(values: &mut Vec<T>, value: T) -> usize {
+    match values.iter().position(|v| *v == value) {
+        Some(i) => i,
+        None => {
+            values.push(value);
+            values.len() - 1
+        }
+    }
+}
+
+/// Xorshift, the search only needs reproducible noise
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        // Xorshift stays at 0 forever
+        Self(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n.max(1) as u64) as usize
+    }
+
+    /// In `[0, 1)`
+    fn unit(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prof::Prof;
+    use serenity::all::GuildId;
+    use std::{fs, path::PathBuf, sync::Arc};
+
+    const WEEKS: &str = "2-9-2024\n9-9-2024\n16-9-2024\n23-9-2024\n";
+    /// Two colles of each subject every week, on four different days
+    const COLLES: &str = "M1 Dupont Lu 17h-18h (201)\nM2 Martin Ma 17h-18h (202)\n\
+        A1 Smith Me 14h-15h (101)\nA2 Jones Je 14h-15h (102)\n";
+
+    fn groups() -> Vec<GeneratorGroup> {
+        ["G1", "G2", "G3"]
+            .into_iter()
+            .map(|id| GeneratorGroup {
+                id: id.into(),
+                name: None,
+                ghost: id == "G3",
+            })
+            .collect()
+    }
+
+    fn generator() -> (Generator, Weeks) {
+        let weeks = Weeks::parse(WEEKS).unwrap();
+        let colle_list = ColleData::parse_list(COLLES, &mut HashMap::new()).unwrap();
+        (Generator::new(&colle_list, &weeks, groups(), None), weeks)
+    }
+
+    /// Cells of the group in the generated file, one per week
+    fn cells<'a>(colloscope: &'a str, group_id: &str) -> Vec<&'a str> {
+        let prefix = format!("[{group_id}]");
+        colloscope
+            .lines()
+            .find_map(|line| line.strip_prefix(prefix.as_str()))
+            .unwrap()
+            .split_whitespace()
+            .collect()
+    }
+
+    /// Folder of a period with the fixture and the generated colloscope
+    fn period_folder(name: &str, colloscope: &str) -> PathBuf {
+        let folder =
+            std::env::temp_dir().join(format!("wattou-generator-{}-{name}", std::process::id()));
+        fs::create_dir_all(&folder).unwrap();
+        for (file, content) in [
+            (Class::FILE_NAME_WEEKS_INFO, WEEKS),
+            (Class::FILE_NAME_COLLE_LIST, COLLES),
+            (Class::FILE_NAME_COLLOSCOPE, colloscope),
+            (Class::FILE_NAME_GHOSTS_GROUPS, "G3\n"),
+        ] {
+            fs::write(folder.join(file), content).unwrap();
+        }
+        folder
+    }
+
+    #[test]
+    fn keeps_locked_cells() {
+        let (generator, weeks) = generator();
+        let locks = generator.parse_locks("G1 2 A1+M2, G2 3 -", &weeks).unwrap();
+        let generated = generator.generate(&locks, 1);
+
+        assert_eq!(cells(&generated.colloscope, "G1")[1], "A1+M2");
+        assert_eq!(cells(&generated.colloscope, "G2")[2], Class::EMPTY_CELL);
+    }
+
+    #[test]
+    fn ghosts_get_no_colles() {
+        let (generator, _) = generator();
+        let generated = generator.generate(&[], 1);
+
+        assert!(
+            cells(&generated.colloscope, "G3")
+                .iter()
+                .all(|cell| *cell == Class::EMPTY_CELL)
+        );
+    }
+
+    #[test]
+    fn finds_an_assignment_without_conflicts() {
+        let (generator, _) = generator();
+        let cost = generator.generate(&[], 1).cost;
+
+        assert_eq!(cost.conflicts, 0);
+    }
+
+    #[test]
+    fn same_seed_same_colloscope() {
+        let (generator, _) = generator();
+
+        assert_eq!(
+            generator.generate(&[], 7).colloscope,
+            generator.generate(&[], 7).colloscope
+        );
+    }
+
+    #[test]
+    fn output_parses_as_a_colloscope() {
+        let (generator, _) = generator();
+        let generated = generator.generate(&[], 1);
+        let folder = period_folder("parse", &generated.colloscope);
+
+        let mut profs: HashMap<Arc<str>, Arc<Prof>> = HashMap::new();
+        let class = Class::new(GuildId::new(1), "MPSI".into(), folder.clone(), &mut profs);
+        fs::remove_dir_all(folder).unwrap();
+
+        let class = class.unwrap();
+        let period = &class.periods[0];
+        assert_eq!(period.groups.len(), 3);
+        assert_eq!(period.ghosts.len(), 1);
+        // Four colles a week, some of them left to no group
+        assert_eq!(period.index.len(), 4 * 4 - generated.cost.unassigned);
+    }
+}
@@ -0,0 +1,180 @@
+//! Ghost groups: groups of the colloscope without students, listed in the `ghosts` file.
+//!
+//! Each line is the id of a group, optionally followed by the first day the group is a ghost and
+//! by `libre` when its colles become places libres that students can claim, e.g.
+//! `G7 13-1-2025 libre`. Without a date, the group is a ghost for the whole period.
+use crate::{
+    class::Class,
+    colle::ColleStringFormat,
+    error::WattouError,
+    group::{Group, GroupId},
+    guild_data::SavedData,
+    index::IndexedColle,
+    utils::write_atomic,
+    weeks::{DATE_FORMAT, strip_comment},
+};
+use color_eyre::Result;
+use serde::{Deserialize, Serialize};
+use serenity::all::UserId;
+use std::{collections::BTreeMap, fs, path::Path};
+use time::{Date, OffsetDateTime};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ghost {
+    pub group_id: GroupId,
+    /// First day without students, [None] for the whole period
+    pub since: Option<Date>,
+    /// Whether the colles of the group can be claimed by students of other groups
+    pub open_places: bool,
+}
+
+impl Ghost {
+    pub const OPEN_PLACES_KEYWORD: &'static str = "libre";
+
+    /// Parses the `ghosts` file, lines whose id is not one of `groups` are skipped and their id
+    /// returned, e.g. a group removed from the colloscope
+    pub fn parse_file(s: &str, groups: &[Group]) -> Result<(Vec<Self>, Vec<String>)> {
+        let (mut ghosts, mut unknown): (Vec<Self>, _) = (Vec::new(), Vec::new());
+        for (i, line) in s.lines().enumerate() {
+            let Some(line) = strip_comment(line) else {
+                continue;
+            };
+            let error = |reason: String| WattouError::InvalidGhosts {
+                line: i + 1,
+                reason,
+            };
+
+            let mut words = line.split_whitespace();
+            let id = words.next().unwrap_or_default();
+            let Some(group) = groups.iter().find(|group| &*group.id == id) else {
+                unknown.push(id.to_string());
+                continue;
+            };
+            if ghosts.iter().any(|ghost| ghost.group_id == group.id) {
+                Err(error(format!("groupe {id} déjà fantôme")))?
+            }
+
+            let mut ghost = Self {
+                group_id: group.id.clone(),
+                since: None,
+                open_places: false,
+            };
+            for word in words {
+                if word == Self::OPEN_PLACES_KEYWORD {
+                    ghost.open_places = true;
+                } else {
+                    ghost.since = Some(
+                        Date::parse(word, DATE_FORMAT)
+                            .map_err(|e| error(format!("date « {word} » invalide ({e})")))?,
+                    );
+                }
+            }
+            ghosts.push(ghost);
+        }
+        Ok((ghosts, unknown))
+    }
+
+    /// Line of the `ghosts` file
+    pub fn to_line(&self) -> String {
+        let mut line = self.group_id.to_string();
+        if let Some(since) = self.since {
+            line += &format!(" {}", since.format(DATE_FORMAT).unwrap());
+        }
+        if self.open_places {
+            line += &format!(" {}", Self::OPEN_PLACES_KEYWORD);
+        }
+        line
+    }
+
+    /// Whether the group has no students on that day
+    pub fn covers(&self, date: Date) -> bool {
+        self.since.is_none_or(|since| date >= since)
+    }
+
+    /// Replaces the line of the group in the `ghosts` file of `folder`, or removes it when
+    /// `ghost` is [None], the other lines and comments are kept as is
+    pub fn write(folder: &Path, group_id: &str, ghost: Option<&Self>) -> Result<()> {
+        let path = folder.join(Class::FILE_NAME_GHOSTS_GROUPS);
+        let content = fs::read_to_string(&path).unwrap_or_default();
+        let mut lines = content
+            .lines()
+            .filter(|line| {
+                strip_comment(line)
+                    .is_none_or(|line| line.split_whitespace().next() != Some(group_id))
+            })
+            .map(str::to_string)
+            .collect::<Vec<_>>();
+        lines.extend(ghost.map(Self::to_line));
+        write_atomic(&path, &(lines.join("\n") + "\n"))
+    }
+}
+
+/// Places libres claimed by students, by class then by place
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct OpenPlaces {
+    claims: BTreeMap<String, BTreeMap<String, UserId>>,
+}
+
+impl SavedData for OpenPlaces {
+    const FILE_NAME: &'static str = "places_libres.json";
+
+    fn ser(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+
+    fn de(value: &str) -> Result<Self> {
+        Ok(serde_json::from_str(value)?)
+    }
+}
+
+impl OpenPlaces {
+    pub fn claimant(&self, class: &Class, indexed: &IndexedColle) -> Option<UserId> {
+        self.claims
+            .get(class.name.as_ref())?
+            .get(&indexed.key())
+            .copied()
+    }
+
+    /// Gives the place to the user, fails if someone else has it
+    pub fn claim(&mut self, class: &Class, indexed: &IndexedColle, user_id: UserId) -> Result<()> {
+        let claims = self.claims.entry(class.name.to_string()).or_default();
+        match claims.get(&indexed.key()) {
+            Some(claimant) if *claimant != user_id => Err(WattouError::PlaceAlreadyTaken(
+                indexed.colle.format(ColleStringFormat::Explicit, vec![]),
+            ))?,
+            _ => {
+                claims.insert(indexed.key(), user_id);
+            }
+        }
+        Ok(())
+    }
+
+    /// Gives the place back, fails if the user does not have it
+    pub fn release(
+        &mut self,
+        class: &Class,
+        indexed: &IndexedColle,
+        user_id: UserId,
+    ) -> Result<()> {
+        let claims = self.claims.entry(class.name.to_string()).or_default();
+        if claims.get(&indexed.key()) != Some(&user_id) {
+            Err(WattouError::PlaceNotClaimed)?
+        }
+        claims.remove(&indexed.key());
+        Ok(())
+    }
+
+    /// Forgets the places of colles that are over
+    pub fn remove_past(&mut self) {
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        for claims in self.claims.values_mut() {
+            claims.retain(|key, _| {
+                key.rsplit('-')
+                    .next()
+                    .and_then(|start| start.parse::<i64>().ok())
+                    .is_some_and(|start| start > now)
+            });
+        }
+        self.claims.retain(|_, claims| !claims.is_empty());
+    }
+}
@@ -2,31 +2,41 @@ use crate::colle::Colle;
 use color_eyre::Result;
 use ics::ICalendar;
 use serenity::all::GuildId;
-use time::OffsetDateTime;
+use std::{
+    fmt::{self, Display, Formatter},
+    sync::Arc,
+};
 
-pub type GroupId = usize;
+/// Declared in the colloscope, e.g. `G7`, or the number of the line of the group
+pub type GroupId = Arc<str>;
 
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct Group {
     pub guild_id: GuildId,
+    pub class: Arc<str>,
     pub id: GroupId,
+    /// e.g. : Trinôme Rouge
+    pub name: Option<String>,
     pub colles: Vec<Colle>,
 }
 
-impl Group {
-    pub fn get_next_colles<'a>(&'a self, limit: usize) -> Vec<&'a Colle> {
-        let now = OffsetDateTime::now_utc();
-        self.colles
-            .iter()
-            .filter(|colle| colle.end > now)
-            .collect::<Vec<_>>()[..limit]
-            .into()
+impl Display for Group {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match &self.name {
+            Some(name) => write!(f, "{} ({name})", self.id),
+            None => f.write_str(&self.id),
+        }
     }
+}
 
-    pub fn ics_calendar<'a>(&self) -> Result<String> {
+impl Group {
+    pub fn ics_calendar(&self) -> Result<String> {
         let mut calendar = ICalendar::new(
             "2.0",
-            format!("-//Wattou//Calendrier de colle groupe {}//FR", self.id),
+            format!(
+                "-//Wattou//Calendrier de colle {} groupe {}//FR",
+                self.class, self
+            ),
         );
 
         // create event which contains the information regarding the conference
@@ -1,236 +1,298 @@
 use crate::{
     Context, GLOBAL_DATA,
-    colle::{Colle, ColleData},
-    debug,
-    error::{ColleParsingError, WattouError},
-    group::{Group, GroupId},
-    recurrent_message::{SemaineTPMessage, ToutesLesCollesMessage},
+    class::Class,
+    config::{GuildConfig, config},
+    error::WattouError,
+    group::Group,
+    prof::Prof,
+    recurrent_message::{ClassBoards, SemaineTPMessage, ToutesLesCollesMessage},
+    roster::Roster,
+    storage::{Record, storage},
     subscriber::{SubscribePlan, Subscribers},
-    utils::{Jour, month_to_short_fr},
+    utils::{easy_comp_string, month_to_short_fr, now_in_timezone},
 };
 use color_eyre::Result;
 use serenity::all::{GuildId, Http};
-use std::{fs, num::ParseIntError, path::PathBuf, sync::Arc};
-use time::{Date, Duration, OffsetDateTime, Weekday, macros::format_description};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use time::Weekday;
+use tokio::sync::RwLock;
+use tracing::{debug, info, info_span, warn};
 
 pub type WeekId = usize;
 
 #[derive(Debug)]
 pub struct GuildData {
     pub guild_id: GuildId,
-    pub groups: Vec<Group>,
-    pub ghosts: Vec<GroupId>,
+    /// Sorted by name
+    pub classes: Vec<Class>,
+    /// Colleurs of every class
+    pub profs: HashMap<Arc<str>, Arc<Prof>>,
 }
 
 impl GuildData {
-    pub const GLOBAL_DATA_FOLDER_NAME: &'static str = "data";
-    pub const FILE_NAME_GHOSTS_GROUPS: &'static str = "ghosts";
-    pub const FILE_NAME_COLLE_LIST: &'static str = "colles";
-    pub const FILE_NAME_WEEKS_INFO: &'static str = "weeks";
-    pub const FILE_NAME_COLLOSCOPE: &'static str = "colloscope";
-
-    fn new(guild_id: GuildId) -> Result<Arc<Self>> {
-        if !fs::exists(Self::folder(guild_id))? {
+    /// Parses the files of the guild, without publishing the data
+    pub fn new(guild_id: GuildId) -> Result<Arc<Self>> {
+        let folder = Self::folder(guild_id);
+        if !fs::exists(&folder)? {
             Err(WattouError::NoDataForGuild(guild_id))?
         }
 
-        debug!("Parsing data for guild {}", guild_id);
+        let _span = info_span!("guild", guild_id = %guild_id).entered();
+        debug!("parsing guild data");
+
+        let mut profs = HashMap::new();
+        let classes = Self::class_folders(&folder)?
+            .into_iter()
+            .map(|(name, folder)| Class::new(guild_id, name, folder, &mut profs))
+            .collect::<Result<Vec<_>>>()?;
+        if classes.is_empty() {
+            Err(WattouError::NoDataForGuild(guild_id))?
+        }
 
-        let groups = Self::read_groups_data(guild_id)?;
-        let ghosts = Self::read_ghost_groups(guild_id)?;
+        info!(classes = classes.len(), "parsed guild data");
 
-        let arc = Arc::new(Self {
+        Ok(Arc::new(Self {
             guild_id,
-            groups,
-            ghosts,
-        });
+            classes,
+            profs,
+        }))
+    }
+
+    /// The guild folder itself when it holds the files of a single class, its sub folders
+    /// holding class files otherwise
+    pub fn class_folders(folder: &Path) -> Result<Vec<(Arc<str>, PathBuf)>> {
+        if Class::is_class_folder(folder) {
+            return Ok(vec![(Arc::from(Class::DEFAULT_NAME), folder.to_path_buf())]);
+        }
 
-        GLOBAL_DATA
-            .lock()
-            .unwrap()
-            .guilds_data
-            .insert(guild_id, arc.clone());
+        let mut classes = Vec::new();
+        for entry in fs::read_dir(folder)? {
+            let path = entry?.path();
+            if let Some(name) = path.file_name().and_then(|name| name.to_str())
+                && Class::is_class_folder(&path)
+            {
+                classes.push((Arc::from(name), path.clone()));
+            }
+        }
+        classes.sort();
+        Ok(classes)
+    }
 
-        debug!("Parsed data for guild {}", guild_id);
+    /// [GuildData::new], then resolves the groups the roster saved by position now that the
+    /// groups are known
+    fn load(id: GuildId) -> Result<Arc<Self>> {
+        let guild_data = Self::new(id)?;
+        let mut roster = Roster::read_or_default(id)?;
+        if roster.resolve_group_ids(&guild_data) {
+            Roster::update(id, |saved| {
+                saved.resolve_group_ids(&guild_data);
+                Ok(())
+            })?;
+            info!("resolved the groups of the roster saved by position");
+        }
+        Ok(guild_data)
+    }
 
-        Ok(arc)
+    /// The published snapshot of the guild, without parsing the files when there is none
+    pub fn cached(id: GuildId) -> Option<Arc<Self>> {
+        GLOBAL_DATA.guild_data(id)
     }
 
     pub fn get_from_id(id: GuildId) -> Result<Arc<Self>> {
-        if let Some(arc) = GLOBAL_DATA.lock().unwrap().guilds_data.get(&id) {
-            return Ok(arc.clone());
+        if let Some(arc) = GLOBAL_DATA.guild_data(id) {
+            Ok(arc)
         } else {
-            let guild_data = Self::new(id)?;
-            return Ok(guild_data);
+            Ok(GLOBAL_DATA.publish_guild_data_if_absent(Self::load(id)?))
         }
     }
 
+    /// Parses the files of the guild again and publishes the new data
+    pub fn reload(id: GuildId) -> Result<Arc<Self>> {
+        let guild_data = Self::load(id)?;
+        GLOBAL_DATA.publish_guild_data(guild_data.clone());
+        Ok(guild_data)
+    }
+
     pub fn from_ctx(ctx: Context<'_>) -> Result<Arc<Self>> {
         let guild_id = ctx
             .guild_id()
             .ok_or(WattouError::CommandCanOnlyBeUsedInGuilds)?;
-        Ok(Self::get_from_id(guild_id)?)
+        Self::get_from_id(guild_id)
     }
 
-    pub fn subscribers(&self) -> Result<Subscribers> {
-        Subscribers::read_or_default(self.guild_id)
+    /// The class with this name, or the only class of the guild when no name is given
+    pub fn class(&self, name: Option<&str>) -> Result<&Class> {
+        match name {
+            Some(name) => {
+                let name_cmp = easy_comp_string(name);
+                Ok(self
+                    .classes
+                    .iter()
+                    .find(|class| easy_comp_string(&class.name) == name_cmp)
+                    .ok_or_else(|| WattouError::ClassNotFound(self.guild_id, name.to_string()))?)
+            }
+            None => match self.classes.as_slice() {
+                [class] => Ok(class),
+                _ => Err(WattouError::ClassRequired(self.classes_summary()))?,
+            },
+        }
     }
 
-    pub fn global_folder() -> PathBuf {
-        Self::GLOBAL_DATA_FOLDER_NAME.into()
-    }
+    /// Like [GuildData::class], but defaults to the class named like one of the roles of the
+    /// member
+    pub async fn class_for_ctx(&self, ctx: Context<'_>, name: Option<&str>) -> Result<&Class> {
+        if name.is_some() || self.classes.len() == 1 {
+            return self.class(name);
+        }
 
-    pub fn folder(id: GuildId) -> PathBuf {
-        Self::global_folder().join(id.to_string())
-    }
+        let role_names = match (ctx.author_member().await, ctx.guild()) {
+            (Some(member), Some(guild)) => member
+                .roles
+                .iter()
+                .filter_map(|role| guild.roles.get(role))
+                .map(|role| easy_comp_string(&role.name))
+                .collect::<Vec<_>>(),
+            _ => vec![],
+        };
 
-    pub fn get_file_path<'a>(id: GuildId, file: impl Into<&'a str>) -> PathBuf {
-        Self::folder(id).join(file.into())
+        Ok(self
+            .classes
+            .iter()
+            .find(|class| role_names.contains(&easy_comp_string(&class.name)))
+            .ok_or_else(|| WattouError::ClassRequired(self.classes_summary()))?)
     }
 
-    pub fn read_text_for_guild<'a>(id: GuildId, file: impl Into<&'a str>) -> Result<String> {
-        let path = Self::get_file_path(id, file);
-        Ok(fs::read_to_string(&path)?)
-    }
+    /// The group with this id, or the group of the member in the roster, or the group named like
+    /// one of their roles
+    pub async fn group_for_ctx<'a>(
+        &self,
+        ctx: Context<'_>,
+        class: &'a Class,
+        group_id: Option<&str>,
+    ) -> Result<&'a Group> {
+        if let Some(group_id) = group_id {
+            return class.get_group(group_id);
+        }
 
-    pub fn read_groups_data(guild_id: GuildId) -> Result<Vec<Group>> {
-        let colloscope = Self::parse_colloscope(guild_id)?;
-        let groups = colloscope
-            .into_iter()
-            .enumerate()
-            .map(|(i, colles)| Group {
-                guild_id,
-                id: i + 1,
-                colles,
+        if let Some(group) =
+            Roster::read_or_default(self.guild_id)?.group_of(class, ctx.author().id)
+        {
+            return Ok(group);
+        }
+
+        let role_names = match (ctx.author_member().await, ctx.guild()) {
+            (Some(member), Some(guild)) => member
+                .roles
+                .iter()
+                .filter_map(|role| guild.roles.get(role))
+                .map(|role| easy_comp_string(&role.name))
+                .collect::<Vec<_>>(),
+            _ => vec![],
+        };
+        Ok(class
+            .active_period()
+            .groups
+            .iter()
+            .find(|group| {
+                [
+                    Some(group.id.to_string()),
+                    Some(format!("groupe {}", group.id)),
+                    group.name.clone(),
+                ]
+                .into_iter()
+                .flatten()
+                .any(|name| role_names.contains(&easy_comp_string(&name)))
             })
-            .collect();
+            .ok_or(WattouError::GroupRequired)?)
+    }
 
-        Ok(groups)
+    /// e.g. : "MP2I, MPSI, PCSI"
+    pub fn classes_summary(&self) -> String {
+        self.classes
+            .iter()
+            .map(|class| class.name.as_ref())
+            .collect::<Vec<_>>()
+            .join(", ")
     }
 
-    pub fn read_ghost_groups(guild_id: GuildId) -> Result<Vec<GroupId>> {
-        let s = Self::read_text_for_guild(guild_id, Self::FILE_NAME_GHOSTS_GROUPS)?;
-        let res: Result<Vec<usize>, ParseIntError> = s
-            .lines()
-            .map(|l| l.parse::<u64>().map(|u64| u64 as usize))
-            .collect();
-        Ok(res?)
+    /// Whether messages need to tell which class they are about
+    pub fn has_several_classes(&self) -> bool {
+        self.classes.len() > 1
     }
 
-    pub fn parse_colloscope(guild_id: GuildId) -> Result<Vec<Vec<Colle>>> {
-        let colloscope = Self::read_text_for_guild(guild_id, Self::FILE_NAME_COLLOSCOPE)?;
-        let colle_list = Self::read_text_for_guild(guild_id, Self::FILE_NAME_COLLE_LIST)?
-            .lines()
-            .map(|s| Colle::parse_string(s))
-            .collect::<Result<Vec<ColleData>>>()?;
-
-        let weeks = Self::read_weeks_data(guild_id)?;
-
-        let mut lines = colloscope.lines();
-
-        let first_line = lines.next().unwrap();
-        let week_numbers: Vec<Vec<usize>> = first_line
-            .split(" ")
-            .map(|weeks_tuple| weeks_tuple.split("-").map(|n| n.parse().unwrap()).collect())
-            .collect();
-
-        let groups = lines
-            .map(|group_colles| {
-                group_colles
-                    .split(" ")
-                    .enumerate()
-                    .map(|(i, colles)| {
-                        let weeks_n = &week_numbers[i];
-                        let data = colles
-                            .split("+")
-                            .map(|colle_id| {
-                                colle_list
-                                    .iter()
-                                    .find(|data| &data.0.to_string() == colle_id)
-                                    .ok_or(WattouError::ColleParsingFailed(
-                                        ColleParsingError::Unknown,
-                                    ))
-                            })
-                            .collect::<Result<Vec<_>, _>>()?;
-
-                        let colles = weeks_n
-                            .iter()
-                            .flat_map(|week| {
-                                data.iter().map(|d| {
-                                    let (_, _, jour, _, _) = d;
-                                    let date = Self::get_date(&weeks, *week, *jour);
-                                    Colle::from_data_and_date(date, (*d).clone())
-                                })
-                            })
-                            .collect::<Result<Vec<Colle>>>()?;
-
-                        Ok(colles)
-                    })
-                    .collect::<Result<Vec<Vec<Colle>>>>()
-                    .map(|inner| {
-                        let mut colles = inner.into_iter().flatten().collect::<Vec<Colle>>();
-                        colles.sort();
-                        colles
-                    })
-            })
-            .collect::<Result<_>>()?;
+    pub fn subscribers(&self) -> Result<Arc<RwLock<Subscribers>>> {
+        GLOBAL_DATA.subscribers(self)
+    }
 
-        Ok(groups)
+    pub fn global_folder() -> PathBuf {
+        config().data_dir.clone()
     }
 
-    pub fn read_weeks_data(guild_id: GuildId) -> Result<Vec<Date>> {
-        let format = format_description!("[day padding:none]-[month padding:none]-[year]");
-        let res = Self::read_text_for_guild(guild_id, Self::FILE_NAME_WEEKS_INFO)?
-            .lines()
-            .map(|line| Date::parse(line.split(" ").last().unwrap(), &format))
-            .collect::<Result<Vec<Date>, _>>()?;
-        Ok(res)
+    pub fn folder(id: GuildId) -> PathBuf {
+        Self::global_folder().join(id.to_string())
     }
 
-    pub fn get_date(weeks: &[Date], week: usize, jour: Jour) -> Date {
-        weeks[week - 1]
-            .saturating_sub(Duration::days(7))
-            .next_occurrence(jour.inner())
+    pub fn config(&self) -> Result<GuildConfig> {
+        GuildConfig::read_or_default(self.guild_id)
     }
 
-    pub fn prochaines_colles_msg(&self) -> String {
-        format!(
+    /// Board of every class of the guild
+    pub fn prochaines_colles_msg(&self) -> Result<String> {
+        let config = self.config()?;
+        let (colles_per_group, show_ghosts) =
+            (config.board_colles_per_group(), config.board_show_ghosts());
+        if let [class] = self.classes.as_slice() {
+            return Ok(format!(
+                "# Prochaines colles: {}",
+                class.board_msg(colles_per_group, show_ghosts)
+            ));
+        }
+
+        Ok(format!(
             "# Prochaines colles: {}",
-            self.groups
+            self.classes
                 .iter()
-                .map(|group| format!(
-                    "\nGroupe {}{} {}",
-                    group.id,
-                    if self.ghosts.contains(&group.id) {
-                        " (fantôme 👻)"
-                    } else {
-                        ""
-                    },
-                    group
-                        .get_next_colles(2)
-                        .iter()
-                        .map(|colle| format!(
-                            "\n- {}",
-                            colle.format(crate::colle::ColleStringFormat::Implicit, vec![])
-                        ))
-                        .collect::<String>()
+                .map(|class| format!(
+                    "\n## {}{}",
+                    class.name,
+                    class.board_msg(colles_per_group, show_ghosts)
                 ))
                 .collect::<String>()
-        )
+        ))
+    }
+
+    pub fn class_colles_msg(&self, class: &Class) -> Result<String> {
+        let config = self.config()?;
+        Ok(format!(
+            "# Prochaines colles {}: {}",
+            class.name,
+            class.board_msg(config.board_colles_per_group(), config.board_show_ghosts())
+        ))
     }
 
     pub async fn try_edit_toutes_les_colles_msg(&self, http: &Http) -> Result<()> {
         if let Some(message) = ToutesLesCollesMessage::read(self.guild_id) {
-            message?.edit(http, self.prochaines_colles_msg()).await?;
+            message?.edit(http, self.prochaines_colles_msg()?).await?;
+        }
+
+        if let Some(boards) = ClassBoards::read(self.guild_id) {
+            for (name, message) in boards?.iter() {
+                match self.class(Some(name)) {
+                    Ok(class) => message.edit(http, self.class_colles_msg(class)?).await?,
+                    Err(_) => warn!(class = name, "board of a class that no longer exists"),
+                }
+            }
         }
         Ok(())
     }
 
     pub fn semaine_tp_msg(&self) -> String {
-        let date = OffsetDateTime::now_local()
-            .unwrap()
-            .date()
-            .next_occurrence(Weekday::Wednesday);
+        let date = now_in_timezone().date().next_occurrence(Weekday::Wednesday);
 
         let grp = date.iso_week() % 2 == 1;
 
@@ -262,19 +324,17 @@ impl GuildData {
         Ok(())
     }
 
-    pub fn get_group(&self, group_id: usize) -> Result<&Group> {
-        Ok(self
-            .groups
-            .iter()
-            .find(|g| g.id == group_id)
-            .ok_or(WattouError::GroupNotFound)?)
-    }
-
     pub async fn refresh_subscribers_message(&self, http: &Http) -> Result<()> {
-        let subs = self.subscribers()?;
+        let subscribers = self
+            .subscribers()?
+            .read()
+            .await
+            .iter()
+            .map(|(user_id, data)| (*user_id, data.clone()))
+            .collect::<Vec<_>>();
 
-        for (user_id, data) in subs.iter() {
-            data.try_send(*user_id, http, &self).await?;
+        for (user_id, data) in subscribers {
+            data.try_send(user_id, http, self).await?;
         }
 
         Ok(())
@@ -283,38 +343,76 @@ impl GuildData {
 
 pub trait SavedData: Sized {
     const FILE_NAME: &'static str;
+    /// Version of the format produced by [SavedData::ser], to bump when it changes along with
+    /// a new step in [SavedData::migrate]
+    const VERSION: u32 = 1;
 
     fn ser(&self) -> String;
     fn de(value: &str) -> Result<Self>;
 
+    /// Converts a value saved with format `version` to format `version + 1`
+    fn migrate(version: u32, _value: String) -> Result<String> {
+        Err(WattouError::UnsupportedDataVersion(
+            Self::FILE_NAME,
+            version,
+        ))?
+    }
+
+    /// Deserializes a record, migrating it first if it was saved with an older format
+    fn from_record(record: Record) -> Result<(Self, bool)> {
+        let Record {
+            mut version,
+            mut value,
+        } = record;
+        if version > Self::VERSION {
+            Err(WattouError::UnsupportedDataVersion(
+                Self::FILE_NAME,
+                version,
+            ))?
+        }
+
+        let migrated = version < Self::VERSION;
+        while version < Self::VERSION {
+            value = Self::migrate(version, value)?;
+            version += 1;
+        }
+        Ok((Self::de(&value)?, migrated))
+    }
+
+    fn to_record(&self) -> Record {
+        Record {
+            version: Self::VERSION,
+            value: self.ser(),
+        }
+    }
+
     /// Returns [None] if no data found, returns [Some(Err())] if parsing or read failed
     fn read(guild_id: GuildId) -> Option<Result<Self>> {
-        let exists = match fs::exists(GuildData::get_file_path(guild_id, Self::FILE_NAME)) {
-            Ok(v) => v,
-            Err(e) => return Some(Err(e.into())),
+        let record = match storage().read(guild_id, Self::FILE_NAME) {
+            Ok(record) => record?,
+            Err(e) => return Some(Err(e)),
         };
 
-        if !exists {
-            return None;
-        }
-
         Some((|| {
-            let data = GuildData::read_text_for_guild(guild_id, Self::FILE_NAME)?;
-            Self::de(&data)
+            let (data, migrated) = Self::from_record(record)?;
+            if migrated {
+                data.save(guild_id)?;
+                info!(
+                    guild_id = %guild_id,
+                    file = Self::FILE_NAME,
+                    "migrated saved data to the current format"
+                );
+            }
+            Ok(data)
         })())
     }
 
-    fn save<'a>(&self, guild_id: GuildId) -> Result<()> {
-        let path = GuildData::get_file_path(guild_id, Self::FILE_NAME);
-        fs::write(path, self.ser())?;
-        Ok(())
+    fn save(&self, guild_id: GuildId) -> Result<()> {
+        storage().write(guild_id, Self::FILE_NAME, &self.to_record())
     }
 
     fn exists(guild_id: GuildId) -> Result<bool> {
-        Ok(fs::exists(GuildData::get_file_path(
-            guild_id,
-            Subscribers::FILE_NAME,
-        ))?)
+        storage().exists(guild_id, Self::FILE_NAME)
     }
 }
 
@@ -322,6 +420,22 @@ pub trait SavedDataWithDefault: SavedData + Default {
     fn read_or_default(guild_id: GuildId) -> Result<Self> {
         Self::read(guild_id).unwrap_or(Ok(Self::default()))
     }
+
+    /// Reads, modifies and saves the data without any other write happening in between
+    fn update<R>(guild_id: GuildId, f: impl FnOnce(&mut Self) -> Result<R>) -> Result<R> {
+        let mut f = Some(f);
+        let mut res = None;
+        storage().update(guild_id, Self::FILE_NAME, &mut |record| {
+            let mut data = match record {
+                Some(record) => Self::from_record(record)?.0,
+                None => Self::default(),
+            };
+            let f = f.take().expect("storage update closure called twice");
+            res = Some(f(&mut data)?);
+            Ok(data.to_record())
+        })?;
+        Ok(res.expect("storage update closure was not called"))
+    }
 }
 
 impl<T> SavedDataWithDefault for T where T: SavedData + Default {}
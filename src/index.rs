@@ -0,0 +1,234 @@
+use crate::{
+    colle::{Colle, RoomNumber},
+    group::{Group, GroupId},
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+};
+use time::{Date, Duration, OffsetDateTime};
+
+/// A colle of the guild along with the group that has it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexedColle {
+    pub group_id: GroupId,
+    pub colle: Colle,
+}
+
+/// Every colle of a guild sorted by start, with lookup tables built once when the data is parsed
+#[derive(Debug, Default)]
+pub struct ColleIndex {
+    colles: Vec<IndexedColle>,
+    /// Longest colle, used to find the first colle that may still be running at a given time
+    max_duration: Duration,
+
+    by_prof: HashMap<Arc<str>, Vec<usize>>,
+    by_date: BTreeMap<Date, Vec<usize>>,
+    by_room: HashMap<RoomNumber, Vec<usize>>,
+    by_subject: HashMap<char, Vec<usize>>,
+    by_group: HashMap<GroupId, Vec<usize>>,
+}
+
+/// Filters of [ColleIndex::query], every filter left to [None] matches everything
+#[derive(Debug, Default, Clone)]
+pub struct ColleQuery<'a> {
+    pub prof: Option<&'a str>,
+    pub room: Option<&'a str>,
+    pub subject: Option<char>,
+    pub group: Option<&'a str>,
+    pub date: Option<Date>,
+    /// Only colles that end after this time
+    pub from: Option<OffsetDateTime>,
+    /// Only colles that start before this time
+    pub to: Option<OffsetDateTime>,
+    pub limit: Option<usize>,
+    /// Also colles of ghost groups, only used by [crate::class::Class::query]
+    pub ghosts: bool,
+}
+
+impl<'a> ColleQuery<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Colles that are not over yet
+    pub fn upcoming() -> Self {
+        Self::new().from(OffsetDateTime::now_utc())
+    }
+
+    pub fn prof(mut self, prof: &'a str) -> Self {
+        self.prof = Some(prof);
+        self
+    }
+
+    pub fn room(mut self, room: &'a str) -> Self {
+        self.room = Some(room);
+        self
+    }
+
+    pub fn subject(mut self, subject: char) -> Self {
+        self.subject = Some(subject);
+        self
+    }
+
+    pub fn group(mut self, group: &'a str) -> Self {
+        self.group = Some(group);
+        self
+    }
+
+    pub fn date(mut self, date: Date) -> Self {
+        self.date = Some(date);
+        self
+    }
+
+    pub fn from(mut self, from: OffsetDateTime) -> Self {
+        self.from = Some(from);
+        self
+    }
+
+    pub fn to(mut self, to: OffsetDateTime) -> Self {
+        self.to = Some(to);
+        self
+    }
+
+    pub fn range(self, from: OffsetDateTime, to: OffsetDateTime) -> Self {
+        self.from(from).to(to)
+    }
+
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn with_ghosts(mut self) -> Self {
+        self.ghosts = true;
+        self
+    }
+
+    fn matches(&self, indexed: &IndexedColle) -> bool {
+        let colle = &indexed.colle;
+        self.prof.is_none_or(|prof| colle.prof.name() == prof)
+            && self.room.is_none_or(|room| colle.room == room)
+            && self.subject.is_none_or(|subject| colle.id.0 == subject)
+            && self.group.is_none_or(|group| &*indexed.group_id == group)
+            && self.date.is_none_or(|date| colle.start.date() == date)
+            && self.from.is_none_or(|from| colle.end > from)
+            && self.to.is_none_or(|to| colle.start < to)
+    }
+}
+
+impl IndexedColle {
+    /// Identifies the colle across reloads, e.g. `G7-M1-1736784000`
+    pub fn key(&self) -> String {
+        format!(
+            "{}-{}-{}",
+            self.group_id,
+            self.colle.id,
+            self.colle.start.unix_timestamp()
+        )
+    }
+}
+
+impl ColleIndex {
+    pub fn new(groups: &[Group]) -> Self {
+        let mut colles = groups
+            .iter()
+            .flat_map(|group| {
+                group.colles.iter().map(|colle| IndexedColle {
+                    group_id: group.id.clone(),
+                    colle: colle.clone(),
+                })
+            })
+            .collect::<Vec<_>>();
+        colles.sort_by(|a, b| a.colle.cmp(&b.colle).then(a.group_id.cmp(&b.group_id)));
+
+        let mut index = Self {
+            max_duration: colles
+                .iter()
+                .map(|indexed| indexed.colle.end - indexed.colle.start)
+                .max()
+                .unwrap_or_default(),
+            ..Default::default()
+        };
+
+        for (i, IndexedColle { group_id, colle }) in colles.iter().enumerate() {
+            index
+                .by_prof
+                .entry(Arc::from(colle.prof.name()))
+                .or_default()
+                .push(i);
+            index.by_date.entry(colle.start.date()).or_default().push(i);
+            index.by_room.entry(colle.room.clone()).or_default().push(i);
+            index.by_subject.entry(colle.id.0).or_default().push(i);
+            index.by_group.entry(group_id.clone()).or_default().push(i);
+        }
+
+        index.colles = colles;
+        index
+    }
+
+    pub fn len(&self) -> usize {
+        self.colles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.colles.is_empty()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, IndexedColle> {
+        self.colles.iter()
+    }
+
+    /// Colles matching every filter of the query, sorted by start
+    pub fn query(&self, query: &ColleQuery) -> Vec<&IndexedColle> {
+        const EMPTY: &[usize] = &[];
+
+        // Narrowest lookup table matching the filters, a filter on an unknown value matches nothing
+        let postings = [
+            query
+                .prof
+                .map(|prof| self.by_prof.get(prof).map_or(EMPTY, Vec::as_slice)),
+            query
+                .room
+                .map(|room| self.by_room.get(room).map_or(EMPTY, Vec::as_slice)),
+            query
+                .subject
+                .map(|subject| self.by_subject.get(&subject).map_or(EMPTY, Vec::as_slice)),
+            query
+                .group
+                .map(|group| self.by_group.get(group).map_or(EMPTY, Vec::as_slice)),
+            query
+                .date
+                .map(|date| self.by_date.get(&date).map_or(EMPTY, Vec::as_slice)),
+        ]
+        .into_iter()
+        .flatten()
+        .min_by_key(|postings| postings.len());
+
+        // Colles starting before this time are over at `from`
+        let first_start = query.from.map(|from| from - self.max_duration);
+        let starts_too_early =
+            |i: &usize| first_start.is_some_and(|start| self.colles[*i].colle.start < start);
+        let too_late = |i: &usize| query.to.is_some_and(|to| self.colles[*i].colle.start >= to);
+
+        let candidates: Box<dyn Iterator<Item = usize>> = match postings {
+            Some(postings) => {
+                let skip = postings.partition_point(starts_too_early);
+                Box::new(postings[skip..].iter().copied())
+            }
+            None => {
+                let skip = self.colles.partition_point(|indexed| {
+                    first_start.is_some_and(|start| indexed.colle.start < start)
+                });
+                Box::new(skip..self.colles.len())
+            }
+        };
+
+        candidates
+            .take_while(|i| !too_late(i))
+            .map(|i| &self.colles[i])
+            .filter(|indexed| query.matches(indexed))
+            .take(query.limit.unwrap_or(usize::MAX))
+            .collect()
+    }
+}
@@ -0,0 +1,344 @@
+//! Private grade journal: students who enable it with `/mes_notes activer` are asked in DM for
+//! the grade of each colle once it is over, and see their averages with `/mes_notes voir`.
+//!
+//! Entries are only ever shown to their author.
+use crate::{
+    class::Class,
+    colle::{ColleId, ColleStringFormat},
+    group::Group,
+    guild_data::{GuildData, SavedData, SavedDataWithDefault},
+    index::{ColleQuery, IndexedColle},
+    roster::{self, Roster},
+    utils::month_to_short_fr,
+};
+use color_eyre::Result;
+use serde::{Deserialize, Serialize};
+use serenity::all::{Http, UserId};
+use std::{collections::BTreeMap, fmt::Write, time::Duration};
+use time::{Date, OffsetDateTime};
+use tracing::info;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JournalEntry {
+    /// [IndexedColle::key] of the graded colle
+    pub colle_key: String,
+    pub subject: char,
+    pub prof: String,
+    /// e.g. : Maths 1: Lundi 13 Jan 17h-18h avec Dupont en 207
+    pub colle: String,
+    /// Unix timestamp of the start of the colle
+    pub start: i64,
+    /// Out of 20
+    pub grade: f32,
+    pub notes: Option<String>,
+}
+
+impl JournalEntry {
+    pub fn new(indexed: &IndexedColle, grade: f32, notes: Option<String>) -> Self {
+        Self {
+            colle_key: indexed.key(),
+            subject: indexed.colle.id.0,
+            prof: indexed.colle.prof.name().to_string(),
+            colle: indexed.colle.format(ColleStringFormat::Explicit, vec![]),
+            start: indexed.colle.start.unix_timestamp(),
+            grade,
+            notes,
+        }
+    }
+
+    fn date(&self) -> Date {
+        OffsetDateTime::from_unix_timestamp(self.start)
+            .unwrap_or(OffsetDateTime::UNIX_EPOCH)
+            .date()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UserJournal {
+    /// Whether the user is asked for their grades
+    pub enabled: bool,
+    pub class: String,
+    pub group_id: String,
+    /// Unix timestamp, colles over before it were already asked for
+    pub asked_until: i64,
+    pub entries: Vec<JournalEntry>,
+}
+
+impl UserJournal {
+    pub fn is_graded(&self, indexed: &IndexedColle) -> bool {
+        let key = indexed.key();
+        self.entries.iter().any(|entry| entry.colle_key == key)
+    }
+
+    /// Averages by subject, by colleur and by month
+    pub fn summary(&self) -> String {
+        if self.entries.is_empty() {
+            return "Aucune note pour l'instant, note tes colles avec `/mes_notes noter`"
+                .to_string();
+        }
+
+        let mut by_subject = BTreeMap::new();
+        let mut by_prof = BTreeMap::new();
+        let mut by_month = BTreeMap::new();
+        for entry in &self.entries {
+            let date = entry.date();
+            for (averages, key) in [
+                (
+                    &mut by_subject,
+                    ColleId::subject_name(entry.subject)
+                        .map(str::to_string)
+                        .unwrap_or_else(|| entry.subject.to_string()),
+                ),
+                (&mut by_prof, entry.prof.clone()),
+            ] {
+                averages
+                    .entry(key)
+                    .or_insert_with(Average::default)
+                    .add(entry.grade);
+            }
+            by_month
+                .entry((date.year(), date.month() as u8))
+                .or_insert_with(|| (date.month(), Average::default()))
+                .1
+                .add(entry.grade);
+        }
+
+        let total = self
+            .entries
+            .iter()
+            .fold(Average::default(), |mut average, entry| {
+                average.add(entry.grade);
+                average
+            });
+        let mut s = format!("# Ton carnet de colles\nMoyenne générale : {total}\n## Par matière");
+        for (subject, average) in by_subject {
+            let _ = write!(s, "\n- {subject} : {average}");
+        }
+        s += "\n## Par colleur";
+        for (prof, average) in by_prof {
+            let _ = write!(s, "\n- {prof} : {average}");
+        }
+        s += "\n## Évolution";
+        let mut previous: Option<f32> = None;
+        for ((year, _), (month, average)) in by_month {
+            let trend = match previous {
+                Some(previous) if average.value() > previous + 0.5 => " ↗",
+                Some(previous) if average.value() < previous - 0.5 => " ↘",
+                Some(_) => " →",
+                None => "",
+            };
+            let _ = write!(
+                s,
+                "\n- {} {year} : {average}{trend}",
+                month_to_short_fr(month)
+            );
+            previous = Some(average.value());
+        }
+        s
+    }
+
+    /// Every entry as CSV, oldest colle first
+    pub fn to_csv(&self) -> String {
+        let mut entries = self.entries.iter().collect::<Vec<_>>();
+        entries.sort_by_key(|entry| entry.start);
+
+        let mut csv = "date,matiere,colleur,colle,note,remarques\n".to_string();
+        for entry in entries {
+            let _ = writeln!(
+                csv,
+                "{},{},{},{},{},{}",
+                entry.date(),
+                entry.subject,
+                csv_field(&entry.prof),
+                csv_field(&entry.colle),
+                entry.grade,
+                csv_field(entry.notes.as_deref().unwrap_or_default())
+            );
+        }
+        csv
+    }
+}
+
+/// Quotes the field when it contains a separator, a quote or a new line
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Average {
+    sum: f32,
+    count: usize,
+}
+
+impl Average {
+    fn add(&mut self, grade: f32) {
+        self.sum += grade;
+        self.count += 1;
+    }
+
+    fn value(&self) -> f32 {
+        self.sum / self.count.max(1) as f32
+    }
+}
+
+impl std::fmt::Display for Average {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:.1}/20 ({} colle(s))", self.value(), self.count)
+    }
+}
+
+/// Journals of the members of a guild
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Journals {
+    users: BTreeMap<UserId, UserJournal>,
+}
+
+impl SavedData for Journals {
+    const FILE_NAME: &'static str = "journal.json";
+
+    fn ser(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+
+    fn de(value: &str) -> Result<Self> {
+        Ok(serde_json::from_str(value)?)
+    }
+}
+
+impl Journals {
+    /// How long a colle can be graded after it is over
+    pub const GRADING_WINDOW: Duration = Duration::from_secs(60 * 60 * 24 * 30);
+
+    pub fn get(&self, user_id: UserId) -> Option<&UserJournal> {
+        self.users.get(&user_id)
+    }
+
+    /// Asks the user for the grades of the colles of the group from now on, keeping their
+    /// previous entries
+    pub fn enable(&mut self, user_id: UserId, class: &Class, group: &Group) {
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        let journal = self.users.entry(user_id).or_insert_with(|| UserJournal {
+            enabled: true,
+            class: class.name.to_string(),
+            group_id: group.id.to_string(),
+            asked_until: now,
+            entries: vec![],
+        });
+        if !journal.enabled {
+            journal.asked_until = now;
+        }
+        journal.enabled = true;
+        journal.class = class.name.to_string();
+        journal.group_id = group.id.to_string();
+    }
+
+    /// Stops asking the user, `erase` also forgets their entries, returns whether they had a
+    /// journal
+    pub fn disable(&mut self, user_id: UserId, erase: bool) -> bool {
+        if erase {
+            return self.users.remove(&user_id).is_some();
+        }
+        self.users
+            .get_mut(&user_id)
+            .map(|journal| journal.enabled = false)
+            .is_some()
+    }
+
+    /// Adds the entry, replacing the previous grade of the same colle
+    pub fn record(&mut self, user_id: UserId, class: &Class, group: &Group, entry: JournalEntry) {
+        let journal = self.users.entry(user_id).or_insert_with(|| UserJournal {
+            enabled: false,
+            class: class.name.to_string(),
+            group_id: group.id.to_string(),
+            asked_until: OffsetDateTime::now_utc().unix_timestamp(),
+            entries: vec![],
+        });
+        journal
+            .entries
+            .retain(|other| other.colle_key != entry.colle_key);
+        journal.entries.push(entry);
+    }
+}
+
+/// Colles of the group over in the last [Journals::GRADING_WINDOW], most recent first
+pub fn recent_colles<'a>(class: &'a Class, group: &Group) -> Vec<&'a IndexedColle> {
+    let now = OffsetDateTime::now_utc();
+    let mut colles = class
+        .query(
+            &ColleQuery::new()
+                .group(&group.id)
+                .range(now - Journals::GRADING_WINDOW, now),
+        )
+        .into_iter()
+        .filter(|indexed| indexed.colle.end <= now)
+        .collect::<Vec<_>>();
+    colles.reverse();
+    colles
+}
+
+/// DMs the users with a journal about the colles over since they were last asked
+pub async fn ask_grades(guild_data: &GuildData, http: &Http) -> Result<()> {
+    let now = OffsetDateTime::now_utc();
+    let journals = Journals::read_or_default(guild_data.guild_id)?;
+    let roster = Roster::read_or_default(guild_data.guild_id)?;
+
+    let (mut asked, mut sent) = (vec![], 0);
+    for (user_id, journal) in journals.users.iter().filter(|(_, journal)| journal.enabled) {
+        let Ok(class) = guild_data.class(Some(&journal.class)) else {
+            continue;
+        };
+        // The roster knows the group of the user in the current period
+        let Some(group) = roster
+            .group_of(class, *user_id)
+            .or_else(|| class.group(&journal.group_id))
+        else {
+            continue;
+        };
+        let colles = recent_colles(class, group)
+            .into_iter()
+            .filter(|indexed| {
+                indexed.colle.end.unix_timestamp() > journal.asked_until
+                    && !journal.is_graded(indexed)
+            })
+            .map(|indexed| indexed.colle.format(ColleStringFormat::Explicit, vec![]))
+            .collect::<Vec<_>>();
+        // No colle to ask about, nothing is missed by moving on
+        if colles.is_empty() {
+            asked.push(*user_id);
+            continue;
+        }
+        // A failed DM asks again at the next refresh
+        let delivered = roster::send_dms(
+            http,
+            &[*user_id],
+            &format!(
+                "📝 Comment s'est passée ta colle {} ? Note-la avec `/mes_notes noter`, seul toi verras ta note",
+                colles.join(", ")
+            ),
+        )
+        .await;
+        if delivered == 1 {
+            asked.push(*user_id);
+            sent += 1;
+        }
+    }
+
+    if !asked.is_empty() {
+        Journals::update(guild_data.guild_id, |journals| {
+            for user_id in &asked {
+                if let Some(journal) = journals.users.get_mut(user_id) {
+                    journal.asked_until = now.unix_timestamp();
+                }
+            }
+            Ok(())
+        })?;
+    }
+    if sent > 0 {
+        info!(sent, "asked journal grades");
+    }
+    Ok(())
+}
@@ -0,0 +1,195 @@
+use color_eyre::{Report, Result, eyre::eyre};
+use poise::{ApplicationContext, BoxFuture, FrameworkError};
+use serde::{Deserialize, Serialize};
+use std::{env, path::PathBuf, sync::Arc, time::Instant};
+use tracing::{Instrument, Span, error, info, info_span};
+use tracing_appender::{non_blocking::WorkerGuard, rolling::Rotation};
+use tracing_subscriber::{EnvFilter, fmt::writer::BoxMakeWriter};
+
+use crate::{Context, GlobalData};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogOutput {
+    Stdout,
+    /// Files in [LogSettings::directory], a new one every [LogSettings::rotation]
+    Files,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Text,
+    /// One JSON object per line
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogRotation {
+    Minutely,
+    Hourly,
+    Daily,
+    Never,
+}
+
+impl From<LogRotation> for Rotation {
+    fn from(value: LogRotation) -> Self {
+        match value {
+            LogRotation::Minutely => Rotation::MINUTELY,
+            LogRotation::Hourly => Rotation::HOURLY,
+            LogRotation::Daily => Rotation::DAILY,
+            LogRotation::Never => Rotation::NEVER,
+        }
+    }
+}
+
+/// `[logging]` section of the config
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogSettings {
+    pub output: LogOutput,
+    pub format: LogFormat,
+    pub directory: PathBuf,
+    pub rotation: LogRotation,
+    /// `RUST_LOG` style filter, e.g. `info,uhu_wattou=debug`, the `RUST_LOG` variable wins if set
+    pub filter: String,
+}
+
+impl Default for LogSettings {
+    fn default() -> Self {
+        Self {
+            output: if cfg!(debug_assertions) {
+                LogOutput::Stdout
+            } else {
+                LogOutput::Files
+            },
+            format: LogFormat::Text,
+            directory: "logs".into(),
+            rotation: LogRotation::Daily,
+            filter: "warn,uhu_wattou=info".to_string(),
+        }
+    }
+}
+
+impl LogSettings {
+    pub fn validate(&self) -> Result<()> {
+        EnvFilter::try_new(&self.filter)
+            .map_err(|e| eyre!("logging.filter invalide {}: {e}", self.filter))?;
+        Ok(())
+    }
+
+    fn env_filter(&self) -> Result<EnvFilter> {
+        Ok(match env::var(EnvFilter::DEFAULT_ENV) {
+            Ok(filter) => EnvFilter::try_new(filter)?,
+            Err(_) => EnvFilter::try_new(&self.filter)?,
+        })
+    }
+
+    /// Installs the global subscriber, the returned guard flushes the logs when dropped
+    pub fn init(&self) -> Result<WorkerGuard> {
+        let (writer, guard) = match self.output {
+            LogOutput::Stdout => tracing_appender::non_blocking(std::io::stdout()),
+            LogOutput::Files => tracing_appender::non_blocking(
+                tracing_appender::rolling::Builder::new()
+                    .rotation(self.rotation.into())
+                    .filename_prefix("wattou")
+                    .filename_suffix("log")
+                    .build(&self.directory)?,
+            ),
+        };
+
+        let builder = tracing_subscriber::fmt()
+            .with_env_filter(self.env_filter()?)
+            .with_writer(BoxMakeWriter::new(writer))
+            .with_ansi(self.output == LogOutput::Stdout);
+
+        match self.format {
+            LogFormat::Text => builder.try_init(),
+            LogFormat::Json => builder.json().with_current_span(true).try_init(),
+        }
+        .map_err(|e| eyre!(e))?;
+
+        Ok(guard)
+    }
+}
+
+/// Span of a command invocation, stored in the invocation data between the pre and post
+/// command hooks
+pub struct CommandSpan {
+    span: Span,
+    start: Instant,
+}
+
+pub async fn pre_command(ctx: Context<'_>) {
+    let span = info_span!(
+        "command",
+        command = %ctx.command().qualified_name,
+        guild_id = ctx.guild_id().map(|id| id.get()),
+        user_id = %ctx.author().id,
+    );
+    span.in_scope(|| info!("command started"));
+
+    ctx.set_invocation_data(CommandSpan {
+        span,
+        start: Instant::now(),
+    })
+    .await;
+}
+
+pub async fn post_command(ctx: Context<'_>) {
+    if let Some(data) = ctx.invocation_data::<CommandSpan>().await {
+        let latency_ms = data.start.elapsed().as_millis() as u64;
+        data.span.in_scope(|| info!(latency_ms, "command finished"));
+    }
+}
+
+pub async fn command_failed(ctx: Context<'_>) {
+    if let Some(data) = ctx.invocation_data::<CommandSpan>().await {
+        let latency_ms = data.start.elapsed().as_millis() as u64;
+        data.span.in_scope(|| info!(latency_ms, "command failed"));
+    }
+}
+
+/// Span created by [pre_command], or a disabled one for commands that did not start
+pub async fn command_span(ctx: Context<'_>) -> Span {
+    ctx.invocation_data::<CommandSpan>()
+        .await
+        .map(|data| data.span.clone())
+        .unwrap_or_else(Span::none)
+}
+
+type Command = poise::Command<Arc<GlobalData>, Report>;
+type SlashAction =
+    for<'a> fn(
+        ApplicationContext<'a, Arc<GlobalData>, Report>,
+    ) -> BoxFuture<'a, Result<(), FrameworkError<'a, Arc<GlobalData>, Report>>>;
+
+/// Runs the slash commands in the span of [pre_command], so that the logs of the handlers carry
+/// the command, guild and user. The original action is kept in the `custom_data` of the command.
+pub fn instrument_commands(commands: &mut [Command]) {
+    for command in commands {
+        if let Some(action) = command.slash_action {
+            command.custom_data = Box::new(action);
+            command.slash_action = Some(instrumented_slash_action);
+        }
+        instrument_commands(&mut command.subcommands);
+    }
+}
+
+fn instrumented_slash_action(
+    ctx: ApplicationContext<'_, Arc<GlobalData>, Report>,
+) -> BoxFuture<'_, Result<(), FrameworkError<'_, Arc<GlobalData>, Report>>> {
+    Box::pin(async move {
+        let Some(action) = ctx.command.custom_data.downcast_ref::<SlashAction>() else {
+            let command = &ctx.command.qualified_name;
+            error!(command, "instrumented command without its original action");
+            return Err(FrameworkError::new_command(
+                Context::Application(ctx),
+                eyre!("La commande {command} n'a pas d'action"),
+            ));
+        };
+        let span = command_span(Context::Application(ctx)).await;
+        action(ctx).instrument(span).await
+    })
+}
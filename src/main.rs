@@ -1,107 +1,202 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 #![allow(async_fn_in_trait)]
+pub mod absence;
+pub mod class;
 pub mod colle;
 pub mod commands;
+pub mod config;
+pub mod diff;
 pub mod error;
+pub mod exercise;
+pub mod fairness;
+pub mod generator;
+pub mod ghost;
 pub mod group;
 pub mod guild_data;
+pub mod index;
+pub mod journal;
+pub mod logging;
+pub mod permissions;
 pub mod prof;
+pub mod programme;
+pub mod purge;
+pub mod rattrapage;
 pub mod recurrent_message;
+pub mod roster;
+pub mod scheduler;
+pub mod storage;
 pub mod subscriber;
 pub mod utils;
+pub mod validator;
+pub mod weeks;
 
-use crate::{error::WattouError, guild_data::GuildData, prof::Prof};
+use crate::{
+    config::{Config, config},
+    guild_data::{GuildData, SavedData, SavedDataWithDefault},
+    subscriber::Subscribers,
+};
+use arc_swap::ArcSwap;
 use color_eyre::{Result, eyre::Report};
 use dotenv::dotenv;
 use once_cell::sync::Lazy;
 use serenity::{
-    all::{GuildId, Http, Interaction, Ready},
+    all::{GuildId, Interaction, Ready},
     async_trait,
     prelude::*,
 };
-use std::{
-    collections::HashMap,
-    env,
-    sync::{Arc, Mutex},
-};
+use std::{collections::HashMap, env, process::ExitCode, sync::Arc};
+use tokio::sync::RwLock;
+use tracing::{debug, info};
 
-type Context<'a> = poise::Context<'a, Arc<Mutex<GlobalData>>, Report>;
+type Context<'a> = poise::Context<'a, Arc<GlobalData>, Report>;
 
 #[derive(Default)]
 pub struct GlobalData {
-    pub guilds_data: HashMap<GuildId, Arc<GuildData>>,
-    pub profs: HashMap<Arc<str>, Arc<Prof>>,
+    /// Immutable snapshots of the parsed data of each guild, a reload publishes a new map
+    /// so readers never wait for it
+    guilds_data: ArcSwap<HashMap<GuildId, Arc<GuildData>>>,
+    /// Subscribers of each guild, loaded once from the storage then kept in sync with it
+    subscribers: ArcSwap<HashMap<GuildId, Arc<RwLock<Subscribers>>>>,
+}
+
+impl GlobalData {
+    pub fn guild_data(&self, guild_id: GuildId) -> Option<Arc<GuildData>> {
+        self.guilds_data.load().get(&guild_id).cloned()
+    }
+
+    /// Replaces the snapshot of the guild, commands already running keep the previous one
+    pub fn publish_guild_data(&self, guild_data: Arc<GuildData>) {
+        self.guilds_data.rcu(|guilds_data| {
+            let mut guilds_data = HashMap::clone(guilds_data);
+            guilds_data.insert(guild_data.guild_id, guild_data.clone());
+            guilds_data
+        });
+    }
+
+    /// Publishes the snapshot only if no other one was published in the meantime and
+    /// returns the one that ended up published
+    pub fn publish_guild_data_if_absent(&self, guild_data: Arc<GuildData>) -> Arc<GuildData> {
+        let guild_id = guild_data.guild_id;
+        self.guilds_data.rcu(|guilds_data| {
+            let mut guilds_data = HashMap::clone(guilds_data);
+            guilds_data
+                .entry(guild_id)
+                .or_insert_with(|| guild_data.clone());
+            guilds_data
+        });
+        self.guild_data(guild_id).unwrap_or(guild_data)
+    }
+
+    /// Subscribers of the guild, the groups saved by position are resolved when first loaded
+    pub fn subscribers(&self, guild_data: &GuildData) -> Result<Arc<RwLock<Subscribers>>> {
+        let guild_id = guild_data.guild_id;
+        if let Some(subscribers) = self.subscribers.load().get(&guild_id) {
+            return Ok(subscribers.clone());
+        }
+
+        let mut subscribers = Subscribers::read_or_default(guild_id)?;
+        if subscribers.resolve_group_ids(guild_data) {
+            subscribers.save(guild_id)?;
+        }
+        let loaded = Arc::new(RwLock::new(subscribers));
+        self.subscribers.rcu(|subscribers| {
+            let mut subscribers = HashMap::clone(subscribers);
+            subscribers
+                .entry(guild_id)
+                .or_insert_with(|| loaded.clone());
+            subscribers
+        });
+        Ok(self.subscribers.load()[&guild_id].clone())
+    }
 }
 
-static GLOBAL_DATA: Lazy<Arc<Mutex<GlobalData>>> =
-    Lazy::new(|| Arc::new(Mutex::new(GlobalData::default())));
+static GLOBAL_DATA: Lazy<Arc<GlobalData>> = Lazy::new(Arc::default);
 
 struct Handler;
 
 #[async_trait]
 impl EventHandler for Handler {
     async fn ready(&self, ctx: serenity::prelude::Context, ready: Ready) {
-        debug!("{} is connected!", ready.user.name);
+        info!(user = %ready.user.name, "connected");
 
-        ctx.set_activity(Some(serenity::all::ActivityData {
-            name: "les aventures de wattou".to_string(),
-            kind: serenity::all::ActivityType::Watching,
-            state: None,
-            url: None,
-        }));
-
-        if let Err(e) = refresh_messages(&ctx.http).await {
-            debug!("Error : {:?}", e);
-        }
+        ctx.set_activity(Some(config().presence.activity_data()));
+        scheduler::start(ctx.http.clone());
     }
 
     async fn interaction_create(&self, _ctx: serenity::prelude::Context, interaction: Interaction) {
         if let Some(command) = interaction.as_command() {
-            debug!("{} executed command {}", command.user.id, command.data.name);
+            debug!(
+                user_id = %command.user.id,
+                command = %command.data.name,
+                "received command interaction"
+            );
         }
     }
 }
 
-async fn refresh_messages(http: &Http) -> color_eyre::Result<()> {
-    let guilds = http.get_guilds(None, None).await?;
-    for guild in guilds {
-        match GuildData::get_from_id(guild.id) {
-            Ok(guild_data) => {
-                guild_data.try_edit_toutes_les_colles_msg(&http).await?;
-                guild_data.edit_semaine_tp_msg(&http).await?;
-                guild_data.refresh_subscribers_message(&http).await?;
-            }
-            Err(e) => {
-                if !e.is::<WattouError>()
-                    || *e.downcast_ref::<WattouError>().unwrap()
-                        != WattouError::NoDataForGuild(guild.id)
-                {
-                    return Err(e);
-                }
-            }
-        }
-    }
-
-    Ok(())
-}
-
 #[tokio::main]
-async fn main() -> Result<()> {
-    color_eyre::install()?;
+async fn main() -> Result<ExitCode> {
+    // No colors so that reports can be forwarded to Discord as is
+    color_eyre::config::HookBuilder::default()
+        .theme(color_eyre::config::Theme::new())
+        .install()?;
     dotenv().ok();
+    config::init(Config::load()?)?;
+    let _log_guard = config().logging.init()?;
+    if let Some(folder) = validator::requested() {
+        // Returning lets the log guard flush the logs
+        return Ok(if validator::run(&folder)? {
+            ExitCode::SUCCESS
+        } else {
+            ExitCode::FAILURE
+        });
+    }
     let token = env::var("DISCORD_TOKEN").expect("Missing DISCORD_TOKEN");
+    storage::init(config().storage)?;
+
+    let mut commands = vec![
+        commands::clear(),
+        commands::colles_de_prof(),
+        commands::mes_colles(),
+        commands::toutes_les_colles(),
+        commands::colles_calendrier(),
+        commands::recharger(),
+        commands::salon_admin(),
+        commands::salon_annonces(),
+        commands::permissions(),
+        commands::config(),
+        commands::semaines(),
+        commands::groupe_fantome(),
+        commands::places_libres(),
+        commands::groupe(),
+        commands::absence(),
+        commands::rattrapages(),
+        commands::rattrapage(),
+        commands::colleur(),
+        commands::programme(),
+        commands::exercice(),
+        commands::equite(),
+        commands::colloscope(),
+    ];
+    if config().features.semaine_tp {
+        commands.push(commands::semaine_tp());
+    }
+    if config().features.reminders {
+        commands.push(commands::rappel());
+    }
+    if config().features.journal {
+        commands.push(commands::mes_notes());
+    }
+
+    logging::instrument_commands(&mut commands);
 
     let framework = poise::Framework::builder()
         .options(poise::FrameworkOptions {
-            commands: vec![
-                commands::clear(),
-                commands::colles_de_prof(),
-                commands::rappel(),
-                commands::mes_colles(),
-                commands::toutes_les_colles(),
-                commands::semaine_tp(),
-                commands::colles_calendrier(),
-            ],
+            commands,
+            command_check: Some(|ctx| Box::pin(permissions::check(ctx))),
+            pre_command: |ctx| Box::pin(logging::pre_command(ctx)),
+            post_command: |ctx| Box::pin(logging::post_command(ctx)),
+            on_error: |error| Box::pin(error::on_error(error)),
             ..Default::default()
         })
         .setup(|ctx, _ready, framework| {
@@ -118,5 +213,5 @@ async fn main() -> Result<()> {
         .await?;
     client.start().await?;
 
-    Ok(())
+    Ok(ExitCode::SUCCESS)
 }
@@ -0,0 +1,321 @@
+use crate::{
+    Context,
+    error::WattouError,
+    guild_data::{SavedData, SavedDataWithDefault},
+};
+use arc_swap::ArcSwap;
+use color_eyre::Result;
+use once_cell::sync::Lazy;
+use poise::ChoiceParameter;
+use serde::{Deserialize, Serialize};
+use serenity::all::{GuildId, Mention, Permissions, RoleId};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::{self, Display, Formatter},
+    str::FromStr,
+    sync::Arc,
+};
+
+/// Set with the `category` attribute of the commands, commands without one are consultation ones
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Serialize,
+    Deserialize,
+    poise::ChoiceParameter,
+)]
+pub enum CommandCategory {
+    Consultation,
+    Administration,
+    #[name = "Modération"]
+    Moderation,
+    /// Publishing the programmes
+    Enseignement,
+}
+
+impl CommandCategory {
+    pub const ALL: [Self; 4] = [
+        Self::Consultation,
+        Self::Administration,
+        Self::Moderation,
+        Self::Enseignement,
+    ];
+
+    pub fn of(command: &poise::Command<impl Send + Sync, impl Send + Sync>) -> Self {
+        command
+            .category
+            .as_deref()
+            .and_then(|category| category.parse().ok())
+            .unwrap_or(Self::Consultation)
+    }
+
+    fn default_rule(&self) -> Rule {
+        Rule {
+            roles: vec![],
+            permissions: match self {
+                Self::Consultation => Permissions::empty(),
+                Self::Administration => Permissions::MANAGE_GUILD,
+                Self::Moderation | Self::Enseignement => Permissions::MANAGE_MESSAGES,
+            },
+        }
+    }
+}
+
+impl FromStr for CommandCategory {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|category| category.name() == s || format!("{category:?}") == s)
+            .ok_or(())
+    }
+}
+
+/// A member is allowed if they have one of the roles or all the permissions, an empty rule
+/// allows everyone
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rule {
+    pub roles: Vec<RoleId>,
+    pub permissions: Permissions,
+}
+
+impl Rule {
+    pub fn is_empty(&self) -> bool {
+        self.roles.is_empty() && self.permissions.is_empty()
+    }
+
+    pub fn allows(&self, roles: &[RoleId], permissions: Permissions) -> bool {
+        self.is_empty()
+            || permissions.administrator()
+            || self.roles.iter().any(|role| roles.contains(role))
+            || (!self.permissions.is_empty() && permissions.contains(self.permissions))
+    }
+}
+
+impl Display for Rule {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return f.write_str("tout le monde");
+        }
+
+        let mut parts = self
+            .roles
+            .iter()
+            .map(|role| Mention::from(*role).to_string())
+            .collect::<Vec<_>>();
+        if !self.permissions.is_empty() {
+            parts.push(format!(
+                "permission {}",
+                self.permissions.get_permission_names().join(" + ")
+            ));
+        }
+        f.write_str(&parts.join(" ou "))
+    }
+}
+
+/// Who can run which command in a guild, the rule of a command replaces the one of its category
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PermissionConfig {
+    pub categories: BTreeMap<CommandCategory, Rule>,
+    pub commands: BTreeMap<String, Rule>,
+}
+
+impl SavedData for PermissionConfig {
+    const FILE_NAME: &'static str = "permissions.json";
+
+    fn ser(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    fn de(value: &str) -> Result<Self> {
+        Ok(serde_json::from_str(value)?)
+    }
+}
+
+/// Permissions that can be required with `/permissions exiger_permission`
+#[derive(Debug, Clone, Copy, poise::ChoiceParameter)]
+pub enum PermissionChoice {
+    Aucune,
+    #[name = "Gérer le serveur"]
+    ManageGuild,
+    #[name = "Gérer les messages"]
+    ManageMessages,
+    #[name = "Gérer les rôles"]
+    ManageRoles,
+    Administrateur,
+}
+
+impl From<PermissionChoice> for Permissions {
+    fn from(value: PermissionChoice) -> Self {
+        match value {
+            PermissionChoice::Aucune => Permissions::empty(),
+            PermissionChoice::ManageGuild => Permissions::MANAGE_GUILD,
+            PermissionChoice::ManageMessages => Permissions::MANAGE_MESSAGES,
+            PermissionChoice::ManageRoles => Permissions::MANAGE_ROLES,
+            PermissionChoice::Administrateur => Permissions::ADMINISTRATOR,
+        }
+    }
+}
+
+/// What a rule of [PermissionConfig] applies to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RuleTarget {
+    Category(CommandCategory),
+    Command(String),
+}
+
+impl RuleTarget {
+    pub fn parse(s: &str, command_names: &[String]) -> Option<Self> {
+        if let Ok(category) = s.parse() {
+            Some(Self::Category(category))
+        } else {
+            let name = s.trim_start_matches('/');
+            command_names
+                .iter()
+                .any(|command| command == name)
+                .then(|| Self::Command(name.to_string()))
+        }
+    }
+}
+
+impl Display for RuleTarget {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Category(category) => write!(f, "catégorie {}", category.name()),
+            Self::Command(command) => write!(f, "commande /{command}"),
+        }
+    }
+}
+
+/// Permissions of each guild, read from the storage once then kept in sync by
+/// [PermissionConfig::edit]
+static CACHE: Lazy<ArcSwap<HashMap<GuildId, Arc<PermissionConfig>>>> = Lazy::new(ArcSwap::default);
+
+impl PermissionConfig {
+    /// Command whose subcommands members with the Manage Server permission can always run, so
+    /// that a rule cannot lock the admins out
+    const PERMISSIONS_COMMAND: &'static str = "permissions";
+
+    pub fn cached(guild_id: GuildId) -> Result<Arc<Self>> {
+        if let Some(config) = CACHE.load().get(&guild_id) {
+            return Ok(config.clone());
+        }
+        let config = Arc::new(Self::read(guild_id).transpose()?.unwrap_or_default());
+        Self::publish(guild_id, config.clone());
+        Ok(config)
+    }
+
+    fn publish(guild_id: GuildId, config: Arc<Self>) {
+        CACHE.rcu(|cache| {
+            let mut cache = HashMap::clone(cache);
+            cache.insert(guild_id, config.clone());
+            cache
+        });
+    }
+
+    /// Saves the changes and publishes them to the next checks
+    pub fn edit<R>(guild_id: GuildId, f: impl FnOnce(&mut Self) -> Result<R>) -> Result<R> {
+        let (res, config) = Self::update(guild_id, |config| {
+            let res = f(config)?;
+            Ok((res, config.clone()))
+        })?;
+        Self::publish(guild_id, Arc::new(config));
+        Ok(res)
+    }
+
+    pub fn category_rule(&self, category: CommandCategory) -> Rule {
+        self.categories
+            .get(&category)
+            .cloned()
+            .unwrap_or_else(|| category.default_rule())
+    }
+
+    pub fn rule(&self, command: &str, category: CommandCategory) -> Rule {
+        self.commands
+            .get(command)
+            .cloned()
+            .unwrap_or_else(|| self.category_rule(category))
+    }
+
+    pub fn target_rule(&self, target: &RuleTarget, category: CommandCategory) -> Rule {
+        match target {
+            RuleTarget::Category(category) => self.category_rule(*category),
+            RuleTarget::Command(command) => self.rule(command, category),
+        }
+    }
+
+    pub fn set_rule(&mut self, target: &RuleTarget, rule: Rule) {
+        match target {
+            RuleTarget::Category(category) => {
+                self.categories.insert(*category, rule);
+            }
+            RuleTarget::Command(command) => {
+                self.commands.insert(command.clone(), rule);
+            }
+        }
+    }
+
+    /// Goes back to the rule of the category, or the default one for a category
+    pub fn reset_rule(&mut self, target: &RuleTarget) {
+        match target {
+            RuleTarget::Category(category) => {
+                self.categories.remove(category);
+            }
+            RuleTarget::Command(command) => {
+                self.commands.remove(command);
+            }
+        }
+    }
+}
+
+/// Pre-command hook checking the member is allowed to run the command in this guild
+pub async fn check(ctx: Context<'_>) -> Result<bool> {
+    let command = ctx.command();
+    let category = CommandCategory::of(command);
+
+    let Some(guild_id) = ctx.guild_id() else {
+        return match category {
+            CommandCategory::Consultation => Ok(true),
+            _ => Err(WattouError::CommandCanOnlyBeUsedInGuilds)?,
+        };
+    };
+
+    let config = PermissionConfig::cached(guild_id)?;
+    let rule = config.rule(&command.qualified_name, category);
+    let (roles, permissions) = author_roles(ctx).await;
+
+    let is_permissions_command = command
+        .qualified_name
+        .split(' ')
+        .next()
+        .is_some_and(|name| name == PermissionConfig::PERMISSIONS_COMMAND);
+    if rule.allows(&roles, permissions) || (is_permissions_command && permissions.manage_guild()) {
+        Ok(true)
+    } else {
+        Err(WattouError::MissingPermission(rule.to_string()))?
+    }
+}
+
+async fn author_roles(ctx: Context<'_>) -> (Vec<RoleId>, Permissions) {
+    ctx.author_member()
+        .await
+        .as_ref()
+        .map(|member| (member.roles.clone(), member.permissions.unwrap_or_default()))
+        .unwrap_or_default()
+}
+
+/// Whether the author may run the commands of the Administration category
+pub async fn is_admin(ctx: Context<'_>) -> Result<bool> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(false);
+    };
+    let (roles, permissions) = author_roles(ctx).await;
+    Ok(PermissionConfig::cached(guild_id)?
+        .category_rule(CommandCategory::Administration)
+        .allows(&roles, permissions))
+}
@@ -1,8 +1,19 @@
-use std::sync::Arc;
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display, Formatter},
+    sync::Arc,
+};
 
-use time::OffsetDateTime;
+use color_eyre::Result;
+use serde::{Deserialize, Serialize};
+use serenity::all::UserId;
 
-use crate::{colle::Colle, group::GroupId, guild_data::GuildData};
+use crate::{
+    class::Class,
+    ghost::OpenPlaces,
+    guild_data::{GuildData, SavedData},
+    index::{ColleQuery, IndexedColle},
+};
 
 #[derive(Debug, Clone)]
 pub struct Prof {
@@ -17,14 +28,14 @@ impl PartialEq for Prof {
 
 impl Eq for Prof {}
 
-impl ToString for Prof {
-    fn to_string(&self) -> String {
-        self.name.to_string()
+impl Display for Prof {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.name)
     }
 }
 
 impl Prof {
-    pub fn name<'a>(&'a self) -> &'a str {
+    pub fn name(&self) -> &str {
         &self.name
     }
 
@@ -32,23 +43,72 @@ impl Prof {
         Self { name }
     }
 
-    pub fn get_next_colles_in_guild(
+    /// Next colles of the prof in every class of the guild, sorted by start
+    ///
+    /// Colles of ghost groups are left out, unless they are places libres claimed by a student
+    pub fn get_next_colles_in_guild<'a>(
         &self,
-        guild_data: Arc<GuildData>,
+        guild_data: &'a GuildData,
+        places: &OpenPlaces,
         limit: usize,
-    ) -> Vec<(GroupId, Colle)> {
-        let now = OffsetDateTime::now_utc();
+    ) -> Vec<(&'a Class, &'a IndexedColle)> {
+        let query = ColleQuery::upcoming().prof(self.name()).with_ghosts();
         let mut colles = guild_data
-            .groups
+            .classes
             .iter()
-            .flat_map(|groupe| {
-                groupe.colles.iter().filter_map(|colle| {
-                    (*self == *colle.prof && colle.end > now).then_some((groupe.id, colle.clone()))
-                })
+            .flat_map(|class| {
+                class
+                    .query(&query)
+                    .into_iter()
+                    .filter(move |indexed| {
+                        class.ghost_of(indexed).is_none()
+                            || places.claimant(class, indexed).is_some()
+                    })
+                    .map(move |indexed| (class, indexed))
             })
             .collect::<Vec<_>>();
-        colles.sort_by(|(_, a), (_, b)| a.cmp(b));
+        colles.sort_by(|(_, a), (_, b)| a.colle.cmp(&b.colle));
+        colles.truncate(limit);
+        colles
+    }
+}
+
+/// Discord member of each colleur, set with `/colleur lier`, to send them messages
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ProfLinks {
+    members: BTreeMap<String, UserId>,
+}
+
+impl SavedData for ProfLinks {
+    const FILE_NAME: &'static str = "colleurs.json";
+
+    fn ser(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+
+    fn de(value: &str) -> Result<Self> {
+        Ok(serde_json::from_str(value)?)
+    }
+}
+
+impl ProfLinks {
+    pub fn get(&self, prof: &Prof) -> Option<UserId> {
+        self.members.get(prof.name()).copied()
+    }
+
+    /// Name of the colleur linked to the member
+    pub fn prof_of(&self, user_id: UserId) -> Option<&str> {
+        self.members
+            .iter()
+            .find(|(_, member)| **member == user_id)
+            .map(|(name, _)| name.as_str())
+    }
 
-        colles[..limit].to_vec()
+    /// Links the colleur to the member, or removes the link when `user_id` is [None]
+    pub fn set(&mut self, prof: &Prof, user_id: Option<UserId>) {
+        match user_id {
+            Some(user_id) => self.members.insert(prof.name().to_string(), user_id),
+            None => self.members.remove(prof.name()),
+        };
     }
 }
@@ -0,0 +1,326 @@
+//! Programmes de colle: what each subject covers during a week, published by the teachers with
+//! `/programme publier`, shown with the colles and posted in the programme channel when the week
+//! starts.
+//!
+//! The text is saved with the other data of the guild, the files in the `programmes` folder of
+//! the guild so that they outlive the Discord links.
+use crate::{
+    class::Class,
+    colle::{Colle, ColleId},
+    error::WattouError,
+    guild_data::{GuildData, SavedData, SavedDataWithDefault},
+    utils::{self, month_to_short_fr},
+    weeks::DATE_FORMAT,
+};
+use color_eyre::Result;
+use serde::{Deserialize, Serialize};
+use serenity::all::{ChannelId, CreateAttachment, CreateMessage, GuildId, Http, UserId};
+use std::{fs, path::PathBuf};
+use time::{Date, Duration, OffsetDateTime, Weekday};
+use tracing::{info, warn};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Programme {
+    pub class: String,
+    /// Unix timestamp of the monday of the week
+    pub monday: i64,
+    pub subject: char,
+    pub text: String,
+    /// Names of the files saved in [Programme::folder]
+    pub files: Vec<String>,
+    pub author: UserId,
+    /// Whether it was posted in the [ProgrammeChannel]
+    pub posted: bool,
+}
+
+impl Programme {
+    /// Longest text shown next to a colle
+    const EXCERPT_LEN: usize = 150;
+    /// Longest chapter given to the exercises of the week
+    const CHAPTER_LEN: usize = 80;
+
+    pub fn new(class: &Class, monday: Date, subject: char, text: String, author: UserId) -> Self {
+        Self {
+            class: class.name.to_string(),
+            monday: timestamp(monday),
+            subject,
+            text,
+            files: vec![],
+            author,
+            posted: false,
+        }
+    }
+
+    pub fn monday(&self) -> Date {
+        OffsetDateTime::from_unix_timestamp(self.monday)
+            .unwrap_or(OffsetDateTime::UNIX_EPOCH)
+            .date()
+    }
+
+    fn subject_name(&self) -> String {
+        ColleId::subject_name(self.subject)
+            .map(str::to_string)
+            .unwrap_or_else(|| self.subject.to_string())
+    }
+
+    /// Where the files of the programme are saved
+    pub fn folder(&self, guild_id: GuildId) -> PathBuf {
+        GuildData::folder(guild_id).join("programmes").join(format!(
+            "{}-{}-{}",
+            self.class,
+            self.monday().format(DATE_FORMAT).unwrap_or_default(),
+            self.subject
+        ))
+    }
+
+    /// e.g. : Programme de Maths, semaine du 13 Jan
+    pub fn title(&self) -> String {
+        let monday = self.monday();
+        format!(
+            "Programme de {}, semaine du {} {}",
+            self.subject_name(),
+            monday.day(),
+            month_to_short_fr(monday.month())
+        )
+    }
+
+    /// Title, text and names of the files
+    pub fn message(&self, class_suffix: &str) -> String {
+        let mut message = format!("# {}{class_suffix}\n{}", self.title(), self.text);
+        if !self.files.is_empty() {
+            message += &format!("\n📎 {}", self.files.join(", "));
+        }
+        message
+    }
+
+    /// Start of the text, shown next to a colle
+    pub fn excerpt(&self) -> String {
+        format!("📚 {}", utils::excerpt(&self.text, Self::EXCERPT_LEN))
+    }
+
+    /// First line of the text, usually the chapter
+    pub fn chapter(&self) -> Option<String> {
+        self.text
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty())
+            .map(|line| utils::excerpt(line, Self::CHAPTER_LEN))
+    }
+
+    /// Replaces the files of [Programme::folder] with the downloaded ones, they are written next
+    /// to it first so that a failure keeps the previous files
+    pub fn replace_files(&mut self, guild_id: GuildId, files: &[(String, Vec<u8>)]) -> Result<()> {
+        let folder = self.folder(guild_id);
+        let mut tmp_name = folder.file_name().unwrap_or_default().to_owned();
+        tmp_name.push(".tmp");
+        let tmp = folder.with_file_name(tmp_name);
+        let _ = fs::remove_dir_all(&tmp);
+
+        self.files.clear();
+        for (name, content) in files {
+            let name = utils::save_file(&tmp, name, content)?;
+            if !self.files.contains(&name) {
+                self.files.push(name);
+            }
+        }
+
+        self.remove_files(guild_id);
+        if fs::exists(&tmp)? {
+            fs::rename(tmp, folder)?;
+        }
+        Ok(())
+    }
+
+    pub async fn attachments(&self, guild_id: GuildId) -> Vec<CreateAttachment> {
+        let folder = self.folder(guild_id);
+        let mut attachments = vec![];
+        for name in &self.files {
+            match CreateAttachment::path(folder.join(name)).await {
+                Ok(attachment) => attachments.push(attachment),
+                Err(e) => warn!(file = %name, error = %e, "missing programme file"),
+            }
+        }
+        attachments
+    }
+
+    /// Deletes the files of the programme
+    pub fn remove_files(&self, guild_id: GuildId) {
+        let _ = fs::remove_dir_all(self.folder(guild_id));
+    }
+}
+
+/// Unix timestamp of midnight of the day, like the times of [Colle]
+fn timestamp(date: Date) -> i64 {
+    date.midnight().assume_utc().unix_timestamp()
+}
+
+/// Monday of the week of the day
+pub fn monday_of(date: Date) -> Date {
+    date - Duration::days(date.weekday().number_days_from_monday() as i64)
+}
+
+/// Monday of the week given as a date, a week number or a label of the `weeks` file, by default
+/// the current week, or the next one during the weekend
+pub fn parse_week(class: &Class, week: Option<&str>) -> Result<Date> {
+    let Some(week) = week else {
+        let today = OffsetDateTime::now_utc().date();
+        return Ok(match today.weekday() {
+            Weekday::Saturday | Weekday::Sunday => monday_of(today) + Duration::weeks(1),
+            _ => monday_of(today),
+        });
+    };
+    if let Ok(date) = Date::parse(week, DATE_FORMAT) {
+        return Ok(monday_of(date));
+    }
+
+    // Week numbers restart with each period, the active one comes first
+    let active = class.active_period();
+    std::iter::once(active)
+        .chain(class.periods.iter())
+        .find_map(|period| {
+            let id = period.weeks.find(week)?;
+            period.weeks.0.iter().find(|w| w.id == id)
+        })
+        .map(|week| week.monday)
+        .ok_or(WattouError::WeekNotFound(week.to_string()).into())
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Programmes {
+    programmes: Vec<Programme>,
+}
+
+impl SavedData for Programmes {
+    const FILE_NAME: &'static str = "programmes.json";
+
+    fn ser(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+
+    fn de(value: &str) -> Result<Self> {
+        Ok(serde_json::from_str(value)?)
+    }
+}
+
+impl Programmes {
+    pub fn get(&self, class: &Class, monday: Date, subject: char) -> Option<&Programme> {
+        let monday = timestamp(monday);
+        self.programmes.iter().find(|programme| {
+            programme.class == *class.name
+                && programme.monday == monday
+                && programme.subject == subject
+        })
+    }
+
+    /// Programmes of the week, sorted by subject
+    pub fn of_week(&self, class: &Class, monday: Date) -> Vec<&Programme> {
+        let monday = timestamp(monday);
+        let mut programmes = self
+            .programmes
+            .iter()
+            .filter(|programme| programme.class == *class.name && programme.monday == monday)
+            .collect::<Vec<_>>();
+        programmes.sort_by_key(|programme| programme.subject);
+        programmes
+    }
+
+    /// Programme of the subject of the colle during its week
+    pub fn for_colle(&self, class: &Class, colle: &Colle) -> Option<&Programme> {
+        self.get(class, monday_of(colle.start.date()), colle.id.0)
+    }
+
+    /// Adds the programme, replacing the one of the same week and subject, which is returned
+    pub fn set(&mut self, programme: Programme) -> Option<Programme> {
+        let previous = self.remove_where(|other| {
+            other.class == programme.class
+                && other.monday == programme.monday
+                && other.subject == programme.subject
+        });
+        self.programmes.push(programme);
+        previous
+    }
+
+    pub fn remove(&mut self, class: &Class, monday: Date, subject: char) -> Result<Programme> {
+        let monday = timestamp(monday);
+        Ok(self
+            .remove_where(|programme| {
+                programme.class == *class.name
+                    && programme.monday == monday
+                    && programme.subject == subject
+            })
+            .ok_or(WattouError::ProgrammeNotFound)?)
+    }
+
+    fn remove_where(&mut self, filter: impl Fn(&Programme) -> bool) -> Option<Programme> {
+        let position = self.programmes.iter().position(filter)?;
+        Some(self.programmes.remove(position))
+    }
+}
+
+/// Channel where the programmes are posted when their week starts
+pub struct ProgrammeChannel(pub ChannelId);
+
+impl SavedData for ProgrammeChannel {
+    const FILE_NAME: &'static str = "programme_channel";
+
+    fn ser(&self) -> String {
+        self.0.to_string()
+    }
+
+    fn de(value: &str) -> Result<Self> {
+        Ok(Self(value.trim().parse()?))
+    }
+}
+
+/// Posts the programmes of the current week not posted yet in the programme channel
+pub async fn post_programmes(guild_data: &GuildData, http: &Http) -> Result<()> {
+    let Some(channel) = ProgrammeChannel::read(guild_data.guild_id) else {
+        return Ok(());
+    };
+    let channel = channel?.0;
+    let monday = timestamp(monday_of(OffsetDateTime::now_utc().date()));
+
+    let programmes = Programmes::read_or_default(guild_data.guild_id)?;
+    let to_post = programmes
+        .programmes
+        .iter()
+        .filter(|programme| programme.monday == monday && !programme.posted)
+        .collect::<Vec<_>>();
+    if to_post.is_empty() {
+        return Ok(());
+    }
+
+    let mut posted = 0;
+    for programme in to_post {
+        let suffix = match guild_data.class(Some(&programme.class)) {
+            Ok(_) if guild_data.has_several_classes() => format!(" ({})", programme.class),
+            Ok(_) => String::new(),
+            // Programmes of a class removed from the colloscope
+            Err(_) => continue,
+        };
+        channel
+            .send_message(
+                http,
+                CreateMessage::new()
+                    .content(programme.message(&suffix))
+                    .add_files(programme.attachments(guild_data.guild_id).await),
+            )
+            .await?;
+        // Saved after each message so that a later failure does not post this one again
+        Programmes::update(guild_data.guild_id, |programmes| {
+            for saved in programmes.programmes.iter_mut() {
+                if saved.monday == monday
+                    && saved.class == programme.class
+                    && saved.subject == programme.subject
+                {
+                    saved.posted = true;
+                }
+            }
+            Ok(())
+        })?;
+        posted += 1;
+    }
+
+    info!(posted, "posted programmes");
+    Ok(())
+}
@@ -0,0 +1,140 @@
+use crate::{
+    guild_data::SavedData,
+    recurrent_message::{ClassBoards, SemaineTPMessage, ToutesLesCollesMessage},
+};
+use color_eyre::{Report, Result};
+use serenity::all::{ChannelId, GetMessages, GuildId, Http, Message, MessageId, UserId};
+use std::str::FromStr;
+use time::{Duration, OffsetDateTime};
+
+/// Which messages of the channel `/clear` deletes
+#[derive(Debug, Default, Clone)]
+pub struct PurgeFilter {
+    pub bots_only: bool,
+    pub author: Option<UserId>,
+    pub contains: Option<String>,
+    pub before: Option<MessageId>,
+    pub after: Option<MessageId>,
+}
+
+impl PurgeFilter {
+    fn matches(&self, message: &Message, protected: &[MessageId]) -> bool {
+        !protected.contains(&message.id)
+            && (!self.bots_only || message.author.bot)
+            && self.author.is_none_or(|author| message.author.id == author)
+            && self.contains.as_ref().is_none_or(|text| {
+                message
+                    .content
+                    .to_lowercase()
+                    .contains(&text.to_lowercase())
+            })
+    }
+}
+
+/// A message id given by hand, either the id itself or a link to the message
+pub fn parse_message_id(s: &str) -> Option<MessageId> {
+    let id = s.trim().rsplit('/').next()?;
+    u64::from_str(id)
+        .ok()
+        .filter(|id| *id != 0)
+        .map(MessageId::new)
+}
+
+pub struct Purge {
+    pub channel_id: ChannelId,
+    pub filter: PurgeFilter,
+    pub limit: usize,
+}
+
+impl Purge {
+    /// Messages are only looked for among the last ones, filters may match very few of them
+    const MAX_SCANNED: usize = 1000;
+    /// Discord refuses to bulk delete messages older than two weeks
+    const BULK_DELETE_MAX_AGE: Duration = Duration::days(14);
+    const BULK_DELETE_MAX_COUNT: usize = 100;
+
+    /// Messages that will be deleted, most recent first
+    pub async fn find_messages(&self, http: &Http, guild_id: GuildId) -> Result<Vec<Message>> {
+        let class_boards = ClassBoards::read(guild_id)
+            .and_then(Result::ok)
+            .unwrap_or_default();
+        let protected = [
+            SemaineTPMessage::read(guild_id)
+                .and_then(Result::ok)
+                .map(|message| message.inner().0),
+            ToutesLesCollesMessage::read(guild_id)
+                .and_then(Result::ok)
+                .map(|message| message.inner().0),
+        ]
+        .into_iter()
+        .flatten()
+        .chain(class_boards.iter().map(|(_, message)| message.inner().0))
+        .collect::<Vec<_>>();
+
+        let mut found = Vec::new();
+        let mut scanned = 0;
+        let mut before = self.filter.before;
+
+        'pages: while found.len() < self.limit && scanned < Self::MAX_SCANNED {
+            let mut request = GetMessages::new().limit(100);
+            if let Some(before) = before {
+                request = request.before(before);
+            }
+            let page = self.channel_id.messages(http, request).await?;
+            if page.is_empty() {
+                break;
+            }
+            scanned += page.len();
+            before = page.last().map(|message| message.id);
+
+            for message in page {
+                if self.filter.after.is_some_and(|after| message.id <= after) {
+                    break 'pages;
+                }
+                if self.filter.matches(&message, &protected) {
+                    found.push(message);
+                    if found.len() == self.limit {
+                        break 'pages;
+                    }
+                }
+            }
+        }
+
+        Ok(found)
+    }
+
+    /// Bulk deletes the recent messages and deletes the older ones one by one, returns how many
+    /// messages were deleted, along with the error that stopped the deletion
+    pub async fn delete(&self, http: &Http, messages: Vec<Message>) -> (usize, Option<Report>) {
+        let bulk_limit =
+            OffsetDateTime::now_utc() - Self::BULK_DELETE_MAX_AGE + Duration::minutes(5);
+        let (recent, old): (Vec<_>, Vec<_>) = messages.into_iter().partition(|message| {
+            OffsetDateTime::from_unix_timestamp(message.id.created_at().unix_timestamp())
+                .is_ok_and(|created| created > bulk_limit)
+        });
+
+        let mut deleted = 0;
+        for chunk in recent.chunks(Self::BULK_DELETE_MAX_COUNT) {
+            let result = if let [message] = chunk {
+                self.channel_id.delete_message(http, message.id).await
+            } else {
+                self.channel_id
+                    .delete_messages(http, chunk.iter().map(|message| message.id))
+                    .await
+            };
+            if let Err(e) = result {
+                return (deleted, Some(e.into()));
+            }
+            deleted += chunk.len();
+        }
+
+        for message in old {
+            if let Err(e) = self.channel_id.delete_message(http, message.id).await {
+                return (deleted, Some(e.into()));
+            }
+            deleted += 1;
+        }
+
+        (deleted, None)
+    }
+}
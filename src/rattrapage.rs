@@ -0,0 +1,201 @@
+//! Rattrapages: colles outside of the colloscope offered by the colleurs with
+//! `/rattrapage proposer`, booked by the students who missed a colle of the same subject.
+//!
+//! A booked rattrapage is an extra [Colle] of the student, shown by `/mes_colles`, in their
+//! calendar and in their reminders.
+use crate::{
+    class::Class,
+    colle::{Colle, ColleId, ColleStringFormat},
+    error::WattouError,
+    guild_data::SavedData,
+    prof::Prof,
+};
+use color_eyre::Result;
+use serde::{Deserialize, Serialize};
+use serenity::all::UserId;
+use std::{collections::HashMap, sync::Arc};
+use time::OffsetDateTime;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RattrapageSlot {
+    pub id: u32,
+    pub class: String,
+    pub prof: String,
+    pub subject: char,
+    pub room: String,
+    /// Unix timestamps, stored as UTC like the times of [Colle]
+    pub start: i64,
+    pub end: i64,
+    /// Students the colleur can take at once
+    pub capacity: usize,
+    pub booked: Vec<UserId>,
+}
+
+impl RattrapageSlot {
+    /// The rattrapage as a colle, `profs` are the colleurs of the guild
+    pub fn colle(&self, profs: &HashMap<Arc<str>, Arc<Prof>>) -> Colle {
+        let timestamp =
+            |t| OffsetDateTime::from_unix_timestamp(t).unwrap_or(OffsetDateTime::UNIX_EPOCH);
+        Colle {
+            id: ColleId(self.subject, ColleId::RATTRAPAGE),
+            prof: profs
+                .get(self.prof.as_str())
+                .cloned()
+                .unwrap_or_else(|| Arc::new(Prof::new(Arc::from(self.prof.as_str())))),
+            room: self.room.clone(),
+            start: timestamp(self.start),
+            end: timestamp(self.end),
+        }
+    }
+
+    pub fn places_left(&self) -> usize {
+        self.capacity.saturating_sub(self.booked.len())
+    }
+
+    pub fn is_upcoming(&self) -> bool {
+        self.end > OffsetDateTime::now_utc().unix_timestamp()
+    }
+
+    /// e.g. : n°3 Rattrapage Maths: Lundi 13 Jan 17h-18h avec Dupont en 207, 2 place(s)
+    pub fn describe(&self, profs: &HashMap<Arc<str>, Arc<Prof>>) -> String {
+        format!(
+            "n°{} {}, {} place(s)",
+            self.id,
+            self.colle(profs)
+                .format(ColleStringFormat::Explicit, vec![]),
+            self.places_left()
+        )
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Rattrapages {
+    next_id: u32,
+    slots: Vec<RattrapageSlot>,
+}
+
+impl SavedData for Rattrapages {
+    const FILE_NAME: &'static str = "rattrapages.json";
+
+    fn ser(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+
+    fn de(value: &str) -> Result<Self> {
+        Ok(serde_json::from_str(value)?)
+    }
+}
+
+impl Rattrapages {
+    /// Adds the slot with a new id, returns the slot
+    pub fn add(&mut self, mut slot: RattrapageSlot) -> RattrapageSlot {
+        self.next_id += 1;
+        slot.id = self.next_id;
+        self.slots.push(slot.clone());
+        slot
+    }
+
+    pub fn get(&self, id: u32) -> Result<&RattrapageSlot> {
+        Ok(self
+            .slots
+            .iter()
+            .find(|slot| slot.id == id)
+            .ok_or(WattouError::RattrapageNotFound)?)
+    }
+
+    /// Upcoming slots of every class, sorted by start
+    pub fn all_upcoming(&self) -> Vec<&RattrapageSlot> {
+        let mut slots = self
+            .slots
+            .iter()
+            .filter(|slot| slot.is_upcoming())
+            .collect::<Vec<_>>();
+        slots.sort_by_key(|slot| (slot.start, slot.id));
+        slots
+    }
+
+    /// Upcoming slots of the class, sorted by start
+    pub fn upcoming(&self, class: &Class) -> Vec<&RattrapageSlot> {
+        self.all_upcoming()
+            .into_iter()
+            .filter(|slot| slot.class == *class.name)
+            .collect()
+    }
+
+    /// Upcoming slots in one of the subjects with places left, not booked by the user yet
+    pub fn available_for(
+        &self,
+        class: &Class,
+        subjects: &[char],
+        user_id: UserId,
+    ) -> Vec<&RattrapageSlot> {
+        self.upcoming(class)
+            .into_iter()
+            .filter(|slot| {
+                subjects.contains(&slot.subject)
+                    && slot.places_left() > 0
+                    && !slot.booked.contains(&user_id)
+            })
+            .collect()
+    }
+
+    /// Upcoming slots booked by the user
+    pub fn booked_by(&self, class: &Class, user_id: UserId) -> Vec<&RattrapageSlot> {
+        self.upcoming(class)
+            .into_iter()
+            .filter(|slot| slot.booked.contains(&user_id))
+            .collect()
+    }
+
+    /// Upcoming rattrapages of the user as colles
+    pub fn colles_of(
+        &self,
+        class: &Class,
+        user_id: UserId,
+        profs: &HashMap<Arc<str>, Arc<Prof>>,
+    ) -> Vec<Colle> {
+        self.booked_by(class, user_id)
+            .into_iter()
+            .map(|slot| slot.colle(profs))
+            .collect()
+    }
+
+    pub fn book(&mut self, id: u32, user_id: UserId) -> Result<RattrapageSlot> {
+        let slot = self
+            .slots
+            .iter_mut()
+            .find(|slot| slot.id == id && slot.is_upcoming())
+            .ok_or(WattouError::RattrapageNotFound)?;
+        if !slot.booked.contains(&user_id) {
+            if slot.places_left() == 0 {
+                Err(WattouError::RattrapageFull(id))?
+            }
+            slot.booked.push(user_id);
+        }
+        Ok(slot.clone())
+    }
+
+    pub fn cancel_booking(&mut self, id: u32, user_id: UserId) -> Result<RattrapageSlot> {
+        let slot = self
+            .slots
+            .iter_mut()
+            .find(|slot| slot.id == id && slot.booked.contains(&user_id))
+            .ok_or(WattouError::RattrapageNotFound)?;
+        slot.booked.retain(|booked| *booked != user_id);
+        Ok(slot.clone())
+    }
+
+    pub fn remove(&mut self, id: u32) -> Result<RattrapageSlot> {
+        let position = self
+            .slots
+            .iter()
+            .position(|slot| slot.id == id)
+            .ok_or(WattouError::RattrapageNotFound)?;
+        Ok(self.slots.remove(position))
+    }
+
+    /// Forgets the slots that are over
+    pub fn remove_past(&mut self) {
+        self.slots.retain(RattrapageSlot::is_upcoming);
+    }
+}
@@ -1,7 +1,9 @@
-use crate::{debug, error::WattouError, guild_data::SavedData};
+use crate::{error::WattouError, guild_data::SavedData};
 use color_eyre::{Result, eyre::Context};
+use serde::{Deserialize, Serialize};
 use serenity::all::{ChannelId, EditMessage, Http, Message, MessageId};
-use std::borrow::Cow;
+use std::{borrow::Cow, collections::BTreeMap};
+use tracing::debug;
 
 pub struct RecurrentMessage<const ID: usize>((MessageId, ChannelId));
 
@@ -19,11 +21,13 @@ impl<const ID: usize> RecurrentMessage<ID> {
 
         message
             .edit(http, EditMessage::new().content(content_str.clone()))
-            .await.with_context(move || content_str)?;
+            .await
+            .with_context(move || content_str)?;
 
         debug!(
-            "edited message {} in channel {} successfully",
-            message.id, message.channel_id
+            message_id = %message.id,
+            channel_id = %message.channel_id,
+            "edited recurrent message"
         );
 
         Ok(())
@@ -56,3 +60,34 @@ impl<const ID: usize> From<&Cow<'_, Message>> for RecurrentMessage<ID> {
 
 pub type SemaineTPMessage = RecurrentMessage<0>;
 pub type ToutesLesCollesMessage = RecurrentMessage<1>;
+
+/// Boards of `/toutes_les_colles` showing a single class, by class name
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ClassBoards {
+    boards: BTreeMap<String, (MessageId, ChannelId)>,
+}
+
+impl SavedData for ClassBoards {
+    const FILE_NAME: &'static str = "class_boards.json";
+
+    fn ser(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+
+    fn de(value: &str) -> Result<Self> {
+        Ok(serde_json::from_str(value)?)
+    }
+}
+
+impl ClassBoards {
+    pub fn set(&mut self, class: &str, message: &Message) {
+        self.boards
+            .insert(class.to_string(), (message.id, message.channel_id));
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, ToutesLesCollesMessage)> {
+        self.boards
+            .iter()
+            .map(|(class, message)| (class.as_str(), RecurrentMessage(*message)))
+    }
+}
@@ -0,0 +1,236 @@
+//! Students of each group, imported by the admins with `/groupe importer` or declared by the
+//! students themselves with `/groupe choisir`.
+//!
+//! Each period of a class has its own roster, a period without one uses the roster of the
+//! previous period until the groups of the new semester are known.
+use crate::{
+    class::{Class, Period},
+    error::WattouError,
+    group::Group,
+    guild_data::{GuildData, SavedData},
+    weeks::strip_comment,
+};
+use color_eyre::Result;
+use serde::{Deserialize, Serialize};
+use serenity::all::{CreateMessage, Http, UserId};
+use std::collections::BTreeMap;
+use tracing::{info, warn};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RosterMember {
+    pub group_id: String,
+    /// e.g. : Jean Dupont, shown instead of the mention
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+/// Members of each group by class, then by period
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Roster {
+    classes: BTreeMap<String, BTreeMap<String, BTreeMap<UserId, RosterMember>>>,
+    /// Delegates of each class, told about absences
+    #[serde(default)]
+    delegates: BTreeMap<String, Vec<UserId>>,
+}
+
+impl SavedData for Roster {
+    const FILE_NAME: &'static str = "roster.json";
+
+    fn ser(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+
+    fn de(value: &str) -> Result<Self> {
+        Ok(serde_json::from_str(value)?)
+    }
+}
+
+impl Roster {
+    /// Roster of the active period, or of the last period before it that has one
+    fn current(&self, class: &Class) -> Option<&BTreeMap<UserId, RosterMember>> {
+        let periods = self.classes.get(class.name.as_ref())?;
+        let active = class.active_period();
+        class
+            .periods
+            .iter()
+            .rev()
+            .skip_while(|period| period.name != active.name)
+            .find_map(|period| {
+                periods
+                    .get(period.name.as_ref())
+                    .filter(|members| !members.is_empty())
+            })
+    }
+
+    /// Group of the user in the active period, [None] when the roster comes from an earlier
+    /// period and their group does not exist anymore
+    pub fn group_of<'a>(&self, class: &'a Class, user_id: UserId) -> Option<&'a Group> {
+        let member = self.current(class)?.get(&user_id)?;
+        class.active_period().group(&member.group_id)
+    }
+
+    /// Members of the group in the active period, sorted by user id
+    pub fn members(&self, class: &Class, group: &Group) -> Vec<(UserId, &RosterMember)> {
+        let active = class.active_period();
+        self.current(class)
+            .into_iter()
+            .flatten()
+            .filter(|(_, member)| {
+                active
+                    .group(&member.group_id)
+                    .is_some_and(|g| g.id == group.id)
+            })
+            .map(|(user_id, member)| (*user_id, member))
+            .collect()
+    }
+
+    /// Puts the user in a group for this period, replacing their previous group
+    pub fn set(&mut self, class: &Class, period: &Period, user_id: UserId, member: RosterMember) {
+        self.classes
+            .entry(class.name.to_string())
+            .or_default()
+            .entry(period.name.to_string())
+            .or_default()
+            .insert(user_id, member);
+    }
+
+    pub fn remove(&mut self, class: &Class, period: &Period, user_id: UserId) -> bool {
+        self.classes
+            .get_mut(class.name.as_ref())
+            .and_then(|periods| periods.get_mut(period.name.as_ref()))
+            .is_some_and(|members| members.remove(&user_id).is_some())
+    }
+
+    pub fn delegates(&self, class: &Class) -> &[UserId] {
+        self.delegates
+            .get(class.name.as_ref())
+            .map_or(&[], Vec::as_slice)
+    }
+
+    /// Adds the delegate, or removes them if they already are one, returns whether they are one
+    pub fn toggle_delegate(&mut self, class: &Class, user_id: UserId) -> bool {
+        let delegates = self.delegates.entry(class.name.to_string()).or_default();
+        if delegates.contains(&user_id) {
+            delegates.retain(|id| *id != user_id);
+            false
+        } else {
+            delegates.push(user_id);
+            true
+        }
+    }
+
+    /// Replaces the group positions saved before groups had ids by the declared ids of the
+    /// groups of their period, returns whether a member changed
+    pub fn resolve_group_ids(&mut self, guild_data: &GuildData) -> bool {
+        let mut changed = false;
+        for (class_name, periods) in self.classes.iter_mut() {
+            let Ok(class) = guild_data.class(Some(class_name)) else {
+                continue;
+            };
+            for (period_name, members) in periods.iter_mut() {
+                let Some(period) = class
+                    .periods
+                    .iter()
+                    .find(|period| *period.name == *period_name)
+                else {
+                    continue;
+                };
+                for member in members.values_mut() {
+                    if let Some(group_id) = period.legacy_group_id(&member.group_id) {
+                        member.group_id = group_id.to_string();
+                        changed = true;
+                    }
+                }
+            }
+        }
+        changed
+    }
+
+    /// Replaces the roster of the period
+    pub fn import(
+        &mut self,
+        class: &Class,
+        period: &Period,
+        members: BTreeMap<UserId, RosterMember>,
+    ) {
+        self.classes
+            .entry(class.name.to_string())
+            .or_default()
+            .insert(period.name.to_string(), members);
+    }
+
+    /// Parses an imported roster, one student per line : the group, the user id or mention then
+    /// optionally the name, e.g. `G7 <@123456789012345678> Jean Dupont`
+    pub fn parse_import(s: &str, period: &Period) -> Result<BTreeMap<UserId, RosterMember>> {
+        let mut members = BTreeMap::new();
+        for (i, line) in s.lines().enumerate() {
+            let Some(line) = strip_comment(line) else {
+                continue;
+            };
+            let error = |reason: String| WattouError::InvalidRoster {
+                line: i + 1,
+                reason,
+            };
+
+            let mut words = line.splitn(3, char::is_whitespace);
+            let (group_id, user) = (
+                words.next().unwrap_or_default(),
+                words.next().unwrap_or_default(),
+            );
+            if !period.groups.iter().any(|group| &*group.id == group_id) {
+                Err(error(format!(
+                    "groupe « {group_id} » inconnu en {}",
+                    period.name
+                )))?
+            }
+            let user_id = user
+                .trim_start_matches("<@")
+                .trim_start_matches('!')
+                .trim_end_matches('>')
+                .parse::<u64>()
+                .ok()
+                .filter(|id| *id != 0)
+                .map(UserId::new)
+                .ok_or_else(|| error(format!("« {user} » n'est pas un membre")))?;
+            let name = words
+                .next()
+                .map(|name| name.trim().to_string())
+                .filter(|name| !name.is_empty());
+
+            if members
+                .insert(
+                    user_id,
+                    RosterMember {
+                        group_id: group_id.to_string(),
+                        name,
+                    },
+                )
+                .is_some()
+            {
+                Err(error(format!("{user} est déjà dans un groupe")))?
+            }
+        }
+        Ok(members)
+    }
+}
+
+/// Sends the same DM to every user, returns how many were sent
+pub async fn send_dms(http: &Http, user_ids: &[UserId], content: &str) -> usize {
+    let mut sent = 0;
+    for user_id in user_ids {
+        let result = async {
+            let channel = user_id.create_dm_channel(http).await?;
+            channel
+                .send_message(http, CreateMessage::new().content(content))
+                .await?;
+            Ok::<_, color_eyre::Report>(())
+        }
+        .await;
+        match result {
+            Ok(()) => sent += 1,
+            Err(e) => warn!(user_id = %user_id, error = %e, "failed to send DM"),
+        }
+    }
+    info!(sent, total = user_ids.len(), "sent DMs");
+    sent
+}
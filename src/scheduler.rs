@@ -0,0 +1,104 @@
+use crate::{
+    config::config, error::WattouError, guild_data::GuildData, journal, programme,
+    utils::now_in_timezone,
+};
+use color_eyre::Result;
+use serenity::all::{GuildId, Http};
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+use tracing::{Instrument, debug, error, info, info_span};
+
+static STARTED: AtomicBool = AtomicBool::new(false);
+
+/// Refreshes the messages every `scheduler.refresh_interval_minutes`, only the first call starts
+/// the task so that reconnections do not start it again
+pub fn start(http: Arc<Http>) {
+    if STARTED.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    let period = Duration::from_secs(60 * config().scheduler.refresh_interval_minutes);
+    info!(period_minutes = period.as_secs() / 60, "starting scheduler");
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(period);
+        loop {
+            // Periods change at midnight, the board must not show the old one until the next tick
+            tokio::select! {
+                _ = interval.tick() => {}
+                _ = tokio::time::sleep(until_midnight()) => {
+                    info!("refreshing messages at midnight");
+                }
+            }
+            if let Err(e) = refresh_messages(&http).await {
+                error!(error = ?e, "failed to refresh messages");
+            }
+        }
+    });
+}
+
+/// Time left until the next midnight in `calendar.timezone`, plus a few seconds to be on the right
+/// day
+fn until_midnight() -> Duration {
+    let now = now_in_timezone();
+    let midnight = now.date().next_day().map(|day| {
+        // The offset changes on the nights of summer time
+        let midnight = day.midnight().assume_offset(now.offset());
+        day.midnight()
+            .assume_offset(config().calendar.offset_at(midnight))
+    });
+    midnight.map_or(Duration::from_secs(24 * 60 * 60), |midnight| {
+        Duration::try_from(midnight - now).unwrap_or_default() + Duration::from_secs(5)
+    })
+}
+
+/// Refreshes every guild in its own task, a guild that fails or panics is logged and does not
+/// stop the next ones
+pub async fn refresh_messages(http: &Arc<Http>) -> Result<()> {
+    let guilds = http.get_guilds(None, None).await?;
+    for guild in guilds {
+        let span = info_span!("guild", guild_id = %guild.id);
+        let refreshed =
+            tokio::spawn(refresh_guild(guild.id, http.clone()).instrument(span.clone()));
+        match refreshed.await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => span.in_scope(|| error!(error = ?e, "failed to refresh messages")),
+            Err(e) => span.in_scope(|| error!(error = ?e, "refreshing messages panicked")),
+        }
+    }
+
+    Ok(())
+}
+
+async fn refresh_guild(guild_id: GuildId, http: Arc<Http>) -> Result<()> {
+    match GuildData::get_from_id(guild_id) {
+        Ok(guild_data) => {
+            guild_data.try_edit_toutes_les_colles_msg(&http).await?;
+            if config().features.semaine_tp {
+                guild_data.edit_semaine_tp_msg(&http).await?;
+            }
+            if config().features.reminders {
+                guild_data.refresh_subscribers_message(&http).await?;
+            }
+            programme::post_programmes(&guild_data, &http).await?;
+            if config().features.journal {
+                journal::ask_grades(&guild_data, &http).await?;
+            }
+            debug!("refreshed messages");
+        }
+        Err(e) => {
+            if !e.is::<WattouError>()
+                || *e.downcast_ref::<WattouError>().unwrap()
+                    != WattouError::NoDataForGuild(guild_id)
+            {
+                return Err(e);
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,250 @@
+//! Persistence of the data written by the bot (subscribers, recurrent messages...).
+//!
+//! The colloscope source files (`colles`, `weeks`...) are written by hand by the admins
+//! and are still read directly from the data folder, see [crate::class::Class::new].
+mod file;
+mod sqlite;
+
+pub use file::FileStorage;
+pub use sqlite::SqliteStorage;
+
+use crate::guild_data::GuildData;
+use color_eyre::{Result, eyre::eyre};
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+use serenity::all::GuildId;
+use std::{fmt::Debug, fs, path::PathBuf};
+use time::OffsetDateTime;
+use tracing::{info, warn};
+
+/// A value saved for a guild along with the version of the format it was serialized with
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    pub version: u32,
+    pub value: String,
+}
+
+pub trait Storage: Send + Sync + Debug {
+    fn kind(&self) -> StorageKind;
+
+    fn read(&self, guild_id: GuildId, key: &str) -> Result<Option<Record>>;
+    fn write(&self, guild_id: GuildId, key: &str, record: &Record) -> Result<()>;
+    fn remove(&self, guild_id: GuildId, key: &str) -> Result<()>;
+
+    /// Every key saved for this guild
+    fn keys(&self, guild_id: GuildId) -> Result<Vec<String>>;
+
+    /// Read-modify-write of a record, no other access to the same key can happen in between
+    fn update(
+        &self,
+        guild_id: GuildId,
+        key: &str,
+        f: &mut dyn FnMut(Option<Record>) -> Result<Record>,
+    ) -> Result<()>;
+
+    fn exists(&self, guild_id: GuildId, key: &str) -> Result<bool> {
+        Ok(self.read(guild_id, key)?.is_some())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageKind {
+    #[serde(alias = "fichiers")]
+    Files,
+    Sqlite,
+}
+
+impl StorageKind {
+    fn open(&self) -> Result<Box<dyn Storage>> {
+        Ok(match self {
+            Self::Files => Box::new(FileStorage::new(GuildData::global_folder())),
+            Self::Sqlite => Box::new(SqliteStorage::open(Self::sqlite_path())?),
+        })
+    }
+
+    fn sqlite_path() -> PathBuf {
+        GuildData::global_folder().join(SqliteStorage::FILE_NAME)
+    }
+
+    /// The storage we may have to import data from when switching to `self`
+    fn previous(&self) -> Result<Option<Box<dyn Storage>>> {
+        Ok(match self {
+            Self::Sqlite => Some(Self::Files.open()?),
+            Self::Files => fs::exists(Self::sqlite_path())?
+                .then(|| Self::Sqlite.open())
+                .transpose()?,
+        })
+    }
+}
+
+/// Runs blocking storage work, letting the runtime move its other tasks to another thread when
+/// called from a command or the scheduler
+fn blocking<T>(f: impl FnOnce() -> T) -> T {
+    match tokio::runtime::Handle::try_current() {
+        Ok(handle) if handle.runtime_flavor() == tokio::runtime::RuntimeFlavor::MultiThread => {
+            tokio::task::block_in_place(f)
+        }
+        _ => f(),
+    }
+}
+
+static STORAGE: OnceCell<Box<dyn Storage>> = OnceCell::new();
+
+/// Written in a backend once its data has been imported in the other one, its records are
+/// then outdated and must not be imported again
+#[derive(Debug, Serialize, Deserialize)]
+struct ExportMarker {
+    to: StorageKind,
+    /// Unix timestamp of the import
+    at: i64,
+}
+
+impl ExportMarker {
+    const KEY: &'static str = "storage_export.json";
+    const VERSION: u32 = 1;
+
+    fn read(storage: &dyn Storage, guild_id: GuildId) -> Result<Option<Self>> {
+        storage
+            .read(guild_id, Self::KEY)?
+            .map(|record| Ok(serde_json::from_str(&record.value)?))
+            .transpose()
+    }
+
+    fn write(storage: &dyn Storage, guild_id: GuildId, to: StorageKind) -> Result<()> {
+        let marker = Self {
+            to,
+            at: OffsetDateTime::now_utc().unix_timestamp(),
+        };
+        storage.write(
+            guild_id,
+            Self::KEY,
+            &Record {
+                version: Self::VERSION,
+                value: serde_json::to_string(&marker)?,
+            },
+        )
+    }
+}
+
+/// Keys of the records of a guild, without the export marker
+fn data_keys(storage: &dyn Storage, guild_id: GuildId) -> Result<Vec<String>> {
+    Ok(storage
+        .keys(guild_id)?
+        .into_iter()
+        .filter(|key| key != ExportMarker::KEY)
+        .collect())
+}
+
+/// Opens the storage backend and imports the data of every guild that was saved with
+/// the other backend.
+///
+/// An import happens once: the other backend is then marked as exported and is ignored until
+/// the selected backend gets exported back to it. Startup fails when both backends hold data
+/// for a guild and none of them is marked, since we cannot tell which one is up to date.
+pub fn init(kind: StorageKind) -> Result<()> {
+    let storage = kind.open()?;
+
+    if let Some(previous) = kind.previous()? {
+        for guild_id in guild_ids()? {
+            import_guild(previous.as_ref(), storage.as_ref(), guild_id)?;
+        }
+    }
+
+    STORAGE
+        .set(storage)
+        .map_err(|_| eyre!("Le stockage est déjà initialisé"))
+}
+
+fn import_guild(previous: &dyn Storage, storage: &dyn Storage, guild_id: GuildId) -> Result<()> {
+    if data_keys(previous, guild_id)?.is_empty() {
+        return Ok(());
+    }
+    let export = ExportMarker::read(previous, guild_id)?;
+    let previous_exported = export.is_some();
+
+    if !data_keys(storage, guild_id)?.is_empty() {
+        match (
+            previous_exported,
+            ExportMarker::read(storage, guild_id)?.is_some(),
+        ) {
+            // Already imported, `previous` is outdated
+            (true, false) => return Ok(()),
+            // `storage` was exported to `previous` which has been used since then
+            (false, true) => {
+                for key in storage.keys(guild_id)? {
+                    storage.remove(guild_id, &key)?;
+                }
+            }
+            _ => {
+                let (previous, storage) = (previous.kind(), storage.kind());
+                return Err(eyre!(
+                    "Le serveur {guild_id} a des données dans les stockages {previous:?} et \
+                     {storage:?} sans savoir lesquelles sont à jour : supprimez celles du \
+                     stockage {storage:?} pour importer celles du stockage {previous:?}, ou \
+                     celles du stockage {previous:?} pour garder celles du stockage {storage:?}"
+                ));
+            }
+        }
+    } else if let Some(export) = export {
+        warn!(
+            guild_id = %guild_id,
+            exported_to = ?export.to,
+            exported_at = export.at,
+            "importing saved data which was already exported, the storage it was exported to is empty"
+        );
+    }
+
+    copy_guild(previous, storage, guild_id)?;
+    ExportMarker::write(previous, guild_id, storage.kind())
+}
+
+pub fn storage() -> &'static dyn Storage {
+    STORAGE
+        .get()
+        .expect("storage::init must be called before accessing saved data")
+        .as_ref()
+}
+
+pub fn copy_guild(from: &dyn Storage, to: &dyn Storage, guild_id: GuildId) -> Result<()> {
+    let keys = data_keys(from, guild_id)?;
+    for key in keys.iter() {
+        if let Some(record) = from.read(guild_id, key)? {
+            to.write(guild_id, key, &record)?;
+        }
+    }
+
+    if !keys.is_empty() {
+        info!(
+            guild_id = %guild_id,
+            records = keys.len(),
+            from = ?from.kind(),
+            to = ?to.kind(),
+            "migrated saved data between storages"
+        );
+    }
+    Ok(())
+}
+
+/// Guilds which have a folder in the data folder
+pub fn guild_ids() -> Result<Vec<GuildId>> {
+    let folder = GuildData::global_folder();
+    if !fs::exists(&folder)? {
+        return Ok(vec![]);
+    }
+
+    let mut ids = Vec::new();
+    for entry in fs::read_dir(folder)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir()
+            && let Some(id) = entry
+                .file_name()
+                .to_str()
+                .and_then(|s| s.parse::<u64>().ok())
+                .filter(|id| *id != 0)
+        {
+            ids.push(GuildId::new(id));
+        }
+    }
+    Ok(ids)
+}
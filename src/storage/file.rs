@@ -0,0 +1,214 @@
+use super::{Record, Storage, StorageKind};
+use crate::{
+    guild_data::SavedData,
+    recurrent_message::{SemaineTPMessage, ToutesLesCollesMessage},
+    subscriber::Subscribers,
+    utils::write_atomic,
+};
+use color_eyre::Result;
+use serde::{Deserialize, Serialize};
+use serenity::all::GuildId;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+/// One file per record in the folder of the guild, starting with the version of the record, the
+/// keys are listed in a manifest next to them
+#[derive(Debug)]
+pub struct FileStorage {
+    root: PathBuf,
+    locks: Mutex<HashMap<PathBuf, Arc<Mutex<()>>>>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Manifest {
+    /// Versions of the records written before the version was in the file, the keys of the
+    /// records written since map to 0
+    records: BTreeMap<String, u32>,
+}
+
+impl Manifest {
+    const FILE_NAME: &'static str = "storage.json";
+
+    /// Files written before the manifest existed, all of them are version 1
+    const LEGACY_KEYS: [&'static str; 3] = [
+        Subscribers::FILE_NAME,
+        SemaineTPMessage::FILE_NAME,
+        ToutesLesCollesMessage::FILE_NAME,
+    ];
+}
+
+impl FileStorage {
+    /// First line of the file of a record, followed by its version
+    const VERSION_HEADER: &'static str = "#version ";
+
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            locks: Mutex::default(),
+        }
+    }
+
+    fn path(&self, guild_id: GuildId, key: &str) -> PathBuf {
+        self.root.join(guild_id.to_string()).join(key)
+    }
+
+    fn lock(&self, path: &Path) -> Arc<Mutex<()>> {
+        self.locks
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(path.to_owned())
+            .or_default()
+            .clone()
+    }
+
+    fn read_manifest(&self, guild_id: GuildId) -> Result<Manifest> {
+        let path = self.path(guild_id, Manifest::FILE_NAME);
+        if fs::exists(&path)? {
+            return Ok(serde_json::from_str(&fs::read_to_string(path)?)?);
+        }
+
+        let mut manifest = Manifest::default();
+        for key in Manifest::LEGACY_KEYS {
+            if fs::exists(self.path(guild_id, key))? {
+                manifest.records.insert(key.to_owned(), 1);
+            }
+        }
+        Ok(manifest)
+    }
+
+    fn edit_manifest(&self, guild_id: GuildId, f: impl FnOnce(&mut Manifest)) -> Result<()> {
+        let path = self.path(guild_id, Manifest::FILE_NAME);
+        let lock = self.lock(&path);
+        let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+
+        let mut manifest = self.read_manifest(guild_id)?;
+        f(&mut manifest);
+        write_atomic(&path, &serde_json::to_string_pretty(&manifest)?)
+    }
+
+    fn read_unlocked(&self, guild_id: GuildId, key: &str) -> Result<Option<Record>> {
+        let path = self.path(guild_id, key);
+        if !fs::exists(&path)? {
+            return Ok(None);
+        }
+
+        let content = fs::read_to_string(path)?;
+        if let Some(header) = content.strip_prefix(Self::VERSION_HEADER)
+            && let Some((version, value)) = header.split_once('\n')
+            && let Ok(version) = version.trim().parse()
+        {
+            return Ok(Some(Record {
+                version,
+                value: value.to_owned(),
+            }));
+        }
+
+        // Written before the version was in the file
+        let version = self
+            .read_manifest(guild_id)?
+            .records
+            .get(key)
+            .copied()
+            .filter(|version| *version > 0)
+            .unwrap_or(1);
+        Ok(Some(Record {
+            version,
+            value: content,
+        }))
+    }
+
+    /// The version is written with the value, so a crash between the file and the manifest
+    /// leaves at worst a listed key without file, which reads as missing
+    fn write_unlocked(&self, guild_id: GuildId, key: &str, record: &Record) -> Result<()> {
+        let listed = self.read_manifest(guild_id)?.records.get(key).copied();
+        let list = || {
+            self.edit_manifest(guild_id, |manifest| {
+                manifest.records.insert(key.to_owned(), 0);
+            })
+        };
+        if listed.is_none() {
+            list()?;
+        }
+        write_atomic(
+            &self.path(guild_id, key),
+            &format!(
+                "{}{}\n{}",
+                Self::VERSION_HEADER,
+                record.version,
+                record.value
+            ),
+        )?;
+        // The version of the manifest is no longer needed once it is in the file
+        if listed.is_some_and(|version| version != 0) {
+            list()?;
+        }
+        Ok(())
+    }
+}
+
+impl Storage for FileStorage {
+    fn kind(&self) -> StorageKind {
+        StorageKind::Files
+    }
+
+    fn read(&self, guild_id: GuildId, key: &str) -> Result<Option<Record>> {
+        super::blocking(|| {
+            let lock = self.lock(&self.path(guild_id, key));
+            let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+            self.read_unlocked(guild_id, key)
+        })
+    }
+
+    fn write(&self, guild_id: GuildId, key: &str, record: &Record) -> Result<()> {
+        super::blocking(|| {
+            let lock = self.lock(&self.path(guild_id, key));
+            let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+            self.write_unlocked(guild_id, key, record)
+        })
+    }
+
+    fn remove(&self, guild_id: GuildId, key: &str) -> Result<()> {
+        super::blocking(|| {
+            let path = self.path(guild_id, key);
+            let lock = self.lock(&path);
+            let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+
+            if fs::exists(&path)? {
+                fs::remove_file(path)?;
+            }
+            self.edit_manifest(guild_id, |manifest| {
+                manifest.records.remove(key);
+            })
+        })
+    }
+
+    fn keys(&self, guild_id: GuildId) -> Result<Vec<String>> {
+        super::blocking(|| {
+            Ok(self
+                .read_manifest(guild_id)?
+                .records
+                .into_keys()
+                .filter(|key| fs::exists(self.path(guild_id, key)).unwrap_or(false))
+                .collect())
+        })
+    }
+
+    fn update(
+        &self,
+        guild_id: GuildId,
+        key: &str,
+        f: &mut dyn FnMut(Option<Record>) -> Result<Record>,
+    ) -> Result<()> {
+        super::blocking(|| {
+            let lock = self.lock(&self.path(guild_id, key));
+            let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+
+            let record = f(self.read_unlocked(guild_id, key)?)?;
+            self.write_unlocked(guild_id, key, &record)
+        })
+    }
+}
@@ -0,0 +1,130 @@
+use super::{Record, Storage, StorageKind};
+use color_eyre::Result;
+use rusqlite::{Connection, OptionalExtension, params};
+use serenity::all::GuildId;
+use std::{path::Path, sync::Mutex};
+
+/// Every record of every guild in a single embedded database
+#[derive(Debug)]
+pub struct SqliteStorage {
+    connection: Mutex<Connection>,
+}
+
+impl SqliteStorage {
+    pub const FILE_NAME: &'static str = "wattou.sqlite3";
+
+    /// Schema migrations, the n-th one brings the database from `user_version` n to n + 1
+    const MIGRATIONS: [&'static str; 1] = ["CREATE TABLE records (
+            guild_id INTEGER NOT NULL,
+            key TEXT NOT NULL,
+            version INTEGER NOT NULL,
+            value TEXT NOT NULL,
+            PRIMARY KEY (guild_id, key)
+        );"];
+
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let mut connection = Connection::open(path)?;
+        Self::migrate(&mut connection)?;
+        Ok(Self {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    fn migrate(connection: &mut Connection) -> Result<()> {
+        let version: usize =
+            connection.pragma_query_value(None, "user_version", |row| row.get(0))?;
+
+        for (i, migration) in Self::MIGRATIONS.iter().enumerate().skip(version) {
+            let transaction = connection.transaction()?;
+            transaction.execute_batch(migration)?;
+            transaction.pragma_update(None, "user_version", i + 1)?;
+            transaction.commit()?;
+        }
+        Ok(())
+    }
+
+    fn connection(&self) -> std::sync::MutexGuard<'_, Connection> {
+        self.connection.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn read_with(connection: &Connection, guild_id: GuildId, key: &str) -> Result<Option<Record>> {
+        Ok(connection
+            .query_row(
+                "SELECT version, value FROM records WHERE guild_id = ?1 AND key = ?2",
+                params![guild_id.get() as i64, key],
+                |row| {
+                    Ok(Record {
+                        version: row.get(0)?,
+                        value: row.get(1)?,
+                    })
+                },
+            )
+            .optional()?)
+    }
+
+    fn write_with(
+        connection: &Connection,
+        guild_id: GuildId,
+        key: &str,
+        record: &Record,
+    ) -> Result<()> {
+        connection.execute(
+            "INSERT INTO records (guild_id, key, version, value) VALUES (?1, ?2, ?3, ?4)
+            ON CONFLICT (guild_id, key) DO UPDATE SET version = excluded.version, value = excluded.value",
+            params![guild_id.get() as i64, key, record.version, record.value],
+        )?;
+        Ok(())
+    }
+}
+
+impl Storage for SqliteStorage {
+    fn kind(&self) -> StorageKind {
+        StorageKind::Sqlite
+    }
+
+    fn read(&self, guild_id: GuildId, key: &str) -> Result<Option<Record>> {
+        super::blocking(|| Self::read_with(&self.connection(), guild_id, key))
+    }
+
+    fn write(&self, guild_id: GuildId, key: &str, record: &Record) -> Result<()> {
+        super::blocking(|| Self::write_with(&self.connection(), guild_id, key, record))
+    }
+
+    fn remove(&self, guild_id: GuildId, key: &str) -> Result<()> {
+        super::blocking(|| {
+            self.connection().execute(
+                "DELETE FROM records WHERE guild_id = ?1 AND key = ?2",
+                params![guild_id.get() as i64, key],
+            )?;
+            Ok(())
+        })
+    }
+
+    fn keys(&self, guild_id: GuildId) -> Result<Vec<String>> {
+        super::blocking(|| {
+            let connection = self.connection();
+            let mut statement =
+                connection.prepare("SELECT key FROM records WHERE guild_id = ?1")?;
+            let keys = statement
+                .query_map(params![guild_id.get() as i64], |row| row.get(0))?
+                .collect::<Result<Vec<String>, _>>()?;
+            Ok(keys)
+        })
+    }
+
+    fn update(
+        &self,
+        guild_id: GuildId,
+        key: &str,
+        f: &mut dyn FnMut(Option<Record>) -> Result<Record>,
+    ) -> Result<()> {
+        super::blocking(|| {
+            let mut connection = self.connection();
+            let transaction = connection.transaction()?;
+            let record = f(Self::read_with(&transaction, guild_id, key)?)?;
+            Self::write_with(&transaction, guild_id, key, &record)?;
+            transaction.commit()?;
+            Ok(())
+        })
+    }
+}
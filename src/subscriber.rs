@@ -2,19 +2,23 @@ use std::{
     collections::{self, HashMap},
     fmt::Debug,
     time::Duration,
-    u8,
 };
 
 use color_eyre::Result;
 use serde::{Deserialize, Serialize};
-use serenity::all::{CreateMessage, GetMessages, GuildId, Http, Mention, PrivateChannel, UserId};
+use serenity::all::{CreateMessage, GetMessages, Http, Mention, PrivateChannel, UserId};
 use time::OffsetDateTime;
+use tracing::{debug, info};
 
 use crate::{
+    class::Class,
     colle::Colle,
-    debug,
-    group::GroupId,
-    guild_data::{GuildData, SavedData},
+    error::WattouError,
+    group::Group,
+    guild_data::{GuildData, SavedData, SavedDataWithDefault},
+    index::ColleQuery,
+    programme::Programmes,
+    rattrapage::Rattrapages,
 };
 
 #[derive(Serialize, Deserialize, Debug, Default)]
@@ -24,12 +28,35 @@ pub struct Subscribers {
 
 impl SavedData for Subscribers {
     const FILE_NAME: &'static str = "subscribers.json";
+    /// 2 : group ids are strings since groups can declare their id
+    const VERSION: u32 = 2;
+
     fn ser(&self) -> String {
         serde_json::to_string(&self).unwrap()
     }
     fn de(value: &str) -> color_eyre::Result<Self> {
         Ok(serde_json::from_str(value)?)
     }
+
+    fn migrate(version: u32, value: String) -> Result<String> {
+        match version {
+            1 => {
+                let mut json: serde_json::Value = serde_json::from_str(&value)?;
+                if let Some(map) = json.get_mut("map").and_then(|map| map.as_object_mut()) {
+                    for data in map.values_mut() {
+                        if let Some(group_id) = data.get("group_id").and_then(|id| id.as_u64()) {
+                            data["group_id"] = group_id.to_string().into();
+                        }
+                    }
+                }
+                Ok(json.to_string())
+            }
+            _ => Err(WattouError::UnsupportedDataVersion(
+                Self::FILE_NAME,
+                version,
+            ))?,
+        }
+    }
 }
 
 impl Subscribers {
@@ -37,20 +64,27 @@ impl Subscribers {
         self.map.get(user_id)
     }
 
-    pub fn remove(
-        &mut self,
-        guild_id: GuildId,
-        user_id: &UserId,
-    ) -> Result<Option<SubscriberData>> {
-        let data = self.map.remove(user_id);
-        self.save(guild_id)?;
-        Ok(data)
+    pub fn remove(&mut self, user_id: &UserId) -> Option<SubscriberData> {
+        self.map.remove(user_id)
     }
 
-    pub fn set(&mut self, guild_id: GuildId, user_id: UserId, data: SubscriberData) -> Result<()> {
+    pub fn set(&mut self, user_id: UserId, data: SubscriberData) {
         self.map.insert(user_id, data);
-        self.save(guild_id)?;
-        Ok(())
+    }
+
+    /// Replaces the group positions saved before groups had ids by the declared ids, returns
+    /// whether a subscription changed
+    pub fn resolve_group_ids(&mut self, guild_data: &GuildData) -> bool {
+        let mut changed = false;
+        for data in self.map.values_mut() {
+            if let Ok(class) = guild_data.class(data.class.as_deref())
+                && let Some(group_id) = class.legacy_group_id(&data.group_id)
+            {
+                data.group_id = group_id.to_string();
+                changed = true;
+            }
+        }
+        changed
     }
 
     pub fn iter<'a>(&'a self) -> collections::hash_map::Iter<'a, UserId, SubscriberData> {
@@ -58,23 +92,52 @@ impl Subscribers {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 pub struct SubscriberData {
-    pub group_id: GroupId,
+    /// Id of the group when subscribing, numbers saved before groups had ids are resolved by
+    /// [Subscribers::resolve_group_ids] when loaded
+    pub group_id: String,
+    /// [None] for the only class of the guild, also for subscriptions made before guilds could
+    /// have several classes
+    #[serde(default)]
+    pub class: Option<String>,
 }
 
 impl SubscriberData {
-    pub fn new_default(group_id: GroupId) -> Self {
-        Self { group_id }
+    /// Number of next colles of the group searched for the subject of the reminders
+    const UPCOMING_COLLES_CHECKED: usize = 4;
+
+    /// The class is only saved when the guild has several of them
+    pub fn new_default(guild_data: &GuildData, class: &Class, group: &Group) -> Self {
+        Self {
+            group_id: group.id.to_string(),
+            class: guild_data
+                .has_several_classes()
+                .then(|| class.name.to_string()),
+        }
+    }
+
+    pub fn is_for(&self, class: &Class, group: &Group) -> bool {
+        self.class
+            .as_deref()
+            .is_none_or(|name| name == class.name.as_ref())
+            && class
+                .group(&self.group_id)
+                .is_some_and(|current| current.id == group.id)
     }
 }
 
 pub trait SubscribePlan: Debug {
     type Predicate: ToString;
 
-    fn get_predicate(&self, guild_data: &GuildData) -> Option<Self::Predicate>;
-    fn create_message(&self, user_id: UserId, predicate: &Self::Predicate) -> Result<String>;
-    fn should_make_message(&self, predicate: &Self::Predicate) -> bool;
+    fn get_predicate(&self, user_id: UserId, guild_data: &GuildData) -> Option<Self::Predicate>;
+    fn create_message(
+        &self,
+        user_id: UserId,
+        guild_data: &GuildData,
+        predicate: &Self::Predicate,
+    ) -> Result<String>;
+    fn should_make_message(&self, guild_data: &GuildData, predicate: &Self::Predicate) -> bool;
 
     async fn check_already_sent(
         channel: &PrivateChannel,
@@ -92,28 +155,24 @@ pub trait SubscribePlan: Debug {
     }
 
     async fn try_send(&self, user_id: UserId, http: &Http, guild_data: &GuildData) -> Result<()> {
-        if let Some(predicate) = self.get_predicate(guild_data) {
-            if self.should_make_message(&predicate) {
-                let user = http.get_user(user_id).await?;
-                let channel = user.create_dm_channel(http).await?;
-                let content = self.create_message(user_id, &predicate)?;
-
-                if !Self::check_already_sent(&channel, http, &content).await? {
-                    channel
-                        .send_message(http, CreateMessage::new().content(content))
-                        .await?;
-                    debug!(
-                        "sent subscriber message for {} with {}",
-                        user_id,
-                        predicate.to_string()
-                    )
-                } else {
-                    debug!(
-                        "already sent subscriber message for {} with {} skipped sending",
-                        user_id,
-                        predicate.to_string()
-                    )
-                }
+        if let Some(predicate) = self.get_predicate(user_id, guild_data)
+            && self.should_make_message(guild_data, &predicate)
+        {
+            let user = http.get_user(user_id).await?;
+            let channel = user.create_dm_channel(http).await?;
+            let content = self.create_message(user_id, guild_data, &predicate)?;
+
+            if !Self::check_already_sent(&channel, http, &content).await? {
+                channel
+                    .send_message(http, CreateMessage::new().content(content))
+                    .await?;
+                info!(user_id = %user_id, predicate = %predicate.to_string(), "sent subscriber message")
+            } else {
+                debug!(
+                    user_id = %user_id,
+                    predicate = %predicate.to_string(),
+                    "subscriber message already sent, skipped sending"
+                )
             }
         }
 
@@ -121,33 +180,61 @@ pub trait SubscribePlan: Debug {
     }
 }
 
-impl SubscriberData {
-    const MIN_HOUR_DIFF: u64 = 30;
-}
-
 impl SubscribePlan for SubscriberData {
     type Predicate = Colle;
 
-    fn get_predicate(&self, guild_data: &GuildData) -> Option<Self::Predicate> {
-        guild_data
-            .get_group(self.group_id)
-            .ok()?
-            .get_next_colles(4)
+    /// Colle in the subject of the reminders among the next colles of the group, or next
+    /// rattrapage of the user
+    fn get_predicate(&self, user_id: UserId, guild_data: &GuildData) -> Option<Self::Predicate> {
+        let subject = guild_data.config().ok()?.reminder_subject();
+        let class = guild_data.class(self.class.as_deref()).ok()?;
+        let group = class.group(&self.group_id)?;
+        let rattrapages = Rattrapages::read_or_default(guild_data.guild_id).ok()?;
+        class
+            .query(
+                &ColleQuery::upcoming()
+                    .group(&group.id)
+                    .limit(Self::UPCOMING_COLLES_CHECKED),
+            )
             .into_iter()
-            .find(|colle| colle.id.0 == 'A')
-            .cloned()
+            .find(|indexed| indexed.colle.id.0 == subject)
+            .map(|indexed| indexed.colle.clone())
+            .into_iter()
+            .chain(
+                rattrapages
+                    .colles_of(class, user_id, &guild_data.profs)
+                    .into_iter()
+                    .filter(|colle| colle.id.0 == subject),
+            )
+            .min()
     }
 
-    fn should_make_message(&self, colle: &Self::Predicate) -> bool {
-        return colle.start - OffsetDateTime::now_local().unwrap()
-            < Duration::from_secs(60 * 60 * Self::MIN_HOUR_DIFF);
+    fn should_make_message(&self, guild_data: &GuildData, colle: &Self::Predicate) -> bool {
+        let Ok(guild_config) = guild_data.config() else {
+            return false;
+        };
+        colle.start - OffsetDateTime::now_utc()
+            < Duration::from_secs(60 * 60 * guild_config.reminder_hours_before())
     }
 
-    fn create_message(&self, user_id: UserId, predicate: &Self::Predicate) -> Result<String> {
-        Ok(format!(
+    /// The reminder, with the programme of the colle when it was published
+    fn create_message(
+        &self,
+        user_id: UserId,
+        guild_data: &GuildData,
+        predicate: &Self::Predicate,
+    ) -> Result<String> {
+        let mut message = format!(
             "{}, n'oublie pas ton carnet de colle pour ta colle {}",
             Mention::from(user_id),
             predicate.format(crate::colle::ColleStringFormat::Explicit, vec![])
-        ))
+        );
+        let class = guild_data.class(self.class.as_deref())?;
+        if let Some(programme) =
+            Programmes::read_or_default(guild_data.guild_id)?.for_colle(class, predicate)
+        {
+            message += &format!("\n{}", programme.excerpt());
+        }
+        Ok(message)
     }
 }
@@ -1,39 +1,14 @@
+use crate::config::config;
 use time::{Month, OffsetDateTime, Weekday};
 
+use color_eyre::Result;
 use std::{
-    fs::OpenOptions,
-    io::{self, Write},
+    fmt::{self, Display, Formatter},
+    fs,
+    io::Write,
+    path::Path,
 };
 
-pub fn write_to_log(s: &str) -> io::Result<()> {
-    let st = format!("{}: {}", OffsetDateTime::now_local().unwrap(), s);
-    let mut f = OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open("latest.log")?;
-    f.write_all(&st.as_bytes())?;
-    f.write_all(b"\n")?;
-    f.flush()
-}
-
-#[macro_export]
-macro_rules! debug {
-    ($($arg:tt)*) => {
-        {
-            let s = format!($($arg)*);
-            #[cfg(not(debug_assertions))]
-            {
-                // in release mode
-                let _ = crate::utils::write_to_log(&s);
-            }
-            #[cfg(debug_assertions)]
-            {
-                println!("{}", s);
-            }
-        }
-    }
-}
-
 pub fn month_to_short_fr(month: Month) -> String {
     match month {
         Month::January => "Jan",
@@ -81,9 +56,12 @@ impl PartialOrd for Jour {
     }
 }
 
-impl From<&str> for Jour {
-    fn from(value: &str) -> Self {
-        Self(match value {
+impl TryFrom<&str> for Jour {
+    /// The unknown day
+    type Error = String;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        Ok(Self(match value {
             "Lu" => Weekday::Monday,
             "Ma" => Weekday::Tuesday,
             "Me" => Weekday::Wednesday,
@@ -91,14 +69,14 @@ impl From<&str> for Jour {
             "Ve" => Weekday::Friday,
             "Sa" => Weekday::Saturday,
             "Di" => Weekday::Sunday,
-            _ => panic!(),
-        })
+            _ => return Err(value.to_string()),
+        }))
     }
 }
 
-impl ToString for Jour {
-    fn to_string(&self) -> String {
-        match self.0 {
+impl Display for Jour {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self.0 {
             Weekday::Monday => "Lundi",
             Weekday::Tuesday => "Mardi",
             Weekday::Wednesday => "Mercredi",
@@ -106,7 +84,64 @@ impl ToString for Jour {
             Weekday::Friday => "Vendredi",
             Weekday::Saturday => "Samedi",
             Weekday::Sunday => "Dimanche",
-        }
-        .to_string()
+        })
+    }
+}
+
+/// Lowercase alphanumeric characters only, to compare what users type with names
+pub fn easy_comp_string(s: &str) -> String {
+    s.chars()
+        .filter(|c| c.is_alphanumeric())
+        .collect::<String>()
+        .to_lowercase()
+}
+
+/// `de` before a lowercase word, elided before a vowel, e.g. `de maths` or `d'anglais`
+pub fn de(word: &str) -> String {
+    let word = word.to_lowercase();
+    match word.chars().next() {
+        Some('a' | 'e' | 'i' | 'o' | 'u' | 'y' | 'é' | 'è' | 'ê' | 'h') => format!("d'{word}"),
+        _ => format!("de {word}"),
     }
 }
+
+/// The text on one line, cut after `len` characters
+pub fn excerpt(text: &str, len: usize) -> String {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    match text.char_indices().nth(len) {
+        Some((end, _)) => format!("{}…", &text[..end]),
+        None => text,
+    }
+}
+
+/// Writes a file sent on Discord in `folder`, returns the name it was saved with
+pub fn save_file(folder: &Path, name: &str, content: &[u8]) -> Result<String> {
+    fs::create_dir_all(folder)?;
+    // The name comes from Discord, keep it from leaving the folder
+    let name = name.replace(['/', '\\'], "_");
+    fs::write(folder.join(&name), content)?;
+    Ok(name)
+}
+
+/// Writes to a temporary file then renames it so that a crash never leaves a half written file
+pub fn write_atomic(path: &Path, content: &str) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut tmp_name = path.file_name().unwrap_or_default().to_owned();
+    tmp_name.push(".tmp");
+    let tmp = path.with_file_name(tmp_name);
+
+    let mut file = fs::File::create(&tmp)?;
+    file.write_all(content.as_bytes())?;
+    file.sync_all()?;
+    fs::rename(tmp, path)?;
+    Ok(())
+}
+
+/// Current time in `calendar.timezone`, whatever the time zone of the server
+pub fn now_in_timezone() -> OffsetDateTime {
+    let now = OffsetDateTime::now_utc();
+    now.to_offset(config().calendar.offset_at(now))
+}
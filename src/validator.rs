@@ -0,0 +1,93 @@
+//! Offline checks of the data files, without connecting to Discord:
+//! `uhu-wattou verifier <dossier du serveur ou de la classe>`
+use crate::{class::Class, config::config, fairness::FairnessReport, guild_data::GuildData};
+use color_eyre::Result;
+use serenity::all::GuildId;
+use std::{
+    collections::HashMap,
+    env,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+pub const COMMAND: &str = "verifier";
+
+/// Folder given after [COMMAND], if the bot was started to check files with [COMMAND] as the
+/// first argument
+pub fn requested() -> Option<PathBuf> {
+    let mut args = env::args().skip(1);
+    if args.next()? != COMMAND {
+        return None;
+    }
+    Some(
+        args.next()
+            .filter(|arg| !arg.starts_with("--"))
+            .unwrap_or_else(|| ".".to_string())
+            .into(),
+    )
+}
+
+/// Prints every issue found, returns whether the files are valid
+pub fn run(folder: &Path) -> Result<bool> {
+    let holidays = config().calendar.holidays()?;
+    // Errors only use the guild id to look up the loaded data, any id works here
+    let guild_id = folder
+        .file_name()
+        .and_then(|name| name.to_str()?.parse().ok())
+        .unwrap_or(GuildId::new(1));
+
+    let classes = if Class::is_class_folder(folder) {
+        vec![(Arc::from(Class::DEFAULT_NAME), folder.to_path_buf())]
+    } else {
+        GuildData::class_folders(folder)?
+    };
+    if classes.is_empty() {
+        println!("❌ Aucun colloscope dans {}", folder.display());
+        return Ok(false);
+    }
+
+    let mut valid = true;
+    let mut profs = HashMap::new();
+    for (name, folder) in classes {
+        println!("# {name}");
+        let class = match Class::new(guild_id, name, folder, &mut profs) {
+            Ok(class) => class,
+            Err(e) => {
+                println!("❌ {e}");
+                valid = false;
+                continue;
+            }
+        };
+
+        for period in class.periods.iter() {
+            let issues = period.weeks.validate(&holidays);
+            println!(
+                "## {} : {} semaines, {} groupes, {} colles",
+                period.name,
+                period.weeks.len(),
+                period.groups.len(),
+                period.index.len()
+            );
+            for issue in issues.iter() {
+                println!("⚠️ {issue}");
+            }
+            for id in period.unknown_ghosts.iter() {
+                println!("⚠️ Fichier ghosts : groupe « {id} » inconnu, ignoré");
+            }
+            valid &= issues.is_empty() && period.unknown_ghosts.is_empty();
+
+            // Unfair colloscopes are still valid, the admins decide
+            println!("### Équité\n{}", FairnessReport::new(period).to_markdown());
+        }
+    }
+
+    println!(
+        "{}",
+        if valid {
+            "✅ Fichiers valides"
+        } else {
+            "❌ Des problèmes ont été trouvés"
+        }
+    );
+    Ok(valid)
+}
@@ -0,0 +1,245 @@
+//! The `weeks` file and the week specifications of the colloscope header.
+//!
+//! Each line of `weeks` is a week of colles, optionally named, followed by the date of its monday,
+//! e.g. `S1 2-9-2024` or `rentrée 2-9-2024`. Week numbers start at 1 in the order of the file.
+//!
+//! Each column of the colloscope header lists the weeks of the column, separated by `-`: a week
+//! number `3`, a week name `rentrée`, a range `1..4` or a range with a step `1..12/2`.
+use crate::{
+    error::WattouError,
+    guild_data::WeekId,
+    utils::{Jour, easy_comp_string},
+};
+use color_eyre::Result;
+use time::{
+    Date, Duration, Weekday, format_description::BorrowedFormatItem, macros::format_description,
+};
+
+/// Format of the dates of the `weeks` and `periode` files, e.g. : 2-9-2024
+pub const DATE_FORMAT: &[BorrowedFormatItem<'_>] =
+    format_description!("[day padding:none]-[month padding:none]-[year]");
+
+/// The line without its `#` comment, [None] if nothing is left
+pub fn strip_comment(line: &str) -> Option<&str> {
+    let line = line.split('#').next().unwrap_or_default().trim();
+    (!line.is_empty()).then_some(line)
+}
+
+/// Days without colles, both ends included
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Holiday {
+    pub name: String,
+    pub start: Date,
+    pub end: Date,
+}
+
+impl Holiday {
+    pub fn contains(&self, date: Date) -> bool {
+        (self.start..=self.end).contains(&date)
+    }
+
+    /// Whether the holiday covers the week from monday to friday
+    pub fn covers_week(&self, monday: Date) -> bool {
+        self.contains(monday) && self.contains(monday + Duration::days(4))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Week {
+    pub id: WeekId,
+    pub label: Option<String>,
+    pub monday: Date,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Weeks(pub Vec<Week>);
+
+impl Weeks {
+    pub fn parse(s: &str) -> Result<Self> {
+        let mut weeks = Vec::new();
+        for (i, line) in s.lines().enumerate() {
+            let Some(line) = strip_comment(line) else {
+                continue;
+            };
+            let (label, date) = match line.rsplit_once(char::is_whitespace) {
+                Some((label, date)) => (Some(label.trim().to_string()), date),
+                None => (None, line),
+            };
+            let monday = Date::parse(date, DATE_FORMAT).map_err(|e| WattouError::InvalidWeeks {
+                line: i + 1,
+                reason: format!("date « {date} » invalide ({e})"),
+            })?;
+            weeks.push(Week {
+                id: weeks.len() + 1,
+                label,
+                monday,
+            });
+        }
+        Ok(Self(weeks))
+    }
+
+    /// One week per monday from the week of `first_day` to the one of `last_day`, except the
+    /// weeks covered by holidays, labelled `S1`, `S2`...
+    pub fn generate(first_day: Date, last_day: Date, holidays: &[Holiday]) -> Self {
+        let mut weeks = Vec::new();
+        let mut monday =
+            first_day - Duration::days(first_day.weekday().number_days_from_monday() as i64);
+        while monday <= last_day {
+            if !holidays.iter().any(|holiday| holiday.covers_week(monday)) {
+                let id = weeks.len() + 1;
+                weeks.push(Week {
+                    id,
+                    label: Some(format!("S{id}")),
+                    monday,
+                });
+            }
+            monday += Duration::weeks(1);
+        }
+        Self(weeks)
+    }
+
+    /// Content of a `weeks` file with these weeks
+    pub fn to_file(&self) -> String {
+        self.0
+            .iter()
+            .map(|week| {
+                let date = week.monday.format(DATE_FORMAT).unwrap();
+                match &week.label {
+                    Some(label) => format!("{label} {date}\n"),
+                    None => format!("{date}\n"),
+                }
+            })
+            .collect()
+    }
+
+    /// Mistakes of a hand-written file: dates that are not mondays or not increasing, weeks
+    /// falling in holidays, weeks missing outside of holidays and labels used twice
+    pub fn validate(&self, holidays: &[Holiday]) -> Vec<String> {
+        let mut issues = Vec::new();
+        for (i, week) in self.0.iter().enumerate() {
+            let name = match &week.label {
+                Some(label) => format!("semaine {} ({label})", week.id),
+                None => format!("semaine {}", week.id),
+            };
+            if week.monday.weekday() != Weekday::Monday {
+                issues.push(format!(
+                    "{name} : le {} est un {}, pas un lundi",
+                    week.monday.format(DATE_FORMAT).unwrap(),
+                    Jour::from(week.monday.weekday()).to_string().to_lowercase()
+                ));
+            }
+            if let Some(holiday) = holidays
+                .iter()
+                .find(|holiday| holiday.covers_week(week.monday))
+            {
+                issues.push(format!("{name} : pendant les vacances {}", holiday.name));
+            }
+            if let Some(label) = &week.label
+                && self.0[..i]
+                    .iter()
+                    .any(|other| other.label.as_ref() == Some(label))
+            {
+                issues.push(format!("{name} : le nom {label} est déjà utilisé"));
+            }
+
+            let Some(previous) = i.checked_sub(1).map(|i| &self.0[i]) else {
+                continue;
+            };
+            if week.monday <= previous.monday {
+                issues.push(format!(
+                    "{name} : le {} n'est pas après la semaine précédente",
+                    week.monday.format(DATE_FORMAT).unwrap()
+                ));
+                continue;
+            }
+            let missing = (1..)
+                .map(|n| previous.monday + Duration::weeks(n))
+                .take_while(|monday| *monday + Duration::days(6) < week.monday)
+                .filter(|monday| !holidays.iter().any(|holiday| holiday.covers_week(*monday)))
+                .count();
+            if missing > 0 {
+                issues.push(format!(
+                    "{name} : {missing} semaine(s) sans colle hors vacances depuis la semaine {}",
+                    previous.id
+                ));
+            }
+        }
+        issues
+    }
+
+    pub fn mondays(&self) -> Vec<Date> {
+        self.0.iter().map(|week| week.monday).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Week number from a number or a label of the file
+    pub fn find(&self, name: &str) -> Option<WeekId> {
+        if let Ok(id) = name.parse::<WeekId>() {
+            return (1..=self.len()).contains(&id).then_some(id);
+        }
+        let name = easy_comp_string(name);
+        self.0
+            .iter()
+            .find(|week| {
+                week.label
+                    .as_deref()
+                    .is_some_and(|label| easy_comp_string(label) == name)
+            })
+            .map(|week| week.id)
+    }
+
+    /// Weeks of one column of the colloscope header, e.g. `1..4-rentrée-10..20/2`
+    pub fn parse_spec(&self, spec: &str) -> Result<Vec<WeekId>, String> {
+        let mut weeks = Vec::new();
+        for item in spec.split('-') {
+            let find = |name: &str| {
+                self.find(name.trim())
+                    .ok_or_else(|| format!("semaine « {name} » inconnue"))
+            };
+            match item.split_once("..") {
+                Some((first, rest)) => {
+                    let (last, step) = match rest.split_once('/') {
+                        Some((last, step)) => (
+                            last,
+                            step.parse::<usize>()
+                                .ok()
+                                .filter(|step| *step > 0)
+                                .ok_or_else(|| format!("pas « {step} » invalide"))?,
+                        ),
+                        None => (rest, 1),
+                    };
+                    let (first, last) = (find(first)?, find(last)?);
+                    if first > last {
+                        Err(format!("intervalle {item} vide"))?
+                    }
+                    weeks.extend((first..=last).step_by(step));
+                }
+                None => weeks.push(find(item)?),
+            }
+        }
+        Ok(weeks)
+    }
+
+    /// Weeks of every column of the colloscope header
+    pub fn parse_header(&self, line_number: usize, header: &str) -> Result<Vec<Vec<WeekId>>> {
+        Ok(header
+            .split_whitespace()
+            .enumerate()
+            .map(|(column, spec)| {
+                self.parse_spec(spec)
+                    .map_err(|reason| WattouError::InvalidColloscope {
+                        line: line_number,
+                        column: column + 1,
+                        reason: format!("« {spec} » : {reason}"),
+                    })
+            })
+            .collect::<Result<_, _>>()?)
+    }
+}